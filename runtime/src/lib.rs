@@ -0,0 +1,258 @@
+// Functions compiled programs call, mapped into the jit by the compiler and linked into objects from build
+// Pointers passed in always come from compiled code and this runtime, so they aren't documented one by one
+#![allow(clippy::missing_safety_doc)]
+
+use std::alloc::{alloc, dealloc, Layout};
+use std::collections::BTreeMap;
+use std::ffi::{c_char, CStr};
+use std::sync::Mutex;
+
+// Every heap allocation is prefixed with [size, reference count, destructor]
+const HEADER_SIZE: usize = 24;
+
+static LIVE_ALLOCATIONS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+unsafe fn header(ptr: *mut u8) -> *mut i64 {
+    ptr.sub(HEADER_SIZE) as *mut i64
+}
+
+fn layout_for(size: usize) -> Layout {
+    Layout::from_size_align(size + HEADER_SIZE, 8).unwrap()
+}
+
+#[no_mangle]
+pub extern "C" fn ss_alloc(size: i64) -> *mut u8 {
+    let size = size.max(0) as usize;
+    unsafe {
+        let raw = alloc(layout_for(size));
+        if raw.is_null() {
+            panic!("Out of memory");
+        }
+        let ptr = raw.add(HEADER_SIZE);
+        *header(ptr) = size as i64;
+        *header(ptr).add(1) = 0;
+        *header(ptr).add(2) = 0;
+        LIVE_ALLOCATIONS.lock().unwrap().insert(ptr as usize, size);
+        ptr
+    }
+}
+
+// Box of a heap value, its first word holds a reference that is released along with it
+#[no_mangle]
+pub extern "C" fn ss_alloc_owner(size: i64) -> *mut u8 {
+    let ptr = ss_alloc(size);
+    unsafe {
+        *header(ptr).add(2) = release_contents as extern "C" fn(*mut u8) as usize as i64;
+    }
+    ptr
+}
+
+extern "C" fn release_contents(ptr: *mut u8) {
+    unsafe { ss_release(*(ptr as *mut *mut u8)) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ss_retain(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        *header(ptr).add(1) += 1;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ss_release(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        let count = header(ptr).add(1);
+        *count -= 1;
+        if *count <= 0 {
+            let destructor = *header(ptr).add(2);
+            if destructor != 0 {
+                std::mem::transmute::<usize, extern "C" fn(*mut u8)>(destructor as usize)(ptr);
+            }
+            let size = *header(ptr) as usize;
+            LIVE_ALLOCATIONS.lock().unwrap().remove(&(ptr as usize));
+            dealloc(ptr.sub(HEADER_SIZE), layout_for(size));
+        }
+    }
+}
+
+// Gives up a reference without freeing, used to hand a value back to a caller
+#[no_mangle]
+pub unsafe extern "C" fn ss_release_unowned(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        *header(ptr).add(1) -= 1;
+    }
+}
+
+// Backing storage for vec[T], elements are stored as raw bytes in words so they stay 8-aligned
+pub struct RuntimeVector {
+    words: Vec<u64>,
+    len: usize,
+    element_size: usize,
+}
+
+// The handle lives in a counted allocation, releasing the last reference drops the storage
+#[no_mangle]
+pub extern "C" fn ss_vec_new() -> *mut RuntimeVector {
+    let vector = ss_alloc(std::mem::size_of::<RuntimeVector>() as i64) as *mut RuntimeVector;
+    unsafe {
+        vector.write(RuntimeVector { words: Vec::new(), len: 0, element_size: 0 });
+        *header(vector as *mut u8).add(2) = drop_vector as extern "C" fn(*mut u8) as usize as i64;
+    }
+    vector
+}
+
+extern "C" fn drop_vector(ptr: *mut u8) {
+    unsafe { std::ptr::drop_in_place(ptr as *mut RuntimeVector) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ss_vec_push(vector: *mut RuntimeVector, element: *const u8, element_size: i64) {
+    let vector = unsafe { &mut *vector };
+    let element_size = element_size as usize;
+    vector.element_size = element_size;
+    vector.words.resize((vector.len + element_size).div_ceil(8), 0);
+    unsafe {
+        std::ptr::copy_nonoverlapping(element, (vector.words.as_mut_ptr() as *mut u8).add(vector.len), element_size);
+    }
+    vector.len += element_size;
+}
+
+// file and line are those of the pop, reported like the checks compiled into programs
+#[no_mangle]
+pub unsafe extern "C" fn ss_vec_pop(vector: *mut RuntimeVector, out: *mut u8, element_size: i64, file: *const c_char, line: i64) {
+    let vector = unsafe { &mut *vector };
+    let element_size = element_size as usize;
+    if vector.len < element_size {
+        unsafe { ss_panic(c"Popped from an empty vector".as_ptr(), file, line) };
+    }
+    vector.len -= element_size;
+    unsafe {
+        std::ptr::copy_nonoverlapping((vector.words.as_ptr() as *const u8).add(vector.len), out, element_size);
+    }
+    vector.words.truncate(vector.len.div_ceil(8));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ss_vec_len(vector: *mut RuntimeVector) -> i64 {
+    let vector = unsafe { &*vector };
+    if vector.element_size == 0 {
+        return 0;
+    }
+    (vector.len / vector.element_size) as i64
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ss_vec_data(vector: *mut RuntimeVector) -> *mut u8 {
+    let vector = unsafe { &mut *vector };
+    vector.words.as_mut_ptr() as *mut u8
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ss_panic(message: *const c_char, file: *const c_char, line: i64) -> ! {
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    let file = unsafe { CStr::from_ptr(file) }.to_string_lossy();
+    eprintln!("{}:{}: {}", file, line, message);
+    std::process::abort();
+}
+
+pub fn symbols() -> Vec<(&'static str, usize)> {
+    vec![
+        ("ss_alloc", ss_alloc as *const () as usize),
+        ("ss_alloc_owner", ss_alloc_owner as *const () as usize),
+        ("ss_retain", ss_retain as *const () as usize),
+        ("ss_release", ss_release as *const () as usize),
+        ("ss_release_unowned", ss_release_unowned as *const () as usize),
+        ("ss_vec_new", ss_vec_new as *const () as usize),
+        ("ss_vec_push", ss_vec_push as *const () as usize),
+        ("ss_vec_pop", ss_vec_pop as *const () as usize),
+        ("ss_vec_len", ss_vec_len as *const () as usize),
+        ("ss_vec_data", ss_vec_data as *const () as usize),
+        ("ss_panic", ss_panic as *const () as usize),
+    ]
+}
+
+pub fn outstanding_allocations() -> Vec<(usize, usize)> {
+    LIVE_ALLOCATIONS.lock().unwrap().iter().map(|(ptr, size)| (*ptr, *size)).collect()
+}
+
+pub fn report_leaks() {
+    let outstanding = outstanding_allocations();
+    if outstanding.is_empty() {
+        println!("Leak check: no outstanding allocations");
+        return;
+    }
+    println!("Leak check: {} outstanding allocation(s)", outstanding.len());
+    for (ptr, size) in outstanding {
+        println!("    {:#x}: {} bytes", ptr, size);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn release_frees_allocation() {
+        let ptr = ss_alloc(8);
+        unsafe {
+            ss_retain(ptr);
+            ss_retain(ptr);
+            ss_release(ptr);
+            assert!(outstanding_allocations().iter().any(|(p, _)| *p == ptr as usize));
+            ss_release(ptr);
+        }
+        assert!(!outstanding_allocations().iter().any(|(p, _)| *p == ptr as usize));
+    }
+
+    #[test]
+    fn release_frees_nested_allocations() {
+        let inner = ss_alloc(8);
+        let outer = ss_alloc_owner(8);
+        unsafe {
+            ss_retain(inner);
+            *(outer as *mut *mut u8) = inner;
+            ss_retain(outer);
+            ss_release(outer);
+        }
+        let outstanding = outstanding_allocations();
+        assert!(!outstanding.iter().any(|(p, _)| *p == inner as usize || *p == outer as usize));
+    }
+
+    #[test]
+    fn vector_push_and_pop() {
+        let vector = ss_vec_new();
+        unsafe {
+            for value in [3i64, 5, 8] {
+                ss_vec_push(vector, &value as *const i64 as *const u8, 8);
+            }
+            assert_eq!(ss_vec_len(vector), 3);
+            let mut out = 0i64;
+            ss_vec_pop(vector, &mut out as *mut i64 as *mut u8, 8, c"main".as_ptr(), 1);
+            assert_eq!(out, 8);
+            assert_eq!(ss_vec_len(vector), 2);
+            assert_eq!(*(ss_vec_data(vector) as *const i64).add(1), 5);
+            assert_eq!(ss_vec_data(vector) as usize % 8, 0);
+        }
+    }
+
+    #[test]
+    fn release_drops_vector() {
+        let vector = ss_vec_new();
+        unsafe {
+            ss_retain(vector as *mut u8);
+            ss_vec_push(vector, &1i64 as *const i64 as *const u8, 8);
+            assert!(outstanding_allocations().iter().any(|(p, _)| *p == vector as usize));
+            ss_release(vector as *mut u8);
+        }
+        assert!(!outstanding_allocations().iter().any(|(p, _)| *p == vector as usize));
+    }
+}
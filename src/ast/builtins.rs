@@ -0,0 +1,13 @@
+// Functions provided by the compiler instead of being defined with def
+pub const BUILTIN_FUNCTIONS: &[&str] = &[
+    "box", "push", "pop", "len",
+    // Copies a string literal into a vec[char], which is counted like any heap value
+    "string",
+    "wrapping_add", "wrapping_sub", "wrapping_mul", "wrapping_div",
+    // Store through their third argument and return whether the result fit
+    "checked_add", "checked_sub", "checked_mul", "checked_div",
+];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTIN_FUNCTIONS.contains(&name)
+}
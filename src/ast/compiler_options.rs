@@ -0,0 +1,62 @@
+#[derive(Clone, Debug)]
+pub struct CompilerOptions {
+    // Name used when runtime checks report a location
+    pub source_name: String,
+    // Insert retain/release calls for heap values
+    pub reference_counting: bool,
+    // Report outstanding heap allocations when main exits
+    pub leak_check: bool,
+    // Check array, string and vector indices before every access
+    pub bounds_checks: bool,
+    // Trap on integer overflow and division by zero
+    pub overflow_checks: bool,
+    // Passes run on the llvm module before it's executed or printed
+    pub optimization: OptimizationLevel,
+    // Describe functions, variables and lines in DWARF so debuggers can follow the source
+    pub debug_info: bool,
+    // LLVM triple, cpu and feature list build generates code for, the host's when unset
+    pub target: Option<String>,
+    pub cpu: Option<String>,
+    pub target_features: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptimizationLevel {
+    None,
+    Less,
+    Default,
+    Aggressive,
+    // Default without the passes that trade size for speed
+    Size,
+}
+
+impl OptimizationLevel {
+    // -O0 through -O3 and -Os
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "-O0" => Some(Self::None),
+            "-O1" => Some(Self::Less),
+            "-O2" => Some(Self::Default),
+            "-O3" => Some(Self::Aggressive),
+            "-Os" => Some(Self::Size),
+            _ => None,
+        }
+    }
+}
+
+impl Default for CompilerOptions {
+    fn default() -> Self {
+        Self {
+            source_name: "main".to_string(),
+            reference_counting: false,
+            leak_check: false,
+            bounds_checks: true,
+            overflow_checks: false,
+            optimization: OptimizationLevel::None,
+            debug_info: false,
+            target: None,
+            cpu: None,
+            target_features: None,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use super::{Expression, BinaryExpressionType, UnaryExpressionType};
+
+impl Expression {
+    // Replaces constant subtrees with literals, comparisons are kept since they lower to bool
+    pub fn fold_constants(&mut self) {
+        let is_literal = matches!(self, Expression::IntegerLiteral(_) | Expression::FloatLiteral(_) |
+            Expression::CharLiteral(_) | Expression::StringLiteral(_));
+        let is_comparison = matches!(self, Expression::Binary(_, _, ref operation) if operation.is_comparison());
+        if !is_literal && !is_comparison {
+            if let Some(value) = self.evaluate_constant(&HashMap::new()) {
+                *self = value;
+                return;
+            }
+        }
+        for child in self.children_mut() {
+            child.fold_constants();
+        }
+    }
+
+    // Folds the expression into a literal, None when the value is only known at runtime
+    pub fn evaluate_constant(&self, constants: &HashMap<String, Expression>) -> Option<Expression> {
+        match self {
+            Expression::IntegerLiteral(_) | Expression::FloatLiteral(_) |
+            Expression::CharLiteral(_) | Expression::StringLiteral(_) => Some(self.clone()),
+            // Function addresses are filled in at link time, so they can initialize globals
+            Expression::FunctionReference(..) => Some(self.clone()),
+            Expression::VariableRead(name) => constants.get(name).cloned(),
+            Expression::Array(values) => {
+                let values = values.iter().map(|value| value.evaluate_constant(constants)).collect::<Option<Vec<_>>>()?;
+                Some(Expression::Array(values))
+            },
+            Expression::Unary(Some(interior), operation) => {
+                match (operation, interior.evaluate_constant(constants)?) {
+                    (UnaryExpressionType::Negation, Expression::IntegerLiteral(value)) => Some(Expression::IntegerLiteral(value.checked_neg()?)),
+                    (UnaryExpressionType::Negation, Expression::FloatLiteral(value)) => Some(Expression::FloatLiteral(-value)),
+                    (UnaryExpressionType::BitwiseNot, Expression::IntegerLiteral(value)) => Some(Expression::IntegerLiteral(!value)),
+                    _ => None,
+                }
+            },
+            Expression::Binary(Some(left), Some(right), operation) => {
+                Self::fold_binary(operation, left.evaluate_constant(constants)?, right.evaluate_constant(constants)?)
+            },
+            Expression::ExpressionCast(interior, data_type) => {
+                let value = match (interior.evaluate_constant(constants)?, data_type.as_str()) {
+                    (Expression::IntegerLiteral(value), "i64") => Expression::IntegerLiteral(value),
+                    (Expression::IntegerLiteral(value), "f64") => Expression::FloatLiteral(value as f64),
+                    (Expression::IntegerLiteral(value), "char") => Expression::CharLiteral(u8::try_from(value).ok()?),
+                    (Expression::FloatLiteral(value), "f64") => Expression::FloatLiteral(value),
+                    (Expression::FloatLiteral(value), "i64") => Expression::IntegerLiteral(value as i64),
+                    (Expression::CharLiteral(value), "char") => Expression::CharLiteral(value),
+                    (Expression::CharLiteral(value), "i64") => Expression::IntegerLiteral(value as i64),
+                    _ => return None,
+                };
+                Some(value)
+            },
+            _ => None,
+        }
+    }
+
+    // Overflow and division by zero aren't folded so they can't silently change meaning
+    // Comparisons evaluate to 1 or 0
+    fn fold_binary(operation: &BinaryExpressionType, left: Expression, right: Expression) -> Option<Expression> {
+        match (left, right) {
+            (Expression::IntegerLiteral(left), Expression::IntegerLiteral(right)) => {
+                if operation.is_comparison() {
+                    return Some(Expression::IntegerLiteral(Self::compare(operation, left.cmp(&right)) as i64));
+                }
+                let value = match operation {
+                    BinaryExpressionType::Addition => left.checked_add(right)?,
+                    BinaryExpressionType::Subtraction => left.checked_sub(right)?,
+                    BinaryExpressionType::Multiplication => left.checked_mul(right)?,
+                    BinaryExpressionType::Division => left.checked_div(right)?,
+                    BinaryExpressionType::Modulo => left.checked_rem(right)?,
+                    BinaryExpressionType::BitwiseAnd => left & right,
+                    BinaryExpressionType::BitwiseOr => left | right,
+                    BinaryExpressionType::BitwiseXor => left ^ right,
+                    BinaryExpressionType::ShiftLeft => left.checked_shl(u32::try_from(right).ok()?)?,
+                    BinaryExpressionType::ShiftRight => left.checked_shr(u32::try_from(right).ok()?)?,
+                    _ => return None,
+                };
+                Some(Expression::IntegerLiteral(value))
+            },
+            (Expression::FloatLiteral(left), Expression::FloatLiteral(right)) => {
+                if operation.is_comparison() {
+                    return Some(Expression::IntegerLiteral(Self::compare(operation, left.partial_cmp(&right)?) as i64));
+                }
+                let value = match operation {
+                    BinaryExpressionType::Addition => left + right,
+                    BinaryExpressionType::Subtraction => left - right,
+                    BinaryExpressionType::Multiplication => left * right,
+                    BinaryExpressionType::Division => left / right,
+                    _ => return None,
+                };
+                Some(Expression::FloatLiteral(value))
+            },
+            (Expression::CharLiteral(left), Expression::CharLiteral(right)) if operation.is_comparison() => {
+                Some(Expression::IntegerLiteral(Self::compare(operation, left.cmp(&right)) as i64))
+            },
+            _ => None,
+        }
+    }
+
+    fn compare(operation: &BinaryExpressionType, ordering: Ordering) -> bool {
+        match operation {
+            BinaryExpressionType::Equal => ordering == Ordering::Equal,
+            BinaryExpressionType::NotEqual => ordering != Ordering::Equal,
+            BinaryExpressionType::Less => ordering == Ordering::Less,
+            BinaryExpressionType::LessEqual => ordering != Ordering::Greater,
+            BinaryExpressionType::Greater => ordering == Ordering::Greater,
+            BinaryExpressionType::GreaterEqual => ordering != Ordering::Less,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn binary(left: Expression, right: Expression, operation: BinaryExpressionType) -> Expression {
+        Expression::Binary(Some(Box::new(left)), Some(Box::new(right)), operation)
+    }
+
+    #[test]
+    fn folds_arithmetic_and_constants() {
+        let mut constants = HashMap::new();
+        constants.insert("SIZE".to_string(), Expression::IntegerLiteral(4));
+        let expression = binary(
+            binary(Expression::IntegerLiteral(2), Expression::VariableRead("SIZE".into()), BinaryExpressionType::Multiplication),
+            Expression::Unary(Some(Box::new(Expression::IntegerLiteral(3))), UnaryExpressionType::Negation),
+            BinaryExpressionType::Addition,
+        );
+        assert_eq!(expression.evaluate_constant(&constants), Some(Expression::IntegerLiteral(5)));
+    }
+
+    #[test]
+    fn folds_comparisons_and_casts() {
+        let constants = HashMap::new();
+        let comparison = binary(Expression::FloatLiteral(1.5), Expression::FloatLiteral(2.0), BinaryExpressionType::LessEqual);
+        let cast = Expression::ExpressionCast(Box::new(Expression::IntegerLiteral(90)), "char".into());
+        assert_eq!(comparison.evaluate_constant(&constants), Some(Expression::IntegerLiteral(1)));
+        assert_eq!(cast.evaluate_constant(&constants), Some(Expression::CharLiteral(90)));
+    }
+
+    #[test]
+    fn folding_keeps_runtime_parts() {
+        let mut expression = binary(
+            Expression::VariableRead("x".into()),
+            binary(Expression::IntegerLiteral(2), Expression::IntegerLiteral(3), BinaryExpressionType::Multiplication),
+            BinaryExpressionType::Less,
+        );
+        expression.fold_constants();
+        assert_eq!(expression, binary(Expression::VariableRead("x".into()), Expression::IntegerLiteral(6), BinaryExpressionType::Less));
+    }
+
+    #[test]
+    fn leaves_runtime_values_and_overflow() {
+        let constants = HashMap::new();
+        let read = binary(Expression::VariableRead("x".into()), Expression::IntegerLiteral(1), BinaryExpressionType::Addition);
+        let overflow = binary(Expression::IntegerLiteral(i64::MAX), Expression::IntegerLiteral(1), BinaryExpressionType::Addition);
+        let division = binary(Expression::IntegerLiteral(1), Expression::IntegerLiteral(0), BinaryExpressionType::Division);
+        assert_eq!(read.evaluate_constant(&constants), None);
+        assert_eq!(overflow.evaluate_constant(&constants), None);
+        assert_eq!(division.evaluate_constant(&constants), None);
+    }
+}
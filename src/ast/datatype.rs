@@ -1,6 +1,7 @@
 use std::{hash::Hash, collections::HashMap, fmt::format};
 
-use inkwell::{types::{BasicType, BasicTypeEnum}, AddressSpace, context::Context};
+#[cfg(feature = "llvm")]
+use inkwell::{types::{BasicType, BasicTypeEnum, BasicMetadataTypeEnum, StructType}, AddressSpace, context::Context};
 
 type DataTypeVector = Vec<Box<DataType>>;
 type NameMap = HashMap<String, u64>;
@@ -11,6 +12,12 @@ pub enum DataTypeEnum {
     Array(Box<DataType>, u64),
     Struct(DataTypeVector, NameMap),
     Pointer(Box<DataType>),
+    Heap(Box<DataType>),
+    Vector(Box<DataType>),
+    // Variants in tag order
+    Enum(Vec<(String, Vec<DataType>)>),
+    // Parameter types and return type, values are function pointers
+    Function(Vec<DataType>, Option<Box<DataType>>),
 }
 
 #[derive(Clone, Debug)]
@@ -20,15 +27,215 @@ pub struct DataType {
 }
 
 impl DataType {
-    pub fn produce_llvm_type<'a>(&self, compiler: &'a Context) -> Box<dyn BasicType<'a> + 'a> {
+    // Ex: fn(i64, f64): i64
+    pub fn function(params: Vec<DataType>, return_type: Option<DataType>) -> DataType {
+        let names: Vec<String> = params.iter().map(|param| param.symbol.clone()).collect();
+        let symbol = match return_type {
+            Some(ref return_type) => format!("fn({}): {}", names.join(", "), return_type.symbol),
+            None => format!("fn({})", names.join(", ")),
+        };
+        DataType { symbol, value: DataTypeEnum::Function(params, return_type.map(Box::new)) }
+    }
+
+    pub fn primitive(name: &str) -> DataType {
+        DataType { symbol: name.to_string(), value: DataTypeEnum::Primitive }
+    }
+
+    pub fn array(interior: DataType, len: u64) -> DataType {
+        DataType { symbol: format!("[{}:{}]", interior.symbol, len), value: DataTypeEnum::Array(Box::new(interior), len) }
+    }
+
+    pub fn pointer(interior: DataType) -> DataType {
+        DataType { symbol: format!("&{}", interior.symbol), value: DataTypeEnum::Pointer(Box::new(interior)) }
+    }
+
+    pub fn heap(interior: DataType) -> DataType {
+        DataType { symbol: format!("box[{}]", interior.symbol), value: DataTypeEnum::Heap(Box::new(interior)) }
+    }
+
+    pub fn vector(interior: DataType) -> DataType {
+        DataType { symbol: format!("vec[{}]", interior.symbol), value: DataTypeEnum::Vector(Box::new(interior)) }
+    }
+
+    // There is no syntax for structs yet, embedders register them with the parser
+    pub fn structure(name: &str, fields: Vec<(&str, DataType)>) -> DataType {
+        let names = fields.iter().enumerate().map(|(index, (field, _))| (field.to_string(), index as u64)).collect();
+        let data_types = fields.into_iter().map(|(_, data_type)| Box::new(data_type)).collect();
+        DataType { symbol: name.to_string(), value: DataTypeEnum::Struct(data_types, names) }
+    }
+
+    fn payload_words(variants: &[(String, Vec<DataType>)], pointer_size: u64) -> u64 {
+        variants.iter().map(|(_, fields)| fields.iter().map(|field| field.storage_size(pointer_size).div_ceil(8)).sum::<u64>()).max().unwrap_or(0)
+    }
+
+    // Upper bound of the bytes a value takes up, struct fields are assumed to be padded to 8 bytes
+    // and pointers take pointer_size, which comes from the target's data layout
+    pub fn storage_size(&self, pointer_size: u64) -> u64 {
+        match self.value {
+            DataTypeEnum::Primitive => match self.symbol.as_str() {
+                "char" | "bool" => 1,
+                _ => 8,
+            },
+            DataTypeEnum::Array(ref interior, len) => interior.storage_size(pointer_size) * len,
+            DataTypeEnum::Struct(ref fields, _) => fields.iter().map(|field| field.storage_size(pointer_size).div_ceil(8) * 8).sum(),
+            DataTypeEnum::Pointer(_) | DataTypeEnum::Heap(_) | DataTypeEnum::Vector(_) | DataTypeEnum::Function(..) => pointer_size,
+            DataTypeEnum::Enum(ref variants) => 8 + 8 * Self::payload_words(variants, pointer_size),
+        }
+    }
+
+    pub fn produce_string(&self) -> String {
+        match self.value {
+            DataTypeEnum::Primitive => self.symbol.clone(),
+            DataTypeEnum::Array(ref interior, ref n) => format!("[{}:{}]", interior.produce_string(), n),
+            DataTypeEnum::Struct(ref values, ref names) => self.symbol.clone(),
+            DataTypeEnum::Pointer(ref interior) => format!("&{}", interior.produce_string()),
+            DataTypeEnum::Heap(ref interior) => format!("box[{}]", interior.produce_string()),
+            DataTypeEnum::Vector(ref interior) => format!("vec[{}]", interior.produce_string()),
+            DataTypeEnum::Enum(_) => self.symbol.clone(),
+            DataTypeEnum::Function(..) => self.symbol.clone(),
+        }
+    }
+
+    // Index and type of a named struct member
+    pub fn field(&self, name: &str) -> Option<(u64, &DataType)> {
+        let DataTypeEnum::Struct(ref data_types, ref names) = self.value else {
+            return None;
+        };
+        let index = *names.get(name)?;
+        Some((index, &data_types[index as usize]))
+    }
+
+    // Tag and payload types of an enum variant
+    pub fn variant(&self, name: &str) -> Option<(u64, &Vec<DataType>)> {
+        let DataTypeEnum::Enum(ref variants) = self.value else {
+            return None;
+        };
+        let tag = variants.iter().position(|(variant, _)| variant == name)?;
+        Some((tag as u64, &variants[tag].1))
+    }
+
+    pub fn element_type(&self) -> Option<&DataType> {
+        match self.value {
+            DataTypeEnum::Array(ref interior, _) => Some(interior),
+            DataTypeEnum::Vector(ref interior) => Some(interior),
+            _ => None,
+        }
+    }
+
+    pub fn return_type(&self) -> Option<&DataType> {
+        match self.value {
+            DataTypeEnum::Function(_, ref return_type) => return_type.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn is_heap(&self) -> bool {
+        matches!(self.value, DataTypeEnum::Heap(_))
+    }
+
+    // Boxes and vector handles share the runtime's reference counted header
+    pub fn is_counted(&self) -> bool {
+        matches!(self.value, DataTypeEnum::Heap(_) | DataTypeEnum::Vector(_))
+    }
+
+    // char and bool divide, shift and check overflow as unsigned, the other integers are signed
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self.symbol.as_str(), "char" | "bool")
+    }
+
+    // Types every target passes the same way as C, aggregates would need the target's struct rules
+    pub fn is_c_type(&self) -> bool {
+        match self.value {
+            DataTypeEnum::Primitive => matches!(self.symbol.as_str(), "i64" | "f64" | "char"),
+            DataTypeEnum::Pointer(_) => true,
+            _ => false,
+        }
+    }
+
+    // Matches a parameter type against an argument type, binding the generic names it contains
+    pub fn bind_generics(&self, concrete: &DataType, generics: &[String], bindings: &mut HashMap<String, DataType>) -> bool {
+        match (&self.value, &concrete.value) {
+            (DataTypeEnum::Primitive, _) if generics.contains(&self.symbol) => {
+                match bindings.get(&self.symbol) {
+                    Some(bound) => bound == concrete,
+                    None => {
+                        bindings.insert(self.symbol.clone(), concrete.clone());
+                        true
+                    }
+                }
+            },
+            (DataTypeEnum::Array(interior, len), DataTypeEnum::Array(other, other_len)) => {
+                len == other_len && interior.bind_generics(other, generics, bindings)
+            },
+            (DataTypeEnum::Pointer(interior), DataTypeEnum::Pointer(other)) |
+            (DataTypeEnum::Heap(interior), DataTypeEnum::Heap(other)) |
+            (DataTypeEnum::Vector(interior), DataTypeEnum::Vector(other)) => interior.bind_generics(other, generics, bindings),
+            (DataTypeEnum::Function(params, return_type), DataTypeEnum::Function(other_params, other_return)) => {
+                params.len() == other_params.len() &&
+                    params.iter().zip(other_params).all(|(param, other)| param.bind_generics(other, generics, bindings)) &&
+                    match (return_type, other_return) {
+                        (Some(return_type), Some(other)) => return_type.bind_generics(other, generics, bindings),
+                        (None, None) => true,
+                        _ => false,
+                    }
+            },
+            _ => self == concrete,
+        }
+    }
+
+    // Ex: [T:3] with T bound to i64 becomes [i64:3]
+    pub fn substitute(&self, bindings: &HashMap<String, DataType>) -> DataType {
+        match self.value {
+            DataTypeEnum::Primitive => bindings.get(&self.symbol).cloned().unwrap_or_else(|| self.clone()),
+            DataTypeEnum::Array(ref interior, len) => DataType::array(interior.substitute(bindings), len),
+            DataTypeEnum::Pointer(ref interior) => DataType::pointer(interior.substitute(bindings)),
+            DataTypeEnum::Heap(ref interior) => DataType::heap(interior.substitute(bindings)),
+            DataTypeEnum::Vector(ref interior) => DataType::vector(interior.substitute(bindings)),
+            DataTypeEnum::Function(ref params, ref return_type) => {
+                let params = params.iter().map(|param| param.substitute(bindings)).collect();
+                DataType::function(params, return_type.as_ref().map(|return_type| return_type.substitute(bindings)))
+            },
+            DataTypeEnum::Struct(..) | DataTypeEnum::Enum(_) => self.clone(),
+        }
+    }
+
+}
+
+#[cfg(feature = "llvm")]
+impl DataType {
+    pub fn produce_llvm_type<'a>(&self, compiler: &'a Context, pointer_size: u64) -> Box<dyn BasicType<'a> + 'a> {
         match &self.value {
             DataTypeEnum::Primitive => self.produce_primitive_llvm_type(compiler),
-            DataTypeEnum::Array(ref interior, len) => Box::new(interior.produce_llvm_type(compiler).array_type(*len as u32)),
-            DataTypeEnum::Struct(ref data_types, ref names) => self.produce_struct_llvm_type(compiler, data_types, names),
-            DataTypeEnum::Pointer(ref interior) => Box::new(interior.produce_llvm_type(compiler).ptr_type(AddressSpace::default())),
+            DataTypeEnum::Array(ref interior, len) => Box::new(interior.produce_llvm_type(compiler, pointer_size).array_type(*len as u32)),
+            DataTypeEnum::Struct(ref data_types, ref names) => self.produce_struct_llvm_type(compiler, data_types, names, pointer_size),
+            DataTypeEnum::Pointer(ref interior) => Box::new(interior.produce_llvm_type(compiler, pointer_size).ptr_type(AddressSpace::default())),
+            DataTypeEnum::Heap(ref interior) => Box::new(interior.produce_llvm_type(compiler, pointer_size).ptr_type(AddressSpace::default())),
+            // Handle to an ss_runtime::RuntimeVector
+            DataTypeEnum::Vector(_) => Box::new(compiler.i8_type().ptr_type(AddressSpace::default())),
+            // Tag followed by enough words to hold the largest variant
+            DataTypeEnum::Enum(ref variants) => {
+                let payload = compiler.i64_type().array_type(Self::payload_words(variants, pointer_size) as u32);
+                Box::new(compiler.struct_type(&[compiler.i64_type().into(), payload.into()], false))
+            },
+            DataTypeEnum::Function(ref params, ref return_type) => {
+                let params: Vec<BasicMetadataTypeEnum> = params.iter().map(|param| param.produce_llvm_type(compiler, pointer_size).as_basic_type_enum().into()).collect();
+                let fn_type = match return_type {
+                    Some(return_type) => return_type.produce_llvm_type(compiler, pointer_size).fn_type(&params, false),
+                    None => compiler.void_type().fn_type(&params, false),
+                };
+                Box::new(fn_type.ptr_type(AddressSpace::default()))
+            },
         }
     }
 
+    pub fn produce_variant_llvm_type<'a>(&self, compiler: &'a Context, tag: u64, pointer_size: u64) -> StructType<'a> {
+        let DataTypeEnum::Enum(ref variants) = self.value else {
+            panic!("{} is not an enum", self.symbol);
+        };
+        let fields: Vec<BasicTypeEnum> = variants[tag as usize].1.iter().map(|field| field.produce_llvm_type(compiler, pointer_size).as_basic_type_enum()).collect();
+        compiler.struct_type(&fields, false)
+    }
+
     fn produce_primitive_llvm_type<'a>(&self, compiler: &'a Context) -> Box<dyn BasicType<'a> + 'a> {
         match self.symbol.as_str() {
             "i64" => Box::new(compiler.i64_type()),
@@ -39,22 +246,12 @@ impl DataType {
         }
     }
 
-    fn produce_struct_llvm_type<'a>(&self, compiler: &'a Context, data_types: &DataTypeVector, names: &NameMap) -> Box<dyn BasicType<'a> + 'a> {
-        let v: Vec<BasicTypeEnum> = data_types.iter().map(|v| v.produce_llvm_type(compiler).as_basic_type_enum()).collect();
+    fn produce_struct_llvm_type<'a>(&self, compiler: &'a Context, data_types: &DataTypeVector, names: &NameMap, pointer_size: u64) -> Box<dyn BasicType<'a> + 'a> {
+        let v: Vec<BasicTypeEnum> = data_types.iter().map(|v| v.produce_llvm_type(compiler, pointer_size).as_basic_type_enum()).collect();
         let slice = v.as_slice();
         let struct_type = compiler.struct_type(slice, false);
         Box::new(struct_type)
     }
-
-    pub fn produce_string(&self) -> String {
-        match self.value {
-            DataTypeEnum::Primitive => self.symbol.clone(),
-            DataTypeEnum::Array(ref interior, ref n) => format!("[{}:{}]", interior.produce_string(), n),
-            DataTypeEnum::Struct(ref values, ref names) => self.symbol.clone(),
-            DataTypeEnum::Pointer(ref interior) => format!("&{}", interior.produce_string()),
-        }
-    }
-
 }
 
 impl PartialEq for DataType {
@@ -67,4 +264,18 @@ impl Hash for DataType {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.symbol.hash(state);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn storage_sizes_follow_the_pointer_size() {
+        let pointers = DataType::array(DataType::pointer(DataType::primitive("i64")), 3);
+        assert_eq!(pointers.storage_size(4), 12);
+        assert_eq!(pointers.storage_size(8), 24);
+        let refs = DataType { symbol: "Refs".to_string(), value: DataTypeEnum::Enum(vec![("Many".to_string(), vec![pointers])]) };
+        assert_eq!(refs.storage_size(4), 8 + 16);
+        assert_eq!(refs.storage_size(8), 8 + 24);
+    }
+}
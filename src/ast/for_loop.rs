@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use super::{Stmt, Variable, Scope, Expression, DataType};
+
+pub struct ForLoop {
+    pub body: Vec<Stmt>,
+    pub variables: HashMap<String, Variable>,
+    pub line: usize,
+    pub variable: String,
+    pub element_type: DataType,
+    pub iterable: Expression,
+    pub iterable_type: DataType,
+}
+
+impl ForLoop {
+    pub fn new(variable: String, iterable: Expression, iterable_type: DataType) -> Self {
+        let element_type = iterable_type.element_type().expect("Can only iterate over arrays and vectors").clone();
+        let mut variables = HashMap::new();
+        variables.insert(variable.clone(), Variable { name: variable.clone(), data_type: element_type.clone() });
+        Self {
+            body: Vec::new(),
+            variables,
+            line: 0,
+            variable,
+            element_type,
+            iterable,
+            iterable_type,
+        }
+    }
+}
+
+impl Scope for ForLoop {
+    fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    fn set_variable(&mut self, variable: Variable) {
+        self.variables.insert(variable.name.clone(), variable);
+    }
+
+    // Functions are only defined at the root, a loop body has none of its own
+    fn contains_function(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn add_function(&mut self, _name: &str, _return_type: Option<DataType>) {
+    }
+
+    fn return_type_of(&self, _name: &str) -> Option<DataType> {
+        None
+    }
+}
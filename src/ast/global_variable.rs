@@ -0,0 +1,18 @@
+use super::{Expression, DataType};
+
+// Module level variable, the initializer has already been folded to a constant
+pub struct GlobalVariable {
+    pub name: String,
+    pub data_type: DataType,
+    pub initializer: Option<Expression>,
+}
+
+impl GlobalVariable {
+    pub fn new(name: String, data_type: DataType, initializer: Option<Expression>) -> Self {
+        Self {
+            name,
+            data_type,
+            initializer,
+        }
+    }
+}
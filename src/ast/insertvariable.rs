@@ -1,25 +1,20 @@
-use inkwell::values::{AnyValue, BasicValueEnum};
-use crate::ast::{Compiler, Expression, Statement};
+use crate::ast::{Expression, DataType, BinaryExpressionType};
 
 pub struct InsertVariable {
-    location: Expression,
-    value: Expression,
+    pub location: Expression,
+    pub value: Expression,
+    pub data_type: Option<DataType>,
+    // Set for compound assignments such as arr[i] += 1
+    pub operation: Option<BinaryExpressionType>,
 }
 
 impl InsertVariable {
-    pub fn new(location: Expression, value: Expression) -> Self {
+    pub fn new(location: Expression, value: Expression, data_type: Option<DataType>) -> Self {
         Self {
             location,
-            value
+            value,
+            data_type,
+            operation: None,
         }
     }
 }
-
-impl Statement for InsertVariable {
-    fn visit<'a>(&'a self, data: &'a Compiler) -> Option<Box<dyn AnyValue + 'a>> {
-        let to_be_stored: BasicValueEnum = self.value.visit(data)?.as_any_value_enum().try_into().unwrap();
-        let ptr = self.location.expression_location(data).unwrap();
-        let stored = data.builder.build_store(ptr, to_be_stored);
-        Some(Box::new(stored))
-    }
-}
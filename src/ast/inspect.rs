@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use crate::json::Json;
+
+use super::{DataType, DataTypeEnum, Expression, Function, GlobalVariable, Item, RootScope, Scope, Stmt, Variable, Visitor};
+
+// Inspectable view of a statement or expression, Ex: for emit --ast
+#[derive(Default)]
+pub struct AstNode {
+    pub kind: &'static str,
+    pub line: Option<usize>,
+    pub fields: Vec<(&'static str, Json)>,
+    pub data_type: Option<DataType>,
+    pub children: Vec<AstNode>,
+}
+
+impl AstNode {
+    pub fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            line: None,
+            fields: Vec::new(),
+            data_type: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn field(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.fields.push((name, Json::String(value.to_string())));
+        self
+    }
+
+    pub fn number(mut self, name: &'static str, value: f64) -> Self {
+        self.fields.push((name, Json::Number(value)));
+        self
+    }
+
+    pub fn typed(mut self, data_type: Option<&DataType>) -> Self {
+        self.data_type = data_type.cloned();
+        self
+    }
+
+    pub fn located(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    pub fn child(mut self, child: AstNode) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    // Ex: SetVariable name=c type=[char:2] line=2, children indented below
+    pub fn to_tree(&self) -> String {
+        let mut tree = String::new();
+        self.write_tree(&mut tree, 0);
+        tree
+    }
+
+    fn write_tree(&self, tree: &mut String, depth: usize) {
+        tree.push_str(&"  ".repeat(depth));
+        tree.push_str(self.kind);
+        for (name, value) in &self.fields {
+            let value = match value {
+                Json::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            tree.push_str(&format!(" {}={}", name, value));
+        }
+        if let Some(ref data_type) = self.data_type {
+            tree.push_str(&format!(" type={}", data_type.symbol));
+        }
+        if let Some(line) = self.line {
+            tree.push_str(&format!(" line={}", line));
+        }
+        tree.push('\n');
+        for child in &self.children {
+            child.write_tree(tree, depth + 1);
+        }
+    }
+
+    // Keys always come in the same order so dumps can be diffed
+    pub fn to_json(&self) -> Json {
+        let mut object = vec![("kind".to_string(), Json::from(self.kind))];
+        if let Some(line) = self.line {
+            object.push(("line".to_string(), Json::from(line)));
+        }
+        for (name, value) in &self.fields {
+            object.push((name.to_string(), value.clone()));
+        }
+        if let Some(ref data_type) = self.data_type {
+            object.push(("type".to_string(), data_type_json(data_type)));
+        }
+        if !self.children.is_empty() {
+            object.push(("children".to_string(), Json::Array(self.children.iter().map(AstNode::to_json).collect())));
+        }
+        Json::Object(object)
+    }
+}
+
+impl RootScope {
+    pub fn inspect(&self, data_types: &HashMap<String, DataType>) -> AstNode {
+        Inspector::new(data_types).visit_root(self)
+    }
+}
+
+// Builds the AstNode tree, one node per item, statement and expression
+pub struct Inspector<'a> {
+    data_types: &'a HashMap<String, DataType>,
+    // Variables of the blocks around the node being built, innermost last
+    variables: Vec<Variable>,
+    functions: HashMap<String, Option<DataType>>,
+}
+
+impl<'a> Inspector<'a> {
+    pub fn new(data_types: &'a HashMap<String, DataType>) -> Self {
+        Self { data_types, variables: Vec::new(), functions: HashMap::new() }
+    }
+
+    fn body(&mut self, node: AstNode, body: &[Stmt]) -> AstNode {
+        body.iter().fold(node, |node, stmt| node.child(self.visit_stmt(stmt)))
+    }
+
+    // A block's variables are only visible while its body is built
+    fn block<'v>(&mut self, node: AstNode, variables: impl IntoIterator<Item = &'v Variable>, body: &[Stmt]) -> AstNode {
+        let depth = self.variables.len();
+        self.variables.extend(variables.into_iter().cloned());
+        let node = self.body(node, body);
+        self.variables.truncate(depth);
+        node
+    }
+
+    fn expressions<'e>(&mut self, node: AstNode, expressions: impl IntoIterator<Item = &'e Expression>) -> AstNode {
+        expressions.into_iter().fold(node, |node, expression| node.child(self.visit_expression(expression)))
+    }
+}
+
+// Linked roots only keep their items, functions and globals are looked up from them
+impl Scope for Inspector<'_> {
+    fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.iter().rev().find(|variable| variable.name == name)
+    }
+
+    fn set_variable(&mut self, variable: Variable) {
+        self.variables.push(variable);
+    }
+
+    fn contains_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    fn return_type_of(&self, name: &str) -> Option<DataType> {
+        self.functions.get(name).cloned().flatten()
+    }
+
+    fn add_function(&mut self, name: &str, return_type: Option<DataType>) {
+        self.functions.insert(name.to_string(), return_type);
+    }
+}
+
+impl Visitor for Inspector<'_> {
+    type Output = AstNode;
+
+    fn visit_root(&mut self, root: &RootScope) -> AstNode {
+        for item in &root.items {
+            match item {
+                Item::Function(function) => self.add_function(&function.name, function.return_type.clone()),
+                Item::Global(global) => self.set_variable(Variable { name: global.name.clone(), data_type: global.data_type.clone() }),
+            }
+        }
+        root.items.iter().fold(AstNode::new("Root"), |node, item| node.child(self.visit_item(item)))
+    }
+
+    fn visit_function(&mut self, function: &Function) -> AstNode {
+        let mut node = AstNode::new("Function").field("name", &function.name).typed(function.return_type.as_ref());
+        for (name, data_type) in &function.params {
+            node = node.child(AstNode::new("Param").field("name", name).typed(Some(data_type)));
+        }
+        let mut locals: Vec<&Variable> = function.variables.values()
+            .filter(|variable| !function.params.iter().any(|(name, _)| *name == variable.name))
+            .collect();
+        locals.sort_by(|a, b| a.name.cmp(&b.name));
+        for local in locals {
+            node = node.child(AstNode::new("Local").field("name", &local.name).typed(Some(&local.data_type)));
+        }
+        self.block(node, function.variables.values(), &function.body)
+    }
+
+    fn visit_global(&mut self, global: &GlobalVariable) -> AstNode {
+        let node = AstNode::new("GlobalVariable").field("name", &global.name).typed(Some(&global.data_type));
+        self.expressions(node, &global.initializer)
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) -> AstNode {
+        match stmt {
+            Stmt::Set(set) => {
+                let node = AstNode::new("SetVariable").field("name", &set.name).typed(Some(&set.data_type));
+                node.child(self.visit_expression(&set.value))
+            },
+            Stmt::Insert(insert) => {
+                let mut node = AstNode::new("InsertVariable").typed(insert.data_type.as_ref());
+                if let Some(ref operation) = insert.operation {
+                    node = node.field("operation", format!("{:?}", operation));
+                }
+                self.expressions(node, [&insert.location, &insert.value])
+            },
+            Stmt::Return(ret) => AstNode::new("Return").child(self.visit_expression(&ret.value)),
+            Stmt::If(condition) => {
+                let node = AstNode::new("If").located(condition.line).child(self.visit_expression(&condition.condition));
+                self.block(node, condition.variables.values(), &condition.body)
+            },
+            Stmt::For(for_loop) => {
+                let iterable = AstNode::new("Iterable").typed(Some(&for_loop.iterable_type)).child(self.visit_expression(&for_loop.iterable));
+                let node = AstNode::new("For")
+                    .located(for_loop.line)
+                    .field("variable", &for_loop.variable)
+                    .typed(Some(&for_loop.element_type))
+                    .child(iterable);
+                self.block(node, for_loop.variables.values(), &for_loop.body)
+            },
+            Stmt::Match(statement) => {
+                let mut node = AstNode::new("Match").located(statement.line).typed(Some(&statement.enum_type)).child(self.visit_expression(&statement.scrutinee));
+                for arm in &statement.arms {
+                    let mut arm_node = AstNode::new("MatchArm").field("tag", arm.tag.map_or("else".to_string(), |tag| tag.to_string()));
+                    for (name, data_type) in &arm.bindings {
+                        arm_node = arm_node.child(AstNode::new("Binding").field("name", name).typed(Some(data_type)));
+                    }
+                    node = node.child(self.block(arm_node, statement.variables.values().chain(arm.variables.values()), &arm.body));
+                }
+                node
+            },
+            Stmt::Expression(expression) => self.visit_expression(expression),
+            // Located statements show up as the statement they wrap
+            Stmt::Located(located) => self.visit_stmt(&located.statement).located(located.line),
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) -> AstNode {
+        let node = match expression {
+            Expression::Binary(_, _, operation) => AstNode::new("Binary").field("operation", format!("{:?}", operation)),
+            Expression::Unary(_, operation) => AstNode::new("Unary").field("operation", format!("{:?}", operation)),
+            Expression::FunctionCall(name, _) => AstNode::new("Call").field("name", name),
+            Expression::IndirectCall(..) => AstNode::new("IndirectCall"),
+            Expression::FunctionReference(name, symbol) => AstNode::new("FunctionReference").field("name", name).field("signature", symbol),
+            Expression::Array(_) => AstNode::new("Array"),
+            Expression::VectorLiteral(_) => AstNode::new("Vector"),
+            Expression::VariableRead(name) => AstNode::new("Variable").field("name", name),
+            Expression::VariableExtract(..) => AstNode::new("Index"),
+            Expression::FieldAccess(_, field) => AstNode::new("FieldAccess").field("field", field),
+            Expression::EnumVariant(name, variant, _) => AstNode::new("EnumVariant").field("enum", name).field("variant", variant),
+            Expression::IntegerLiteral(value) => AstNode::new("Integer").number("value", *value as f64),
+            Expression::FloatLiteral(value) => AstNode::new("Float").number("value", *value),
+            Expression::StringLiteral(value) => AstNode::new("String").field("value", format!("{:?}", value)),
+            Expression::CharLiteral(value) => AstNode::new("Char").field("value", format!("{:?}", *value as char)),
+            Expression::ExpressionCast(_, to) => AstNode::new("Cast").field("to", to),
+        };
+        let data_type = expression.expression_type(self, self.data_types);
+        self.expressions(node.typed(data_type.as_ref()), expression.children())
+    }
+}
+
+// The full structure of a type, not only its symbol
+pub fn data_type_json(data_type: &DataType) -> Json {
+    let mut object = vec![("symbol", Json::from(data_type.symbol.as_str()))];
+    match data_type.value {
+        DataTypeEnum::Primitive => object.push(("kind", Json::from("primitive"))),
+        DataTypeEnum::Array(ref element, size) => {
+            object.push(("kind", Json::from("array")));
+            object.push(("element", data_type_json(element)));
+            object.push(("size", Json::Number(size as f64)));
+        },
+        DataTypeEnum::Pointer(ref element) => {
+            object.push(("kind", Json::from("pointer")));
+            object.push(("element", data_type_json(element)));
+        },
+        DataTypeEnum::Heap(ref element) => {
+            object.push(("kind", Json::from("box")));
+            object.push(("element", data_type_json(element)));
+        },
+        DataTypeEnum::Vector(ref element) => {
+            object.push(("kind", Json::from("vector")));
+            object.push(("element", data_type_json(element)));
+        },
+        DataTypeEnum::Struct(ref fields, ref names) => {
+            let mut names: Vec<(&String, &u64)> = names.iter().collect();
+            names.sort_by_key(|(_, index)| **index);
+            object.push(("kind", Json::from("struct")));
+            object.push(("fields", Json::Array(names.into_iter().map(|(name, index)| {
+                Json::object(vec![("name", Json::from(name.as_str())), ("type", data_type_json(&fields[*index as usize]))])
+            }).collect())));
+        },
+        DataTypeEnum::Enum(ref variants) => {
+            object.push(("kind", Json::from("enum")));
+            object.push(("variants", Json::Array(variants.iter().map(|(name, fields)| {
+                Json::object(vec![("name", Json::from(name.as_str())), ("fields", Json::Array(fields.iter().map(data_type_json).collect()))])
+            }).collect())));
+        },
+        DataTypeEnum::Function(ref params, ref return_type) => {
+            object.push(("kind", Json::from("function")));
+            object.push(("params", Json::Array(params.iter().map(data_type_json).collect())));
+            object.push(("return", return_type.as_ref().map_or(Json::Null, |return_type| data_type_json(return_type))));
+        },
+    }
+    Json::object(object)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parsing::Parser;
+
+    const SOURCE: &str = "def main(a: i64): i64 {\n    x = a + 3\n    return x\n}\n";
+
+    fn inspect(source: &str) -> super::AstNode {
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        root.inspect(&parser.data_types)
+    }
+
+    #[test]
+    fn prints_an_indented_tree() {
+        let expected = "Root
+  Function name=main type=i64
+    Param name=a type=i64
+    Local name=x type=i64
+    SetVariable name=x type=i64 line=2
+      Binary operation=Addition type=i64
+        Variable name=a type=i64
+        Integer value=3 type=i64
+    Return line=3
+      Variable name=x type=i64
+";
+        assert_eq!(inspect(SOURCE).to_tree(), expected);
+    }
+
+    #[test]
+    fn prints_stable_json() {
+        let json = inspect(SOURCE).to_json();
+        let function = &json.get("children").as_array().unwrap()[0];
+        let assignment = &function.get("children").as_array().unwrap()[2];
+        let i64_type = r#""type":{"symbol":"i64","kind":"primitive"}"#;
+        assert_eq!(assignment.to_string(), [
+            r#"{"kind":"SetVariable","line":2,"name":"x","#, i64_type, r#","children":[{"kind":"Binary","operation":"Addition","#, i64_type,
+            r#","children":[{"kind":"Variable","name":"a","#, i64_type, r#"},{"kind":"Integer","value":3,"#, i64_type, "}]}]}",
+        ].concat());
+        assert_eq!(json.to_string(), inspect(SOURCE).to_json().to_string());
+    }
+
+    #[test]
+    fn types_expressions_inside_blocks() {
+        let source = "def twice(n: i64): i64 {\n    return n * 2\n}\ndef main(): i64 {\n    items = [1, 2]\n    if items[0] > 0 {\n        y = 2.5\n        z = y\n        return twice(items[1])\n    }\n    return 0\n}\n";
+        let tree = inspect(source).to_tree();
+        for line in [
+            "    Local name=items type=[i64:2]\n",
+            "        Index type=i64\n",
+            "          Variable name=items type=[i64:2]\n",
+            "        Float value=2.5 type=f64\n",
+            "        Variable name=y type=f64\n",
+            "        Call name=twice type=i64\n",
+        ] {
+            assert!(tree.contains(line), "missing {:?} in\n{}", line, tree);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use super::Stmt;
+
+// Records the source line of a statement so runtime checks can report it
+pub struct LocatedStatement {
+    pub line: usize,
+    pub statement: Box<Stmt>,
+}
+
+impl LocatedStatement {
+    pub fn new(line: usize, statement: Stmt) -> Self {
+        Self {
+            line,
+            statement: Box::new(statement),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use crate::parsing::{ParsingError, ParsingResult};
+
+use super::{Stmt, Variable, Scope, Expression, DataType, DataTypeEnum};
+
+pub struct MatchStatement {
+    pub arms: Vec<MatchArm>,
+    pub variables: HashMap<String, Variable>,
+    pub line: usize,
+    pub enum_type: DataType,
+    pub scrutinee: Expression,
+}
+
+// A tag of None is the else arm
+pub struct MatchArm {
+    pub body: Vec<Stmt>,
+    pub variables: HashMap<String, Variable>,
+    pub tag: Option<u64>,
+    pub bindings: Vec<(String, DataType)>,
+}
+
+impl MatchStatement {
+    pub fn new(scrutinee: Expression, enum_type: DataType) -> Self {
+        Self {
+            arms: Vec::new(),
+            variables: HashMap::new(),
+            line: 0,
+            enum_type,
+            scrutinee,
+        }
+    }
+
+    // Every variant needs an arm unless there is an else arm
+    pub fn check_exhaustive(&self) -> ParsingResult<()> {
+        if self.arms.iter().any(|arm| arm.tag.is_none()) {
+            return Ok(());
+        }
+        let DataTypeEnum::Enum(ref variants) = self.enum_type.value else {
+            return Err(Box::new(ParsingError::NotAnEnum(self.enum_type.symbol.clone())));
+        };
+        let missing: Vec<String> = variants.iter().enumerate()
+            .filter(|(tag, _)| !self.arms.iter().any(|arm| arm.tag == Some(*tag as u64)))
+            .map(|(_, (name, _))| name.clone())
+            .collect();
+        if !missing.is_empty() {
+            return Err(Box::new(ParsingError::NonExhaustiveMatch(missing)));
+        }
+        Ok(())
+    }
+}
+
+impl MatchArm {
+    pub fn new(tag: Option<u64>, bindings: Vec<(String, DataType)>) -> Self {
+        let variables = bindings.iter().map(|(name, data_type)| {
+            (name.clone(), Variable { name: name.clone(), data_type: data_type.clone() })
+        }).collect();
+        Self {
+            body: Vec::new(),
+            variables,
+            tag,
+            bindings,
+        }
+    }
+}
+
+impl Scope for MatchStatement {
+    fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    fn set_variable(&mut self, variable: Variable) {
+        self.variables.insert(variable.name.clone(), variable);
+    }
+
+    fn contains_function(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn add_function(&mut self, _name: &str, _return_type: Option<DataType>) {
+    }
+
+    fn return_type_of(&self, _name: &str) -> Option<DataType> {
+        None
+    }
+}
+
+impl Scope for MatchArm {
+    fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    fn set_variable(&mut self, variable: Variable) {
+        self.variables.insert(variable.name.clone(), variable);
+    }
+
+    fn contains_function(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn add_function(&mut self, _name: &str, _return_type: Option<DataType>) {
+    }
+
+    fn return_type_of(&self, _name: &str) -> Option<DataType> {
+        None
+    }
+}
@@ -9,6 +9,15 @@ mod return_command;
 mod datatype;
 mod root_scope;
 mod ifcondition;
+mod compiler_options;
+mod builtins;
+mod for_loop;
+mod located_statement;
+mod match_statement;
+mod global_variable;
+mod const_eval;
+mod inspect;
+mod visitor;
 
 pub use statement::*;
 pub use expression::*;
@@ -20,4 +29,11 @@ pub use return_command::*;
 pub use datatype::*;
 pub use insertvariable::*;
 pub use root_scope::*;
-pub use ifcondition::*;
\ No newline at end of file
+pub use ifcondition::*;
+pub use compiler_options::*;
+pub use builtins::*;
+pub use for_loop::*;
+pub use located_statement::*;
+pub use match_statement::*;
+pub use global_variable::*;
+pub use visitor::*;
\ No newline at end of file
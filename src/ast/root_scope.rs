@@ -1,28 +1,16 @@
-use std::{collections::HashSet};
 use std::collections::HashMap;
-use inkwell::values::AnyValue;
-use crate::ast::{Compiler, Scope, Statement, Variable};
+use crate::ast::{Scope, Item, Variable};
 
 use super::DataType;
 
 #[derive(Default)]
 pub struct RootScope {
-    pub commands: Vec<Box<dyn Statement>>,
+    pub items: Vec<Item>,
     pub variables: HashMap<String, Variable>,
     pub functions: HashMap<String, Option<DataType>>,
     pub name: String,
 }
 
-impl Statement for RootScope {
-    fn visit<'a>(&'a self, data: &'a Compiler) -> Option<Box<dyn AnyValue + 'a>> {
-        for command in &self.commands {
-            command.visit(data);
-        }
-
-        None
-    }
-}
-
 impl Scope for RootScope {
     fn get_variable(&self, name: &str) -> Option<&Variable> {
         self.variables.get(name)
@@ -32,18 +20,6 @@ impl Scope for RootScope {
         self.variables.insert(variable.name.clone(), variable);
     }
 
-    fn commands(&self) -> &Vec<Box<dyn Statement>> {
-        &self.commands
-    }
-
-    fn commands_mut(&mut self) -> &mut Vec<Box<dyn Statement>> {
-        &mut self.commands
-    }
-
-    fn scope_type(&self) -> &'static str {
-        "root"
-    }
-
     fn contains_function(&self, name: &str) -> bool {
         self.functions.contains_key(name)
     }
@@ -55,4 +31,4 @@ impl Scope for RootScope {
     fn return_type_of(&self, name: &str) -> Option<DataType> {
         self.functions[name].clone()
     }
-}
\ No newline at end of file
+}
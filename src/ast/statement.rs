@@ -1,19 +1,20 @@
-use inkwell::{module::Module, context::Context, builder::Builder, values::{AnyValue, PointerValue, FunctionValue, BasicValueEnum}};
-use std::{collections::HashMap, cell::RefCell};
-use std::any::Any;
+use super::{Expression, SetVariable, InsertVariable, ReturnCommand, IfCondition, ForLoop, MatchStatement, LocatedStatement, Function, GlobalVariable};
 
-use super::DataType;
-
-pub struct Compiler<'ctx> {
-    pub context: &'ctx Context,
-    pub module: Module<'ctx>,
-    pub builder: Builder<'ctx>,
-    pub variable_table: RefCell<HashMap<String, PointerValue<'ctx>>>,
-    pub function_table: RefCell<HashMap<String, FunctionValue<'ctx>>>,
-    pub current_function_params: RefCell<HashMap<String, BasicValueEnum<'ctx>>>,
-    pub data_types: HashMap<String, DataType>,
+// Anything that can appear inside a function body
+pub enum Stmt {
+    Set(SetVariable),
+    Insert(InsertVariable),
+    Return(ReturnCommand),
+    If(IfCondition),
+    For(ForLoop),
+    Match(MatchStatement),
+    // Calls made for their effect, Ex: push(v, 2)
+    Expression(Expression),
+    Located(LocatedStatement),
 }
 
-pub trait Statement: Any {
-    fn visit<'a>(&'a self, data: &'a Compiler) -> Option<Box<dyn AnyValue + 'a>>;
+// Anything that can appear at the top of a module
+pub enum Item {
+    Function(Function),
+    Global(GlobalVariable),
 }
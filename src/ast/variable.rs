@@ -1,7 +1,7 @@
 use super::datatype::DataType;
 
 
-#[derive(Hash)]
+#[derive(Clone, Hash)]
 pub struct Variable {
     pub name: String,
     pub data_type: DataType,
@@ -0,0 +1,147 @@
+use super::{Expression, Function, GlobalVariable, Item, RootScope, Stmt};
+
+// A read only pass over the tree, Ex: codegen or --ast
+// Nodes a pass doesn't handle itself are walked into and produce the default output
+pub trait Visitor {
+    type Output: Default;
+
+    fn visit_root(&mut self, root: &RootScope) -> Self::Output {
+        walk_root(self, root)
+    }
+
+    fn visit_item(&mut self, item: &Item) -> Self::Output {
+        walk_item(self, item)
+    }
+
+    fn visit_function(&mut self, function: &Function) -> Self::Output {
+        walk_function(self, function)
+    }
+
+    fn visit_global(&mut self, global: &GlobalVariable) -> Self::Output {
+        walk_global(self, global)
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) -> Self::Output {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) -> Self::Output {
+        walk_expression(self, expression)
+    }
+}
+
+pub fn walk_root<V: Visitor + ?Sized>(visitor: &mut V, root: &RootScope) -> V::Output {
+    for item in &root.items {
+        visitor.visit_item(item);
+    }
+    V::Output::default()
+}
+
+pub fn walk_item<V: Visitor + ?Sized>(visitor: &mut V, item: &Item) -> V::Output {
+    match item {
+        Item::Function(function) => visitor.visit_function(function),
+        Item::Global(global) => visitor.visit_global(global),
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, function: &Function) -> V::Output {
+    walk_body(visitor, &function.body);
+    V::Output::default()
+}
+
+pub fn walk_global<V: Visitor + ?Sized>(visitor: &mut V, global: &GlobalVariable) -> V::Output {
+    if let Some(ref initializer) = global.initializer {
+        visitor.visit_expression(initializer);
+    }
+    V::Output::default()
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) -> V::Output {
+    match stmt {
+        Stmt::Set(set) => {
+            visitor.visit_expression(&set.value);
+        },
+        Stmt::Insert(insert) => {
+            visitor.visit_expression(&insert.location);
+            visitor.visit_expression(&insert.value);
+        },
+        Stmt::Return(ret) => {
+            visitor.visit_expression(&ret.value);
+        },
+        Stmt::If(condition) => {
+            visitor.visit_expression(&condition.condition);
+            walk_body(visitor, &condition.body);
+        },
+        Stmt::For(for_loop) => {
+            visitor.visit_expression(&for_loop.iterable);
+            walk_body(visitor, &for_loop.body);
+        },
+        Stmt::Match(statement) => {
+            visitor.visit_expression(&statement.scrutinee);
+            for arm in &statement.arms {
+                walk_body(visitor, &arm.body);
+            }
+        },
+        Stmt::Expression(expression) => {
+            visitor.visit_expression(expression);
+        },
+        Stmt::Located(located) => {
+            visitor.visit_stmt(&located.statement);
+        },
+    }
+    V::Output::default()
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) -> V::Output {
+    for child in expression.children() {
+        visitor.visit_expression(child);
+    }
+    V::Output::default()
+}
+
+fn walk_body<V: Visitor + ?Sized>(visitor: &mut V, body: &[Stmt]) {
+    for stmt in body {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::Parser;
+
+    const SOURCE: &str = "def main(): i64 {\n    x = 2\n    if x > 1 {\n        x = x + 1\n    }\n    return x * 3\n}\n";
+
+    #[derive(Default)]
+    struct Counter {
+        statements: usize,
+        reads: usize,
+    }
+
+    impl Visitor for Counter {
+        type Output = ();
+
+        fn visit_stmt(&mut self, stmt: &Stmt) {
+            if !matches!(stmt, Stmt::Located(_)) {
+                self.statements += 1;
+            }
+            walk_stmt(self, stmt)
+        }
+
+        fn visit_expression(&mut self, expression: &Expression) {
+            if let Expression::VariableRead(_) = expression {
+                self.reads += 1;
+            }
+            walk_expression(self, expression)
+        }
+    }
+
+    #[test]
+    fn visits_nested_statements() {
+        let root = Parser::new(SOURCE.to_string()).parse().unwrap();
+        let mut counter = Counter::default();
+        counter.visit_root(&root);
+        assert_eq!(counter.statements, 4);
+        assert_eq!(counter.reads, 3);
+    }
+}
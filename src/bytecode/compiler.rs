@@ -0,0 +1,533 @@
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use crate::{ast::{self, BinaryExpressionType, CompilerOptions, DataType, DataTypeEnum, Expression, GlobalVariable, Item, RootScope, Stmt, UnaryExpressionType, Visitor, is_builtin}, interpreter::Value};
+
+use super::{Cast, Function, Instruction, Program};
+
+// Reported like runtime errors, with the function and line being compiled
+#[derive(Debug)]
+pub struct CompileError {
+    pub function: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.function, self.line, self.message)
+    }
+}
+
+impl Error for CompileError {}
+
+pub fn compile(root: &RootScope, data_types: &HashMap<String, DataType>, options: &CompilerOptions) -> Result<Program, CompileError> {
+    let mut compiler = Compiler {
+        data_types,
+        checked: options.overflow_checks,
+        program: Program {
+            source_name: options.source_name.clone(),
+            constants: Vec::new(),
+            globals: Vec::new(),
+            functions: Vec::new(),
+            initializer: 0,
+        },
+        function_indices: HashMap::new(),
+        globals: HashMap::new(),
+        current: None,
+        locals: HashMap::new(),
+        error: None,
+    };
+    compiler.visit_root(root);
+    match compiler.error {
+        Some(error) => Err(error),
+        None => Ok(compiler.program),
+    }
+}
+
+struct Compiler<'a> {
+    data_types: &'a HashMap<String, DataType>,
+    checked: bool,
+    program: Program,
+    function_indices: HashMap<String, u32>,
+    globals: HashMap<String, (u32, DataType)>,
+    // Function being compiled and the slots and types of its variables
+    current: Option<Function>,
+    locals: HashMap<String, (u32, DataType)>,
+    // The first error, compiling carries on so the rest of the tree is still walked
+    error: Option<CompileError>,
+}
+
+impl<'a> Compiler<'a> {
+    fn begin_function(&mut self, name: &str, params: &[(String, DataType)], returns_value: bool) {
+        self.locals = params.iter().enumerate().map(|(slot, (param, data_type))| (param.clone(), (slot as u32, data_type.clone()))).collect();
+        self.current = Some(Function {
+            name: name.to_string(),
+            params: params.len() as u32,
+            locals: params.len() as u32,
+            returns_value,
+            code: Vec::new(),
+            lines: Vec::new(),
+        });
+    }
+
+    fn end_function(&mut self) {
+        self.emit(Instruction::ReturnVoid);
+        let function = self.current.take().unwrap();
+        self.program.functions.push(function);
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.current.as_mut().expect("code is only emitted inside functions")
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        let code = &mut self.function().code;
+        code.push(instruction);
+        code.len() - 1
+    }
+
+    // Points the jump at the next instruction
+    fn patch(&mut self, jump: usize) {
+        let target = self.function().code.len() as u32;
+        match self.function().code[jump] {
+            Instruction::Jump(ref mut to) | Instruction::JumpIfFalse(ref mut to) => *to = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn mark_line(&mut self, line: usize) {
+        let function = self.function();
+        let start = function.code.len() as u32;
+        if function.lines.last().map(|(_, last)| *last as usize) != Some(line) {
+            function.lines.push((start, line as u32));
+        }
+    }
+
+    fn fail(&mut self, message: String) {
+        if self.error.is_some() {
+            return;
+        }
+        let (function, line) = match self.current {
+            Some(ref function) => (function.name.clone(), function.lines.last().map_or(0, |(_, line)| *line as usize)),
+            None => (String::new(), 0),
+        };
+        self.error = Some(CompileError { function, line, message });
+    }
+
+    fn constant(&mut self, value: Value) {
+        let index = match self.program.constants.iter().position(|constant| *constant == value) {
+            Some(index) => index,
+            None => {
+                self.program.constants.push(value);
+                self.program.constants.len() - 1
+            },
+        };
+        self.emit(Instruction::Constant(index as u32));
+    }
+
+    // Slot that no variable can name, for values a statement keeps around
+    fn temporary(&mut self) -> u32 {
+        let function = self.function();
+        function.locals += 1;
+        function.locals - 1
+    }
+
+    fn define(&mut self, name: &str, data_type: &DataType) -> u32 {
+        let slot = self.temporary();
+        self.locals.insert(name.to_string(), (slot, data_type.clone()));
+        slot
+    }
+
+    // The local a loop variable or match binding hides, put back by restore
+    fn shadowed(&self, name: &str) -> Option<(u32, DataType)> {
+        self.locals.get(name).cloned()
+    }
+
+    fn restore(&mut self, name: &str, shadowed: Option<(u32, DataType)>) {
+        match shadowed {
+            Some(local) => self.locals.insert(name.to_string(), local),
+            None => self.locals.remove(name),
+        };
+    }
+
+    fn variable_type(&self, name: &str) -> Option<&DataType> {
+        self.locals.get(name).or_else(|| self.globals.get(name)).map(|(_, data_type)| data_type)
+    }
+
+    // Type of anything compile_address accepts, Ex: outer.inner or items[0]
+    fn place_type(&self, expression: &Expression) -> Option<DataType> {
+        match expression {
+            Expression::VariableRead(name) => self.variable_type(name).cloned(),
+            Expression::VariableExtract(base, _) => self.place_type(base)?.element_type().cloned(),
+            Expression::FieldAccess(base, field) => self.place_type(base)?.field(field).map(|(_, data_type)| data_type.clone()),
+            Expression::Unary(Some(interior), UnaryExpressionType::Dereference) => match self.place_type(interior)?.value {
+                DataTypeEnum::Pointer(interior) | DataTypeEnum::Heap(interior) => Some(*interior),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn compile_function(&mut self, function: &ast::Function) {
+        self.begin_function(&function.name, &function.params, function.return_type.is_some());
+        // The definition is only linked into native programs
+        if function.external {
+            self.mark_line(function.line);
+            return self.fail(format!("extern function {} needs the llvm backend", function.name));
+        }
+        for stmt in &function.body {
+            self.visit_stmt(stmt);
+        }
+        self.end_function();
+    }
+
+    fn compile_global(&mut self, global: &GlobalVariable) {
+        match global.initializer {
+            Some(ref initializer) => self.visit_expression(initializer),
+            None => self.constant(Value::zero(&global.data_type)),
+        }
+        let (index, _) = self.globals[&global.name];
+        self.emit(Instruction::StoreGlobal(index));
+    }
+
+    fn compile_statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Set(set) => {
+                self.compile_expression(&set.value);
+                let store = match (self.locals.get(&set.name), self.globals.get(&set.name)) {
+                    (Some((slot, _)), _) => Instruction::StoreLocal(*slot),
+                    (None, Some((index, _))) => Instruction::StoreGlobal(*index),
+                    (None, None) => Instruction::StoreLocal(self.define(&set.name, &set.data_type)),
+                };
+                self.emit(store);
+            },
+            // Same evaluation order as the other backends, the location first only for compound assignments
+            Stmt::Insert(insert) => match insert.operation {
+                Some(ref operation) => {
+                    let location = self.temporary();
+                    self.compile_address(&insert.location);
+                    self.emit(Instruction::StoreLocal(location));
+                    self.emit(Instruction::LoadLocal(location));
+                    self.emit(Instruction::Load);
+                    self.compile_expression(&insert.value);
+                    self.emit(Instruction::Binary(operation.clone(), self.checked));
+                    self.emit(Instruction::LoadLocal(location));
+                    self.emit(Instruction::Store);
+                },
+                None => {
+                    self.compile_expression(&insert.value);
+                    self.compile_address(&insert.location);
+                    self.emit(Instruction::Store);
+                },
+            },
+            Stmt::Return(ret) => {
+                self.compile_expression(&ret.value);
+                self.emit(Instruction::Return);
+            },
+            Stmt::If(condition) => {
+                self.mark_line(condition.line);
+                self.compile_expression(&condition.condition);
+                let skip = self.emit(Instruction::JumpIfFalse(0));
+                for stmt in &condition.body {
+                    self.visit_stmt(stmt);
+                }
+                self.patch(skip);
+            },
+            Stmt::For(for_loop) => {
+                self.mark_line(for_loop.line);
+                let (iterable, index) = (self.temporary(), self.temporary());
+                self.compile_address(&for_loop.iterable);
+                self.emit(Instruction::StoreLocal(iterable));
+                self.constant(Value::Int(0));
+                self.emit(Instruction::StoreLocal(index));
+                let shadowed = self.shadowed(&for_loop.variable);
+                let variable = self.define(&for_loop.variable, &for_loop.element_type);
+
+                // Vectors may grow inside the body so their length is read every iteration
+                let condition = self.function().code.len() as u32;
+                self.emit(Instruction::LoadLocal(index));
+                self.emit(Instruction::LoadLocal(iterable));
+                self.emit(Instruction::Count);
+                self.emit(Instruction::Binary(BinaryExpressionType::Less, false));
+                let exit = self.emit(Instruction::JumpIfFalse(0));
+                self.emit(Instruction::LoadLocal(iterable));
+                self.emit(Instruction::LoadLocal(index));
+                self.emit(Instruction::Element);
+                self.emit(Instruction::Load);
+                self.emit(Instruction::StoreLocal(variable));
+                for stmt in &for_loop.body {
+                    self.visit_stmt(stmt);
+                }
+                self.emit(Instruction::LoadLocal(index));
+                self.constant(Value::Int(1));
+                self.emit(Instruction::Binary(BinaryExpressionType::Addition, false));
+                self.emit(Instruction::StoreLocal(index));
+                self.emit(Instruction::Jump(condition));
+                self.patch(exit);
+                self.restore(&for_loop.variable, shadowed);
+            },
+            Stmt::Match(statement) => {
+                self.mark_line(statement.line);
+                let scrutinee = self.temporary();
+                self.compile_expression(&statement.scrutinee);
+                self.emit(Instruction::StoreLocal(scrutinee));
+                // An arm for the tag wins over the else arm wherever it is written
+                let tagged = statement.arms.iter().filter(|arm| arm.tag.is_some());
+                let mut exits = Vec::new();
+                for arm in tagged.chain(statement.arms.iter().filter(|arm| arm.tag.is_none())) {
+                    let next = arm.tag.map(|tag| {
+                        self.emit(Instruction::LoadLocal(scrutinee));
+                        self.emit(Instruction::Tag);
+                        self.constant(Value::Int(tag as i64));
+                        self.emit(Instruction::Binary(BinaryExpressionType::Equal, false));
+                        self.emit(Instruction::JumpIfFalse(0))
+                    });
+                    self.emit(Instruction::LoadLocal(scrutinee));
+                    self.emit(Instruction::Unpack(arm.bindings.len() as u32));
+                    let shadowed: Vec<_> = arm.bindings.iter().map(|(name, _)| self.shadowed(name)).collect();
+                    let slots: Vec<u32> = arm.bindings.iter().map(|(name, data_type)| self.define(name, data_type)).collect();
+                    for slot in slots.into_iter().rev() {
+                        self.emit(Instruction::StoreLocal(slot));
+                    }
+                    for stmt in &arm.body {
+                        self.visit_stmt(stmt);
+                    }
+                    for ((name, _), local) in arm.bindings.iter().zip(shadowed).rev() {
+                        self.restore(name, local);
+                    }
+                    exits.push(self.emit(Instruction::Jump(0)));
+                    if let Some(next) = next {
+                        self.patch(next);
+                    }
+                }
+                for exit in exits {
+                    self.patch(exit);
+                }
+            },
+            Stmt::Expression(expression) => {
+                self.compile_expression(expression);
+                self.emit(Instruction::Discard);
+            },
+            Stmt::Located(located) => {
+                self.mark_line(located.line);
+                self.visit_stmt(&located.statement);
+            },
+        }
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Binary(Some(left), Some(right), operation) => {
+                self.compile_expression(left);
+                self.compile_expression(right);
+                self.emit(Instruction::Binary(operation.clone(), self.checked));
+            },
+            Expression::Binary(..) | Expression::Unary(None, _) => self.fail("incomplete expression".to_string()),
+            Expression::Unary(Some(interior), operation) => match operation {
+                UnaryExpressionType::Reference => self.compile_address(interior),
+                UnaryExpressionType::Dereference => {
+                    self.compile_expression(interior);
+                    self.emit(Instruction::Load);
+                },
+                UnaryExpressionType::BitwiseNot => {
+                    self.compile_expression(interior);
+                    self.emit(Instruction::Not);
+                },
+                UnaryExpressionType::Negation => {
+                    self.compile_expression(interior);
+                    self.emit(Instruction::Negate);
+                },
+            },
+            Expression::VariableRead(name) => {
+                let load = match (self.locals.get(name), self.globals.get(name)) {
+                    (Some((slot, _)), _) => Instruction::LoadLocal(*slot),
+                    (None, Some((index, _))) => Instruction::LoadGlobal(*index),
+                    (None, None) => return self.fail(format!("unknown variable {}", name)),
+                };
+                self.emit(load);
+            },
+            Expression::IntegerLiteral(literal) => self.constant(Value::Int(*literal)),
+            Expression::FloatLiteral(literal) => self.constant(Value::Float(*literal)),
+            Expression::StringLiteral(str) => self.constant(Value::Array(str.bytes().map(Value::Char).collect())),
+            Expression::CharLiteral(c) => self.constant(Value::Char(*c)),
+            Expression::Array(values) => {
+                for value in values {
+                    self.compile_expression(value);
+                }
+                self.emit(Instruction::Array(values.len() as u32));
+            },
+            Expression::VectorLiteral(values) => {
+                for value in values {
+                    self.compile_expression(value);
+                }
+                self.emit(Instruction::Vector(values.len() as u32));
+            },
+            Expression::VariableExtract(..) | Expression::FieldAccess(..) => {
+                self.compile_address(expression);
+                self.emit(Instruction::Load);
+            },
+            Expression::FunctionCall(name, args) if is_builtin(name) => self.compile_builtin(name, args),
+            Expression::FunctionCall(name, args) => {
+                let index = self.function_indices.get(name).copied();
+                if index.is_none() {
+                    // Reported by the vm when the call runs, like the interpreter does
+                    self.constant(Value::Function(name.clone()));
+                }
+                for arg in args {
+                    self.compile_expression(arg);
+                }
+                match index {
+                    Some(index) => self.emit(Instruction::Call(index)),
+                    None => self.emit(Instruction::CallIndirect(args.len() as u32)),
+                };
+            },
+            Expression::IndirectCall(callee, args) => {
+                self.compile_expression(callee);
+                for arg in args {
+                    self.compile_expression(arg);
+                }
+                self.emit(Instruction::CallIndirect(args.len() as u32));
+            },
+            Expression::FunctionReference(name, _) => self.constant(Value::Function(name.clone())),
+            Expression::ExpressionCast(interior, resultant) => {
+                self.compile_expression(interior);
+                let cast = match resultant.as_str() {
+                    "f64" => Cast::Float,
+                    "i64" => Cast::Int,
+                    "char" => Cast::Char,
+                    _ => return self.fail(format!("can't cast to {}", resultant)),
+                };
+                self.emit(Instruction::Cast(cast));
+            },
+            Expression::EnumVariant(name, variant, payload) => {
+                let Some((tag, _)) = self.data_types.get(name).and_then(|enum_type| enum_type.variant(variant)) else {
+                    return self.fail(format!("unknown variant {}.{}", name, variant));
+                };
+                for value in payload {
+                    self.compile_expression(value);
+                }
+                self.emit(Instruction::Variant(tag, payload.len() as u32));
+            },
+        }
+    }
+
+    // Pushes a pointer to where the expression is stored, values that aren't stored anywhere get a temporary
+    fn compile_address(&mut self, expression: &Expression) {
+        match expression {
+            Expression::VariableRead(name) => {
+                let address = match (self.locals.get(name), self.globals.get(name)) {
+                    (Some((slot, _)), _) => Instruction::LocalAddress(*slot),
+                    (None, Some((index, _))) => Instruction::GlobalAddress(*index),
+                    (None, None) => return self.fail(format!("unknown variable {}", name)),
+                };
+                self.emit(address);
+            },
+            Expression::VariableExtract(base, slot) => {
+                self.compile_address(base);
+                self.compile_expression(slot);
+                self.emit(Instruction::Element);
+            },
+            Expression::FieldAccess(base, field) => {
+                let Some((index, _)) = self.place_type(base).as_ref().and_then(|data_type| data_type.field(field)) else {
+                    return self.fail(format!("no field {}", field));
+                };
+                self.compile_address(base);
+                self.emit(Instruction::Field(index as u32));
+            },
+            Expression::Unary(Some(interior), UnaryExpressionType::Dereference) => self.compile_expression(interior),
+            _ => {
+                let temporary = self.temporary();
+                self.compile_expression(expression);
+                self.emit(Instruction::StoreLocal(temporary));
+                self.emit(Instruction::LocalAddress(temporary));
+            },
+        }
+    }
+
+    fn compile_builtin(&mut self, name: &str, args: &[Box<Expression>]) {
+        if name == "string" {
+            let Some(Expression::StringLiteral(value)) = args.first().map(|arg| &**arg) else {
+                return self.fail("string needs a string literal".to_string());
+            };
+            for byte in value.bytes() {
+                self.constant(Value::Char(byte));
+            }
+            self.emit(Instruction::Vector(value.len() as u32));
+            return;
+        }
+        for arg in args {
+            self.compile_expression(arg);
+        }
+        let instruction = match name {
+            "box" => Instruction::Box,
+            "push" => Instruction::Push,
+            "pop" => Instruction::Pop,
+            "len" => Instruction::Len,
+            _ if name.starts_with("wrapping_") || name.starts_with("checked_") => {
+                let (mode, operation) = name.split_once('_').unwrap();
+                let operation = match operation {
+                    "add" => BinaryExpressionType::Addition,
+                    "sub" => BinaryExpressionType::Subtraction,
+                    "mul" => BinaryExpressionType::Multiplication,
+                    "div" => BinaryExpressionType::Division,
+                    _ => unreachable!()
+                };
+                match mode {
+                    "checked" => Instruction::Checked(operation),
+                    _ => Instruction::Binary(operation, false),
+                }
+            },
+            _ => return self.fail(format!("unknown builtin {}", name)),
+        };
+        self.emit(instruction);
+    }
+}
+
+impl<'a> Visitor for Compiler<'a> {
+    type Output = ();
+
+    fn visit_root(&mut self, root: &RootScope) {
+        // Number every function and global up front so they can be used before their definition
+        for item in &root.items {
+            match item {
+                Item::Function(function) => {
+                    let index = self.function_indices.len() as u32;
+                    self.function_indices.insert(function.name.clone(), index);
+                },
+                Item::Global(global) => {
+                    self.globals.insert(global.name.clone(), (self.program.globals.len() as u32, global.data_type.clone()));
+                    self.program.globals.push(global.name.clone());
+                },
+            }
+        }
+        for item in &root.items {
+            if let Item::Function(function) = item {
+                self.visit_function(function);
+            }
+        }
+        self.program.initializer = self.program.functions.len() as u32;
+        self.begin_function("__globals__", &[], false);
+        for item in &root.items {
+            if let Item::Global(global) = item {
+                self.visit_global(global);
+            }
+        }
+        self.end_function();
+    }
+
+    fn visit_function(&mut self, function: &ast::Function) {
+        self.compile_function(function);
+    }
+
+    fn visit_global(&mut self, global: &GlobalVariable) {
+        self.compile_global(global);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.compile_statement(stmt);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        self.compile_expression(expression);
+    }
+}
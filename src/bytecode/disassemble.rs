@@ -0,0 +1,56 @@
+use std::fmt::Write;
+
+use super::{Instruction, Program};
+
+// One block per function, each instruction with its index and the source line it came from
+pub fn disassemble(program: &Program) -> String {
+    let mut out = String::new();
+    for global in &program.globals {
+        writeln!(out, "global {}", global).unwrap();
+    }
+    for function in &program.functions {
+        writeln!(out, "\nfunction {} params={} locals={}", function.name, function.params, function.locals).unwrap();
+        let mut previous = None;
+        for (pc, instruction) in function.code.iter().enumerate() {
+            let line = function.line(pc);
+            let column = match previous {
+                Some(previous) if previous == line => "   |".to_string(),
+                _ => format!("{:4}", line),
+            };
+            previous = Some(line);
+            writeln!(out, "{:04} {} {}", pc, column, describe(program, instruction)).unwrap();
+        }
+    }
+    out
+}
+
+// Operands that index a table are followed by what they refer to
+fn describe(program: &Program, instruction: &Instruction) -> String {
+    match *instruction {
+        Instruction::Constant(index) => format!("{:?} {:?}", instruction, program.constants[index as usize]),
+        Instruction::LoadGlobal(index) | Instruction::StoreGlobal(index) | Instruction::GlobalAddress(index) => {
+            format!("{:?} {}", instruction, program.globals[index as usize])
+        },
+        Instruction::Call(index) => format!("{:?} {}", instruction, program.functions[index as usize].name),
+        _ => format!("{:?}", instruction),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::CompilerOptions, bytecode::compile, parsing::Parser};
+
+    #[test]
+    fn lists_instructions_with_lines() {
+        let source = "total = 1\ndef square(x: i64): i64 {\n    return x * x\n}\ndef main(): i64 {\n    y = square(3)\n    return y + total\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        let listing = disassemble(&compile(&root, &parser.data_types, &CompilerOptions::default()).unwrap());
+        assert!(listing.starts_with("global total\n"));
+        assert!(listing.contains("function square params=1 locals=1\n0000    3 LoadLocal(0)\n0001    | LoadLocal(0)\n"));
+        assert!(listing.contains("Call(0) square"));
+        assert!(listing.contains("LoadGlobal(0) total"));
+        assert!(listing.contains("Constant(0) Int(3)"));
+    }
+}
@@ -0,0 +1,98 @@
+mod compiler;
+mod disassemble;
+mod serialize;
+mod vm;
+
+pub use compiler::compile;
+pub use disassemble::disassemble;
+pub use serialize::{load, save};
+pub use vm::Vm;
+
+use crate::{ast::BinaryExpressionType, interpreter::Value};
+
+// Operands index into the tables of the program or the locals of the running function
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    Constant(u32),
+    LoadLocal(u32),
+    StoreLocal(u32),
+    LoadGlobal(u32),
+    StoreGlobal(u32),
+    // Push a pointer to the variable
+    LocalAddress(u32),
+    GlobalAddress(u32),
+    // Pops an index and a pointer to an array or vector, pushes a pointer to the element
+    Element,
+    // Pops a pointer to a struct, pushes a pointer to the field
+    Field(u32),
+    // Pops a pointer to an array or vector, pushes its number of elements
+    Count,
+    Load,
+    // Pops a pointer then the value stored through it
+    Store,
+    // The flag turns on overflow and shift checks
+    Binary(BinaryExpressionType, bool),
+    // Pops a pointer then the operands, stores the result through the pointer when it fits and pushes whether it did
+    Checked(BinaryExpressionType),
+    Negate,
+    Not,
+    Cast(Cast),
+    // Pop that many values into a new aggregate
+    Array(u32),
+    Vector(u32),
+    Variant(u64, u32),
+    // Pops an enum, pushes its tag or the first values of its payload
+    Tag,
+    Unpack(u32),
+    Box,
+    Push,
+    Pop,
+    Len,
+    // Pops the arguments of a function of the program
+    Call(u32),
+    // Pops the arguments then the function value
+    CallIndirect(u32),
+    Jump(u32),
+    // Pops a condition, jumps when it is zero
+    JumpIfFalse(u32),
+    Discard,
+    Return,
+    ReturnVoid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cast {
+    Int,
+    Float,
+    Char,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    // Parameters are passed in the first locals
+    pub params: u32,
+    pub locals: u32,
+    pub returns_value: bool,
+    pub code: Vec<Instruction>,
+    // Instruction index where each line starts, in order
+    pub lines: Vec<(u32, u32)>,
+}
+
+impl Function {
+    pub fn line(&self, pc: usize) -> usize {
+        let lines = self.lines.iter().take_while(|(start, _)| *start as usize <= pc);
+        lines.last().map_or(0, |(_, line)| *line as usize)
+    }
+}
+
+// A compiled program, what .ssb files hold
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub source_name: String,
+    pub constants: Vec<Value>,
+    pub globals: Vec<String>,
+    pub functions: Vec<Function>,
+    // Function run before main that sets up the globals
+    pub initializer: u32,
+}
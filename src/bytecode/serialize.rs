@@ -0,0 +1,414 @@
+use std::{error::Error, fmt::Display};
+
+use crate::{ast::BinaryExpressionType, interpreter::Value};
+
+use super::{Cast, Function, Instruction, Program};
+
+const MAGIC: &[u8; 4] = b"SSB\0";
+const VERSION: u8 = 1;
+
+// Operators in the order of their encoding
+const OPERATORS: [BinaryExpressionType; 16] = [
+    BinaryExpressionType::Addition,
+    BinaryExpressionType::Subtraction,
+    BinaryExpressionType::Multiplication,
+    BinaryExpressionType::Division,
+    BinaryExpressionType::Modulo,
+    BinaryExpressionType::BitwiseAnd,
+    BinaryExpressionType::BitwiseOr,
+    BinaryExpressionType::BitwiseXor,
+    BinaryExpressionType::ShiftLeft,
+    BinaryExpressionType::ShiftRight,
+    BinaryExpressionType::Equal,
+    BinaryExpressionType::NotEqual,
+    BinaryExpressionType::Less,
+    BinaryExpressionType::LessEqual,
+    BinaryExpressionType::Greater,
+    BinaryExpressionType::GreaterEqual,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    NotBytecode,
+    UnsupportedVersion(u8),
+    Truncated,
+    Malformed(String),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::NotBytecode => write!(f, "Not a bytecode file"),
+            LoadError::UnsupportedVersion(version) => write!(f, "Bytecode version {} is not supported, expected {}", version, VERSION),
+            LoadError::Truncated => write!(f, "Bytecode file is truncated"),
+            LoadError::Malformed(reason) => write!(f, "Malformed bytecode: {}", reason),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+type LoadResult<T> = Result<T, LoadError>;
+
+// Integers are little endian, strings and lists are prefixed with their length
+pub fn save(program: &Program) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u8(VERSION);
+    writer.str(&program.source_name);
+    writer.u32(program.constants.len() as u32);
+    for constant in &program.constants {
+        writer.value(constant);
+    }
+    writer.u32(program.globals.len() as u32);
+    for global in &program.globals {
+        writer.str(global);
+    }
+    writer.u32(program.functions.len() as u32);
+    for function in &program.functions {
+        writer.str(&function.name);
+        writer.u32(function.params);
+        writer.u32(function.locals);
+        writer.u8(function.returns_value as u8);
+        writer.u32(function.code.len() as u32);
+        for instruction in &function.code {
+            writer.instruction(instruction);
+        }
+        writer.u32(function.lines.len() as u32);
+        for (start, line) in &function.lines {
+            writer.u32(*start);
+            writer.u32(*line);
+        }
+    }
+    writer.u32(program.initializer);
+    writer.bytes
+}
+
+pub fn load(bytes: &[u8]) -> LoadResult<Program> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(LoadError::NotBytecode);
+    }
+    let mut reader = Reader { bytes, position: MAGIC.len() };
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    let source_name = reader.str()?;
+    let constants = reader.list(Reader::value)?;
+    let globals = reader.list(Reader::str)?;
+    let functions = reader.list(|reader| {
+        Ok(Function {
+            name: reader.str()?,
+            params: reader.u32()?,
+            locals: reader.u32()?,
+            returns_value: reader.u8()? != 0,
+            code: reader.list(Reader::instruction)?,
+            lines: reader.list(|reader| Ok((reader.u32()?, reader.u32()?)))?,
+        })
+    })?;
+    let program = Program { source_name, constants, globals, functions, initializer: reader.u32()? };
+    if reader.position != bytes.len() {
+        return Err(LoadError::Malformed("trailing bytes".to_string()));
+    }
+    validate(&program)?;
+    Ok(program)
+}
+
+// Operands are checked once here so the vm can index its tables without checks
+fn validate(program: &Program) -> LoadResult<()> {
+    let malformed = |function: &Function, message: String| LoadError::Malformed(format!("{} in {}", message, function.name));
+    if program.initializer as usize >= program.functions.len() {
+        return Err(LoadError::Malformed("missing global initializer".to_string()));
+    }
+    for function in &program.functions {
+        if function.params > function.locals {
+            return Err(malformed(function, "more parameters than locals".to_string()));
+        }
+        for instruction in &function.code {
+            let (operand, limit, table) = match *instruction {
+                Instruction::Constant(index) => (index, program.constants.len(), "constant"),
+                Instruction::LoadLocal(slot) | Instruction::StoreLocal(slot) | Instruction::LocalAddress(slot) => (slot, function.locals as usize, "local"),
+                Instruction::LoadGlobal(index) | Instruction::StoreGlobal(index) | Instruction::GlobalAddress(index) => (index, program.globals.len(), "global"),
+                Instruction::Call(index) => (index, program.functions.len(), "function"),
+                Instruction::Jump(target) | Instruction::JumpIfFalse(target) => (target, function.code.len(), "jump target"),
+                _ => continue,
+            };
+            if operand as usize >= limit {
+                return Err(malformed(function, format!("{} {} out of range", table, operand)));
+            }
+        }
+        if !matches!(function.code.last(), Some(Instruction::Return | Instruction::ReturnVoid)) {
+            return Err(malformed(function, "code runs past the end".to_string()));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn operator(&mut self, operation: &BinaryExpressionType) {
+        self.u8(OPERATORS.iter().position(|operator| operator == operation).unwrap() as u8);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, str: &str) {
+        self.u32(str.len() as u32);
+        self.bytes.extend_from_slice(str.as_bytes());
+    }
+
+    fn values(&mut self, values: &[Value]) {
+        self.u32(values.len() as u32);
+        for value in values {
+            self.value(value);
+        }
+    }
+
+    // Constants never hold pointers or vectors, those only exist while a program runs
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Int(value) => {
+                self.u8(0);
+                self.u64(*value as u64);
+            },
+            Value::Float(value) => {
+                self.u8(1);
+                self.u64(value.to_bits());
+            },
+            Value::Char(value) => {
+                self.u8(2);
+                self.u8(*value);
+            },
+            Value::Bool(value) => {
+                self.u8(3);
+                self.u8(*value as u8);
+            },
+            Value::Array(values) => {
+                self.u8(4);
+                self.values(values);
+            },
+            Value::Struct(values) => {
+                self.u8(5);
+                self.values(values);
+            },
+            Value::Enum(tag, payload) => {
+                self.u8(6);
+                self.u64(*tag);
+                self.values(payload);
+            },
+            Value::Function(name) => {
+                self.u8(7);
+                self.str(name);
+            },
+            Value::Null => self.u8(8),
+            Value::Void => self.u8(9),
+            Value::Pointer(_) | Value::Vector(_) => panic!("{:?} can't be a constant", value),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        let (opcode, operands): (u8, &[u32]) = match *instruction {
+            Instruction::Constant(index) => (0, &[index]),
+            Instruction::LoadLocal(slot) => (1, &[slot]),
+            Instruction::StoreLocal(slot) => (2, &[slot]),
+            Instruction::LoadGlobal(index) => (3, &[index]),
+            Instruction::StoreGlobal(index) => (4, &[index]),
+            Instruction::LocalAddress(slot) => (5, &[slot]),
+            Instruction::GlobalAddress(index) => (6, &[index]),
+            Instruction::Element => (7, &[]),
+            Instruction::Field(index) => (8, &[index]),
+            Instruction::Count => (9, &[]),
+            Instruction::Load => (10, &[]),
+            Instruction::Store => (11, &[]),
+            Instruction::Binary(ref operation, checked) => {
+                self.u8(12);
+                self.operator(operation);
+                self.u8(checked as u8);
+                return;
+            },
+            Instruction::Negate => (13, &[]),
+            Instruction::Not => (14, &[]),
+            Instruction::Cast(cast) => {
+                self.u8(15);
+                self.u8(cast as u8);
+                return;
+            },
+            Instruction::Array(count) => (16, &[count]),
+            Instruction::Vector(count) => (17, &[count]),
+            Instruction::Variant(tag, count) => {
+                self.u8(18);
+                self.u64(tag);
+                self.u32(count);
+                return;
+            },
+            Instruction::Tag => (19, &[]),
+            Instruction::Unpack(count) => (20, &[count]),
+            Instruction::Box => (21, &[]),
+            Instruction::Push => (22, &[]),
+            Instruction::Pop => (23, &[]),
+            Instruction::Len => (24, &[]),
+            Instruction::Call(index) => (25, &[index]),
+            Instruction::CallIndirect(count) => (26, &[count]),
+            Instruction::Jump(target) => (27, &[target]),
+            Instruction::JumpIfFalse(target) => (28, &[target]),
+            Instruction::Discard => (29, &[]),
+            Instruction::Return => (30, &[]),
+            Instruction::ReturnVoid => (31, &[]),
+            Instruction::Checked(ref operation) => {
+                self.u8(32);
+                self.operator(operation);
+                return;
+            },
+        };
+        self.u8(opcode);
+        for operand in operands {
+            self.u32(*operand);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> LoadResult<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position + len).ok_or(LoadError::Truncated)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> LoadResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> LoadResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> LoadResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> LoadResult<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| LoadError::Malformed("invalid utf-8 in a name".to_string()))
+    }
+
+    fn list<T>(&mut self, mut read: impl FnMut(&mut Self) -> LoadResult<T>) -> LoadResult<Vec<T>> {
+        let len = self.u32()?;
+        (0..len).map(|_| read(self)).collect()
+    }
+
+    fn value(&mut self) -> LoadResult<Value> {
+        Ok(match self.u8()? {
+            0 => Value::Int(self.u64()? as i64),
+            1 => Value::Float(f64::from_bits(self.u64()?)),
+            2 => Value::Char(self.u8()?),
+            3 => Value::Bool(self.u8()? != 0),
+            4 => Value::Array(self.list(Reader::value)?),
+            5 => Value::Struct(self.list(Reader::value)?),
+            6 => Value::Enum(self.u64()?, self.list(Reader::value)?),
+            7 => Value::Function(self.str()?),
+            8 => Value::Null,
+            9 => Value::Void,
+            kind => return Err(LoadError::Malformed(format!("unknown value kind {}", kind))),
+        })
+    }
+
+    fn operator(&mut self) -> LoadResult<BinaryExpressionType> {
+        let operator = self.u8()?;
+        OPERATORS.get(operator as usize).cloned().ok_or_else(|| LoadError::Malformed(format!("unknown operator {}", operator)))
+    }
+
+    fn instruction(&mut self) -> LoadResult<Instruction> {
+        Ok(match self.u8()? {
+            0 => Instruction::Constant(self.u32()?),
+            1 => Instruction::LoadLocal(self.u32()?),
+            2 => Instruction::StoreLocal(self.u32()?),
+            3 => Instruction::LoadGlobal(self.u32()?),
+            4 => Instruction::StoreGlobal(self.u32()?),
+            5 => Instruction::LocalAddress(self.u32()?),
+            6 => Instruction::GlobalAddress(self.u32()?),
+            7 => Instruction::Element,
+            8 => Instruction::Field(self.u32()?),
+            9 => Instruction::Count,
+            10 => Instruction::Load,
+            11 => Instruction::Store,
+            12 => Instruction::Binary(self.operator()?, self.u8()? != 0),
+            13 => Instruction::Negate,
+            14 => Instruction::Not,
+            15 => Instruction::Cast(match self.u8()? {
+                0 => Cast::Int,
+                1 => Cast::Float,
+                2 => Cast::Char,
+                cast => return Err(LoadError::Malformed(format!("unknown cast {}", cast))),
+            }),
+            16 => Instruction::Array(self.u32()?),
+            17 => Instruction::Vector(self.u32()?),
+            18 => Instruction::Variant(self.u64()?, self.u32()?),
+            19 => Instruction::Tag,
+            20 => Instruction::Unpack(self.u32()?),
+            21 => Instruction::Box,
+            22 => Instruction::Push,
+            23 => Instruction::Pop,
+            24 => Instruction::Len,
+            25 => Instruction::Call(self.u32()?),
+            26 => Instruction::CallIndirect(self.u32()?),
+            27 => Instruction::Jump(self.u32()?),
+            28 => Instruction::JumpIfFalse(self.u32()?),
+            29 => Instruction::Discard,
+            30 => Instruction::Return,
+            31 => Instruction::ReturnVoid,
+            32 => Instruction::Checked(self.operator()?),
+            opcode => return Err(LoadError::Malformed(format!("unknown opcode {}", opcode))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::CompilerOptions, bytecode::{Vm, compile}, conformance, parsing::Parser};
+
+    #[test]
+    fn round_trips_programs() {
+        for (name, source, expected) in conformance::PROGRAMS {
+            let mut parser = conformance::parser(source);
+            let root = parser.parse().unwrap();
+            let program = compile(&root, &parser.data_types, &CompilerOptions::default()).unwrap();
+            let loaded = load(&save(&program)).unwrap();
+            assert_eq!(loaded, program, "{}", name);
+            assert_eq!(Vm::new(&loaded).unwrap().call("main", Vec::new()).unwrap(), Some(Value::Int(*expected)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert_eq!(load(b"def main(): i64 {").err(), Some(LoadError::NotBytecode));
+        let mut parser = Parser::new("def main(): i64 {\n    return 1\n}\n".to_string());
+        let root = parser.parse().unwrap();
+        let bytes = save(&compile(&root, &parser.data_types, &CompilerOptions::default()).unwrap());
+        assert_eq!(load(&bytes[..bytes.len() - 2]).err(), Some(LoadError::Truncated));
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = VERSION + 1;
+        assert_eq!(load(&newer).err(), Some(LoadError::UnsupportedVersion(VERSION + 1)));
+    }
+}
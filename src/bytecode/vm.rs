@@ -0,0 +1,340 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::interpreter::{InterpretResult, Pointer, RuntimeError, Value, binary, signed, unsigned};
+
+use super::{Cast, Instruction, Program};
+
+struct Frame {
+    function: usize,
+    pc: usize,
+    locals: Vec<Rc<RefCell<Value>>>,
+}
+
+// Runs compiled programs, values and runtime checks are the same as the interpreter's
+pub struct Vm<'a> {
+    program: &'a Program,
+    functions: HashMap<&'a str, usize>,
+    globals: Vec<Rc<RefCell<Value>>>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(program: &'a Program) -> InterpretResult<Self> {
+        let mut vm = Self {
+            program,
+            functions: program.functions.iter().enumerate().map(|(index, function)| (function.name.as_str(), index)).collect(),
+            globals: program.globals.iter().map(|_| Rc::new(RefCell::new(Value::Null))).collect(),
+            stack: Vec::new(),
+            frames: Vec::new(),
+        };
+        vm.invoke(program.initializer as usize, Vec::new())?;
+        Ok(vm)
+    }
+
+    // None for functions without a return type
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> InterpretResult<Option<Value>> {
+        let Some(index) = self.functions.get(name).copied() else {
+            return Err(self.error(&format!("unknown function {}", name)));
+        };
+        self.invoke(index, args)
+    }
+
+    fn invoke(&mut self, function: usize, args: Vec<Value>) -> InterpretResult<Option<Value>> {
+        let depth = self.frames.len();
+        self.push_frame(function, args);
+        match self.run(depth) {
+            Ok(value) => Ok(self.program.functions[function].returns_value.then_some(value)),
+            Err(error) => {
+                self.frames.truncate(depth);
+                self.stack.clear();
+                Err(error)
+            },
+        }
+    }
+
+    fn push_frame(&mut self, function: usize, args: Vec<Value>) {
+        let mut args = args.into_iter();
+        let locals = (0..self.program.functions[function].locals)
+            .map(|_| Rc::new(RefCell::new(args.next().unwrap_or(Value::Null))))
+            .collect();
+        self.frames.push(Frame { function, pc: 0, locals });
+    }
+
+    // Runs until the frame at depth returns
+    fn run(&mut self, depth: usize) -> InterpretResult<Value> {
+        let program = self.program;
+        loop {
+            let current = self.frames.len() - 1;
+            let frame = &mut self.frames[current];
+            let function = &program.functions[frame.function];
+            let instruction = &function.code[frame.pc];
+            frame.pc += 1;
+            match *instruction {
+                Instruction::Constant(index) => self.stack.push(program.constants[index as usize].clone()),
+                Instruction::LoadLocal(slot) => {
+                    let value = self.frames[current].locals[slot as usize].borrow().clone();
+                    self.stack.push(value);
+                },
+                Instruction::StoreLocal(slot) => {
+                    let value = self.pop();
+                    *self.frames[current].locals[slot as usize].borrow_mut() = value;
+                },
+                Instruction::LoadGlobal(index) => {
+                    let value = self.globals[index as usize].borrow().clone();
+                    self.stack.push(value);
+                },
+                Instruction::StoreGlobal(index) => {
+                    let value = self.pop();
+                    *self.globals[index as usize].borrow_mut() = value;
+                },
+                Instruction::LocalAddress(slot) => {
+                    let pointer = Pointer::new(self.frames[current].locals[slot as usize].clone());
+                    self.stack.push(Value::Pointer(pointer));
+                },
+                Instruction::GlobalAddress(index) => self.stack.push(Value::Pointer(Pointer::new(self.globals[index as usize].clone()))),
+                // Out of bounds accesses are checked even with --release, there is no memory to read past
+                Instruction::Element => {
+                    let index = self.pop();
+                    let elements = self.elements()?;
+                    match index.as_integer() {
+                        Some((bits, width)) if (unsigned(bits, width) as usize) < elements.element_count() => {
+                            self.stack.push(Value::Pointer(elements.element(unsigned(bits, width) as usize)));
+                        },
+                        _ => return Err(self.error("index out of bounds")),
+                    }
+                },
+                Instruction::Field(index) => {
+                    let pointer = self.pointer()?;
+                    self.stack.push(Value::Pointer(pointer.element(index as usize)));
+                },
+                Instruction::Count => {
+                    let elements = self.elements()?;
+                    self.stack.push(Value::Int(elements.element_count() as i64));
+                },
+                Instruction::Load => {
+                    let value = self.pointer()?.load();
+                    self.stack.push(value);
+                },
+                Instruction::Store => {
+                    let pointer = self.pointer()?;
+                    pointer.store(self.pop());
+                },
+                Instruction::Binary(ref operation, checked) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = binary(operation, left, right, checked).map_err(|message| self.error(&message))?;
+                    self.stack.push(value);
+                },
+                Instruction::Checked(ref operation) => {
+                    let out = self.pointer()?;
+                    let right = self.pop();
+                    let left = self.pop();
+                    let fits = match binary(operation, left, right, true) {
+                        Ok(value) => {
+                            out.store(value);
+                            true
+                        },
+                        Err(_) => false,
+                    };
+                    self.stack.push(Value::Bool(fits));
+                },
+                Instruction::Negate => {
+                    let value = match self.pop() {
+                        Value::Float(value) => Value::Float(-value),
+                        value => match value.as_integer() {
+                            Some((bits, width)) => Value::from_integer(bits.wrapping_neg(), width),
+                            None => return Err(self.error("- needs a number")),
+                        },
+                    };
+                    self.stack.push(value);
+                },
+                Instruction::Not => match self.pop().as_integer() {
+                    Some((bits, width)) => self.stack.push(Value::from_integer(!bits, width)),
+                    None => return Err(self.error("~ needs an integer")),
+                },
+                Instruction::Cast(cast) => {
+                    let Some((bits, width)) = self.pop().as_integer() else {
+                        return Err(self.error("only integers can be cast"));
+                    };
+                    self.stack.push(match cast {
+                        Cast::Float => Value::Float(signed(bits, width) as f64),
+                        Cast::Int => Value::Int(signed(bits, width)),
+                        Cast::Char => Value::Char(bits as u8),
+                    });
+                },
+                Instruction::Array(count) => {
+                    let values = self.pop_many(count);
+                    self.stack.push(Value::Array(values));
+                },
+                Instruction::Vector(count) => {
+                    let values = self.pop_many(count);
+                    self.stack.push(Value::vector(values));
+                },
+                Instruction::Variant(tag, count) => {
+                    let payload = self.pop_many(count);
+                    self.stack.push(Value::Enum(tag, payload));
+                },
+                Instruction::Tag => match self.pop() {
+                    Value::Enum(tag, _) => self.stack.push(Value::Int(tag as i64)),
+                    _ => return Err(self.error("match needs an enum")),
+                },
+                Instruction::Unpack(count) => match self.pop() {
+                    Value::Enum(_, payload) => self.stack.extend(payload.into_iter().take(count as usize)),
+                    _ => return Err(self.error("match needs an enum")),
+                },
+                Instruction::Box => {
+                    let value = self.pop();
+                    self.stack.push(Value::Pointer(Pointer::new(Rc::new(RefCell::new(value)))));
+                },
+                Instruction::Push => {
+                    let value = self.pop();
+                    let handle = self.vector()?;
+                    handle.borrow_mut().elements_mut().push(value);
+                    self.stack.push(Value::Void);
+                },
+                Instruction::Pop => {
+                    let handle = self.vector()?;
+                    let value = handle.borrow_mut().elements_mut().pop();
+                    self.stack.push(value.ok_or_else(|| self.error("Popped from an empty vector"))?);
+                },
+                Instruction::Len => {
+                    let len = match self.pop() {
+                        Value::Array(elements) => elements.len(),
+                        Value::Vector(handle) => handle.borrow().elements().len(),
+                        _ => return Err(self.error("len needs an array or vector")),
+                    };
+                    self.stack.push(Value::Int(len as i64));
+                },
+                Instruction::Call(index) => {
+                    let args = self.pop_many(program.functions[index as usize].params);
+                    self.push_frame(index as usize, args);
+                },
+                Instruction::CallIndirect(count) => {
+                    let args = self.pop_many(count);
+                    let Value::Function(name) = self.pop() else {
+                        return Err(self.error("called a null function pointer"));
+                    };
+                    let Some(index) = self.functions.get(name.as_str()).copied() else {
+                        return Err(self.error(&format!("unknown function {}", name)));
+                    };
+                    self.push_frame(index, args);
+                },
+                Instruction::Jump(target) => self.frames[current].pc = target as usize,
+                Instruction::JumpIfFalse(target) => {
+                    let Some((bits, _)) = self.pop().as_integer() else {
+                        return Err(self.error("if needs a condition"));
+                    };
+                    if bits == 0 {
+                        self.frames[current].pc = target as usize;
+                    }
+                },
+                Instruction::Discard => {
+                    self.pop();
+                },
+                Instruction::Return | Instruction::ReturnVoid => {
+                    let value = match instruction {
+                        Instruction::Return => self.pop(),
+                        _ if function.returns_value => return Err(self.error(&format!("{} ended without returning a value", function.name))),
+                        _ => Value::Void,
+                    };
+                    self.frames.pop();
+                    if self.frames.len() == depth {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                },
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("malformed bytecode, popped an empty stack")
+    }
+
+    fn pop_many(&mut self, count: u32) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - count as usize)
+    }
+
+    fn pointer(&mut self) -> InterpretResult<Pointer> {
+        match self.pop() {
+            Value::Pointer(pointer) => Ok(pointer),
+            _ => Err(self.error("null pointer dereference")),
+        }
+    }
+
+    // Pointer to an array, the elements of a vector are reached through its handle
+    fn elements(&mut self) -> InterpretResult<Pointer> {
+        let pointer = self.pointer()?;
+        let handle = pointer.read(|value| match value {
+            Value::Vector(handle) => Some(handle.clone()),
+            _ => None,
+        });
+        Ok(handle.map_or(pointer, Pointer::new))
+    }
+
+    fn vector(&mut self) -> InterpretResult<Rc<RefCell<Value>>> {
+        match self.pop() {
+            Value::Vector(handle) => Ok(handle),
+            _ => Err(self.error("null pointer dereference")),
+        }
+    }
+
+    // Reported at the line of the instruction that is running
+    fn error(&self, message: &str) -> RuntimeError {
+        let line = self.frames.last().map_or(0, |frame| self.program.functions[frame.function].line(frame.pc.saturating_sub(1)));
+        RuntimeError {
+            file: self.program.source_name.clone(),
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::CompilerOptions, bytecode::compile, conformance};
+
+    fn run(source: &str, options: CompilerOptions) -> InterpretResult<Option<Value>> {
+        let mut parser = conformance::parser(source);
+        let root = parser.parse().unwrap();
+        let program = compile(&root, &parser.data_types, &options).unwrap();
+        Vm::new(&program)?.call("main", Vec::new())
+    }
+
+    #[test]
+    fn passes_conformance_suite() {
+        for (name, source, expected) in conformance::PROGRAMS {
+            let result = run(source, CompilerOptions::default());
+            assert_eq!(result.unwrap(), Some(Value::Int(*expected)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn restores_shadowed_variables() {
+        let source = "enum Shape {\n    Rect(i64, i64)\n}\ndef main(): i64 {\n    v = 5\n    for v in [1, 2] {\n        v = v\n    }\n    x = 3\n    match Shape.Rect(1, 2) {\n        Rect(x, y) {\n            v += x\n        }\n    }\n    return v * 10 + x\n}\n";
+        assert_eq!(run(source, CompilerOptions::default()).unwrap(), Some(Value::Int(63)));
+    }
+
+    #[test]
+    fn reports_compile_errors_with_their_line() {
+        let source = "def main(): i64 {\n    x = 1\n    return Point(x)\n}\n";
+        let mut parser = conformance::parser(source);
+        let root = parser.parse().unwrap();
+        let error = compile(&root, &parser.data_types, &CompilerOptions::default()).err().unwrap();
+        assert_eq!(error.to_string(), "main:3: can't cast to Point");
+    }
+
+    #[test]
+    fn reports_runtime_checks_with_their_line() {
+        let source = "def get(values: [i64:3], i: i64): i64 {\n    return values[i]\n}\ndef main(): i64 {\n    return get([1, 2, 3], 3)\n}\n";
+        let error = run(source, CompilerOptions::default()).err().unwrap();
+        assert_eq!(error.to_string(), "main:2: index out of bounds");
+
+        let source = "def main(): i64 {\n    x = 9223372036854775807\n    return x + 1\n}\n";
+        assert_eq!(run(source, CompilerOptions::default()).unwrap(), Some(Value::Int(i64::MIN)));
+        let options = CompilerOptions { overflow_checks: true, ..Default::default() };
+        assert_eq!(run(source, options).err().unwrap().message, "integer overflow");
+    }
+}
@@ -0,0 +1,211 @@
+use std::{cell::RefCell, path::Path};
+
+use inkwell::{context::Context, debug_info::{AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants, DISubprogram, DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder}, module::{FlagBehavior, Module}, targets::TargetData, types::StructType, values::{FunctionValue, PointerValue}, AddressSpace};
+
+use crate::{ast::{CompilerOptions, DataType, DataTypeEnum, OptimizationLevel}, mir::Body};
+
+use super::Compiler;
+
+// DWARF base type encodings
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_UNSIGNED_CHAR: u32 = 0x08;
+
+// Debug metadata of one module, functions are described as they're translated
+pub struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    // Subprogram of the body being translated
+    scope: RefCell<Option<DISubprogram<'ctx>>>,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    pub fn new(context: &'ctx Context, module: &Module<'ctx>, options: &CompilerOptions) -> Self {
+        let path = Path::new(&options.source_name);
+        let file_name = path.file_name().map_or(options.source_name.clone(), |name| name.to_string_lossy().to_string());
+        let directory = path.parent().map_or(String::new(), |parent| parent.to_string_lossy().to_string());
+        // Without the version flag llvm drops the metadata when the module is verified
+        module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, context.i32_type().const_int(3, false));
+        module.add_basic_value_flag("Dwarf Version", FlagBehavior::Warning, context.i32_type().const_int(4, false));
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            // Values are laid out like C, so debuggers print them correctly
+            DWARFSourceLanguage::C,
+            &file_name,
+            &directory,
+            env!("CARGO_PKG_NAME"),
+            options.optimization != OptimizationLevel::None,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        Self { builder, unit, scope: RefCell::new(None) }
+    }
+}
+
+impl<'ctx> Compiler<'ctx> {
+    // Attaches a subprogram to the function, instructions built afterwards are located in it
+    pub fn begin_debug_scope(&self, fn_value: FunctionValue<'ctx>, body: &Body) {
+        let Some(ref debug_info) = self.debug_info else {
+            return;
+        };
+        let params: Vec<DIType> = body.locals.iter().take(body.params as usize).map(|decl| self.debug_type(&decl.data_type)).collect();
+        let return_type = body.return_type.as_ref().map(|data_type| self.debug_type(data_type));
+        let file = debug_info.unit.get_file();
+        let subroutine = debug_info.builder.create_subroutine_type(file, return_type, &params, DIFlags::PUBLIC);
+        let line = body.line as u32;
+        let optimized = self.options.optimization != OptimizationLevel::None;
+        let subprogram = debug_info.builder.create_function(
+            debug_info.unit.as_debug_info_scope(), &body.name, None, file, line, subroutine, false, true, line, DIFlags::PUBLIC, optimized,
+        );
+        fn_value.set_subprogram(subprogram);
+        debug_info.scope.replace(Some(subprogram));
+        self.set_debug_line(body.line);
+    }
+
+    // Named locals become variables a debugger can print, temporaries stay hidden
+    pub fn declare_debug_locals(&self, body: &Body, locals: &[PointerValue<'ctx>]) {
+        let Some(ref debug_info) = self.debug_info else {
+            return;
+        };
+        let scope = debug_info.scope.borrow().unwrap().as_debug_info_scope();
+        let file = debug_info.unit.get_file();
+        let block = self.builder.get_insert_block().unwrap();
+        for (index, (decl, storage)) in body.locals.iter().zip(locals).enumerate() {
+            let Some(ref name) = decl.name else {
+                continue;
+            };
+            let data_type = self.debug_type(&decl.data_type);
+            let line = decl.line as u32;
+            let variable = if index < body.params as usize {
+                debug_info.builder.create_parameter_variable(scope, name, index as u32 + 1, file, line, data_type, true, DIFlags::PUBLIC)
+            } else {
+                debug_info.builder.create_auto_variable(scope, name, file, line, data_type, true, DIFlags::PUBLIC, 0)
+            };
+            let location = debug_info.builder.create_debug_location(self.context, line, 0, scope, None);
+            debug_info.builder.insert_declare_at_end(*storage, Some(variable), None, location, block);
+        }
+    }
+
+    pub fn set_debug_line(&self, line: usize) {
+        let Some(ref debug_info) = self.debug_info else {
+            return;
+        };
+        let Some(scope) = *debug_info.scope.borrow() else {
+            return;
+        };
+        let location = debug_info.builder.create_debug_location(self.context, line as u32, 0, scope.as_debug_info_scope(), None);
+        self.builder.set_current_debug_location(self.context, location);
+    }
+
+    // Resolves the metadata, has to run before the module is verified or emitted
+    pub fn finalize_debug_info(&self) {
+        if let Some(ref debug_info) = self.debug_info {
+            debug_info.builder.finalize();
+        }
+    }
+
+    fn debug_type(&self, data_type: &DataType) -> DIType<'ctx> {
+        let debug_info = self.debug_info.as_ref().unwrap();
+        let llvm_type = self.llvm_type(data_type);
+        let target_data = TargetData::create(self.module.get_data_layout().as_str().to_str().unwrap());
+        let size = target_data.get_abi_size(&llvm_type) * 8;
+        let align = target_data.get_abi_alignment(&llvm_type) * 8;
+        match data_type.value {
+            DataTypeEnum::Primitive => {
+                let encoding = match data_type.symbol.as_str() {
+                    "f64" => DW_ATE_FLOAT,
+                    "bool" => DW_ATE_BOOLEAN,
+                    "char" => DW_ATE_UNSIGNED_CHAR,
+                    _ => DW_ATE_SIGNED,
+                };
+                debug_info.builder.create_basic_type(&data_type.symbol, size, encoding, DIFlags::PUBLIC).unwrap().as_type()
+            },
+            DataTypeEnum::Array(ref interior, len) => {
+                debug_info.builder.create_array_type(self.debug_type(interior), size, align, &[0..len as i64]).as_type()
+            },
+            DataTypeEnum::Pointer(ref interior) | DataTypeEnum::Heap(ref interior) => {
+                debug_info.builder.create_pointer_type(&data_type.symbol, self.debug_type(interior), size, align, AddressSpace::default()).as_type()
+            },
+            // Vector handles and function pointers are opaque to the debugger
+            DataTypeEnum::Vector(_) | DataTypeEnum::Function(..) => {
+                let pointee = self.debug_type(&DataType::primitive("char"));
+                debug_info.builder.create_pointer_type(&data_type.symbol, pointee, size, align, AddressSpace::default()).as_type()
+            },
+            DataTypeEnum::Struct(ref fields, ref names) => {
+                let members: Vec<(String, DataType)> = fields.iter().enumerate().map(|(index, field)| {
+                    let name = names.iter().find(|(_, position)| **position == index as u64).map(|(name, _)| name.clone());
+                    (name.unwrap_or_default(), (**field).clone())
+                }).collect();
+                self.debug_struct(data_type, llvm_type.into_struct_type(), &members, &target_data)
+            },
+            // The payload is shown as raw words, which variant it holds depends on the tag
+            DataTypeEnum::Enum(_) => {
+                let struct_type = llvm_type.into_struct_type();
+                let words = struct_type.get_field_type_at_index(1).unwrap().into_array_type().len();
+                let i64_type = DataType::primitive("i64");
+                let members = [("tag".to_string(), i64_type.clone()), ("payload".to_string(), DataType::array(i64_type, words as u64))];
+                self.debug_struct(data_type, struct_type, &members, &target_data)
+            },
+        }
+    }
+
+    fn debug_struct(&self, data_type: &DataType, struct_type: StructType<'ctx>, members: &[(String, DataType)], target_data: &TargetData) -> DIType<'ctx> {
+        let debug_info = self.debug_info.as_ref().unwrap();
+        let file = debug_info.unit.get_file();
+        let scope = debug_info.unit.as_debug_info_scope();
+        let elements: Vec<DIType> = members.iter().enumerate().map(|(index, (name, member))| {
+            let member_type = self.llvm_type(member);
+            let offset = target_data.offset_of_element(&struct_type, index as u32).unwrap() * 8;
+            let size = target_data.get_abi_size(&member_type) * 8;
+            let align = target_data.get_abi_alignment(&member_type) * 8;
+            debug_info.builder.create_member_type(scope, name, file, 0, size, align, offset, DIFlags::PUBLIC, self.debug_type(member)).as_type()
+        }).collect();
+        let size = target_data.get_abi_size(&struct_type) * 8;
+        let align = target_data.get_abi_alignment(&struct_type) * 8;
+        debug_info.builder.create_struct_type(scope, &data_type.symbol, file, 0, size, align, DIFlags::PUBLIC, None, &elements, 0, None, &data_type.symbol).as_type()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{conformance, parsing::Parser};
+
+    #[test]
+    fn describes_functions_variables_and_lines() {
+        let source = "def square(n: i64): i64 {\n    result = n * n\n    return result\n}\ndef main(): i64 {\n    x = square(3)\n    return x\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        let context = Context::create();
+        let options = CompilerOptions { source_name: "test/square.ss".to_string(), debug_info: true, ..Default::default() };
+        let compiler = Compiler::new(&context, context.create_module("main"), parser.data_types.clone(), options);
+        compiler.compile_root(&root);
+        compiler.module.verify().unwrap();
+        let ir = compiler.module.print_to_string().to_string();
+        for expected in [
+            r#"!DIFile(filename: "square.ss", directory: "test")"#,
+            r#"!DISubprogram(name: "square", scope: "#,
+            r#"!DILocalVariable(name: "n", arg: 1, "#,
+            r#"!DILocalVariable(name: "result", "#,
+            "!DILocation(line: 6,",
+        ] {
+            assert!(ir.contains(expected), "{} missing from\n{}", expected, ir);
+        }
+    }
+
+    #[test]
+    fn conformance_programs_run_with_debug_info() {
+        for (name, source, expected) in conformance::PROGRAMS {
+            let options = CompilerOptions { debug_info: true, ..Default::default() };
+            assert_eq!(conformance::run_llvm(source, options), *expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use inkwell::{values::{ArrayValue, IntValue, PointerValue, BasicValueEnum}, types::{BasicType, BasicTypeEnum}, AddressSpace, IntPredicate, FloatPredicate};
+
+use crate::{ast::{BinaryExpressionType, DataType, DataTypeEnum}, mir::{Body, Constant, Operand, Place, Projection, Root, Rvalue, UnaryOperation}};
+
+use super::Compiler;
+
+impl<'ctx> Compiler<'ctx> {
+    pub fn compile_operand(&self, body: &Body, operand: &Operand) -> BasicValueEnum<'ctx> {
+        match operand {
+            Operand::Copy(place) => self.builder.build_load(self.place_address(body, place), "__tmp__"),
+            Operand::Constant(constant) => self.compile_constant(constant),
+        }
+    }
+
+    pub fn compile_constant(&self, constant: &Constant) -> BasicValueEnum<'ctx> {
+        match constant {
+            Constant::Int(value) => self.context.i64_type().const_int(*value as u64, true).into(),
+            Constant::Float(value) => self.context.f64_type().const_float(*value).into(),
+            Constant::Char(value) => self.context.i8_type().const_int(*value as u64, false).into(),
+            Constant::Str(value) => {
+                let bytes: Vec<_> = value.as_bytes().iter().map(|v| self.context.i8_type().const_int(*v as u64, false)).collect();
+                self.context.i8_type().const_array(&bytes).into()
+            },
+            Constant::Array(values, data_type) => {
+                let element_type = self.llvm_type(data_type.element_type().unwrap());
+                let values: Vec<BasicValueEnum> = values.iter().map(|value| self.compile_constant(value)).collect();
+                self.const_array(element_type, &values).into()
+            },
+            Constant::Function(name, _) => self.module.get_function(name).unwrap().as_global_value().as_pointer_value().into(),
+            Constant::Zero(data_type) => self.zero(data_type),
+        }
+    }
+
+    pub fn zero(&self, data_type: &DataType) -> BasicValueEnum<'ctx> {
+        match self.llvm_type(data_type) {
+            BasicTypeEnum::ArrayType(array) => array.const_zero().into(),
+            BasicTypeEnum::FloatType(float) => float.const_zero().into(),
+            BasicTypeEnum::IntType(int) => int.const_zero().into(),
+            BasicTypeEnum::PointerType(pointer) => pointer.const_null().into(),
+            BasicTypeEnum::StructType(structure) => structure.const_zero().into(),
+            BasicTypeEnum::VectorType(vector) => vector.const_zero().into(),
+        }
+    }
+
+    fn const_array(&self, element_type: BasicTypeEnum<'ctx>, values: &[BasicValueEnum<'ctx>]) -> ArrayValue<'ctx> {
+        match element_type {
+            BasicTypeEnum::ArrayType(array) => array.const_array(&values.iter().map(|v| v.into_array_value()).collect::<Vec<_>>()),
+            BasicTypeEnum::FloatType(float) => float.const_array(&values.iter().map(|v| v.into_float_value()).collect::<Vec<_>>()),
+            BasicTypeEnum::IntType(int) => int.const_array(&values.iter().map(|v| v.into_int_value()).collect::<Vec<_>>()),
+            BasicTypeEnum::PointerType(pointer) => pointer.const_array(&values.iter().map(|v| v.into_pointer_value()).collect::<Vec<_>>()),
+            BasicTypeEnum::StructType(structure) => structure.const_array(&values.iter().map(|v| v.into_struct_value()).collect::<Vec<_>>()),
+            BasicTypeEnum::VectorType(vector) => vector.const_array(&values.iter().map(|v| v.into_vector_value()).collect::<Vec<_>>()),
+        }
+    }
+
+    // Address of a place, projections are applied from the root outwards
+    pub fn place_address(&self, body: &Body, place: &Place) -> PointerValue<'ctx> {
+        let (mut address, mut data_type) = match place.root {
+            Root::Local(local) => (self.locals.borrow()[local.index()], body.locals[local.index()].data_type.clone()),
+            Root::Global(ref name, ref data_type) => (self.module.get_global(name).unwrap().as_pointer_value(), data_type.clone()),
+        };
+        for projection in &place.projections {
+            address = match projection {
+                Projection::Deref => self.builder.build_load(address, "__tmp__").into_pointer_value(),
+                Projection::Field(index) => self.builder.build_struct_gep(address, *index, "__tmp__").unwrap(),
+                Projection::Index(local) => {
+                    let index = self.builder.build_load(self.locals.borrow()[local.index()], "__tmp__").into_int_value();
+                    if let DataTypeEnum::Vector(ref element) = data_type.value {
+                        let handle = self.builder.build_load(address, "__tmp__").into_pointer_value();
+                        let elements = self.build_vector_data(handle, self.llvm_type(element));
+                        unsafe { self.builder.build_gep(elements, &[index], "__tmp__") }
+                    } else {
+                        unsafe { self.builder.build_gep(address, &[self.context.i64_type().const_zero(), index], "__tmp__") }
+                    }
+                },
+                Projection::VariantField(tag, index) => {
+                    let fields = self.build_enum_payload(address, &data_type, *tag);
+                    self.builder.build_struct_gep(fields, *index, "__tmp__").unwrap()
+                },
+            };
+            data_type = projection.apply(&data_type).unwrap();
+        }
+        address
+    }
+
+    // Value of an rvalue of the given type
+    pub fn compile_rvalue(&self, body: &Body, rvalue: &Rvalue, data_type: &DataType) -> BasicValueEnum<'ctx> {
+        match rvalue {
+            Rvalue::Use(operand) => self.compile_operand(body, operand),
+            Rvalue::Binary(binary_type, left, right, checked) => {
+                let unsigned = left.data_type(body).is_some_and(|data_type| data_type.is_unsigned());
+                let left = self.compile_operand(body, left);
+                let right = self.compile_operand(body, right);
+                self.build_binary(binary_type, left, right, unsigned, *checked)
+            },
+            Rvalue::Fits(binary_type, left, right) => {
+                let unsigned = left.data_type(body).is_some_and(|data_type| data_type.is_unsigned());
+                let left = self.compile_operand(body, left).into_int_value();
+                let right = self.compile_operand(body, right).into_int_value();
+                self.build_fits(binary_type, left, right, unsigned).into()
+            },
+            Rvalue::Unary(UnaryOperation::Not, operand) => {
+                let value = self.compile_operand(body, operand).into_int_value();
+                self.builder.build_not(value, "__tmp__").into()
+            },
+            Rvalue::Unary(UnaryOperation::Negate, operand) => match self.compile_operand(body, operand) {
+                BasicValueEnum::FloatValue(value) => self.builder.build_float_neg(value, "__tmp__").into(),
+                value => self.builder.build_int_neg(value.into_int_value(), "__tmp__").into(),
+            },
+            Rvalue::Ref(place) => self.place_address(body, place).into(),
+            Rvalue::Cast(operand, resultant) => {
+                let integer = self.compile_operand(body, operand).into_int_value();
+                self.build_cast(integer, &resultant.symbol)
+            },
+            Rvalue::Array(values) => {
+                let mut array = self.llvm_type(data_type).into_array_type().get_undef();
+                for (index, value) in values.iter().enumerate() {
+                    let value = self.compile_operand(body, value);
+                    array = self.builder.build_insert_value(array, value, index as u32, "__tmp__").unwrap().into_array_value();
+                }
+                array.into()
+            },
+            Rvalue::Vector(_, values) => {
+                let handle = self.builder.build_call(self.runtime_function("ss_vec_new"), &[], "__tmp__")
+                    .try_as_basic_value().left().unwrap().into_pointer_value();
+                for value in values {
+                    let value = self.compile_operand(body, value);
+                    self.build_vector_push(handle, value);
+                }
+                handle.into()
+            },
+            Rvalue::Variant(enum_type, tag, values) => {
+                let slot = self.build_entry_alloca(self.llvm_type(enum_type), "__tmp__");
+                let tag_location = self.builder.build_struct_gep(slot, 0, "__tmp__").unwrap();
+                self.builder.build_store(tag_location, self.context.i64_type().const_int(*tag, false));
+                let fields = self.build_enum_payload(slot, enum_type, *tag);
+                for (index, value) in values.iter().enumerate() {
+                    let value = self.compile_operand(body, value);
+                    let field = self.builder.build_struct_gep(fields, index as u32, "__tmp__").unwrap();
+                    self.builder.build_store(field, value);
+                }
+                self.builder.build_load(slot, "__tmp__")
+            },
+            Rvalue::Box(operand) => {
+                let value = self.compile_operand(body, operand);
+                let value_type = value.get_type();
+                let size = value_type.size_of().unwrap();
+                let counted = operand.data_type(body).is_some_and(|data_type| data_type.is_counted());
+                let allocate = self.runtime_function(if counted { "ss_alloc_owner" } else { "ss_alloc" });
+                let raw = self.builder.build_call(allocate, &[size.into()], "__tmp__")
+                    .try_as_basic_value().left().unwrap().into_pointer_value();
+                let allocation = self.builder.build_pointer_cast(raw, value_type.ptr_type(AddressSpace::default()), "__tmp__");
+                self.builder.build_store(allocation, value);
+                allocation.into()
+            },
+            Rvalue::Len(operand) => {
+                if let Some(DataTypeEnum::Array(_, len)) = operand.data_type(body).map(|dt| dt.value) {
+                    return self.context.i64_type().const_int(len, false).into();
+                }
+                let handle = self.compile_operand(body, operand).into_pointer_value();
+                self.builder.build_call(self.runtime_function("ss_vec_len"), &[handle.into()], "__tmp__")
+                    .try_as_basic_value().left().unwrap()
+            },
+            Rvalue::Pop(operand) => {
+                let element_type = self.llvm_type(data_type);
+                let handle = self.compile_operand(body, operand).into_pointer_value();
+                let slot = self.build_entry_alloca(element_type, "__tmp__");
+                let raw = self.builder.build_pointer_cast(slot, self.context.i8_type().ptr_type(AddressSpace::default()), "__tmp__");
+                let size = element_type.size_of().unwrap();
+                let (file, line) = self.build_location();
+                self.builder.build_call(self.runtime_function("ss_vec_pop"), &[handle.into(), raw.into(), size.into(), file.into(), line.into()], "");
+                self.builder.build_load(slot, "__tmp__")
+            },
+            Rvalue::Tag(operand) => {
+                let value = self.compile_operand(body, operand).into_struct_value();
+                self.builder.build_extract_value(value, 0, "__tmp__").unwrap()
+            },
+        }
+    }
+
+    fn build_checked_int_operation(&self, binary_type: &BinaryExpressionType, left: IntValue<'ctx>, right: IntValue<'ctx>, unsigned: bool) -> IntValue<'ctx> {
+        match binary_type {
+            BinaryExpressionType::Division | BinaryExpressionType::Modulo => {
+                self.build_division_check(left, right, !unsigned);
+                return match (binary_type, unsigned) {
+                    (BinaryExpressionType::Division, false) => self.builder.build_int_signed_div(left, right, "__tmp__"),
+                    (BinaryExpressionType::Division, true) => self.builder.build_int_unsigned_div(left, right, "__tmp__"),
+                    (_, false) => self.builder.build_int_signed_rem(left, right, "__tmp__"),
+                    (_, true) => self.builder.build_int_unsigned_rem(left, right, "__tmp__"),
+                };
+            },
+            BinaryExpressionType::ShiftLeft | BinaryExpressionType::ShiftRight => {
+                let width = left.get_type().const_int(left.get_type().get_bit_width() as u64, false);
+                let in_range = self.builder.build_int_compare(IntPredicate::ULT, right, width, "__tmp__");
+                self.build_runtime_check(in_range, "shift amount out of range");
+                return match binary_type {
+                    BinaryExpressionType::ShiftLeft => self.builder.build_left_shift(left, right, "__tmp__"),
+                    _ => self.builder.build_right_shift(left, right, !unsigned, "__tmp__"),
+                };
+            },
+            _ => {},
+        }
+        let (result, overflowed) = self.build_with_overflow(binary_type, left, right, unsigned);
+        let fits = self.builder.build_not(overflowed, "__tmp__");
+        self.build_runtime_check(fits, "integer overflow");
+        result
+    }
+
+    // The wrapped result of an addition, subtraction or multiplication and whether it overflowed
+    fn build_with_overflow(&self, binary_type: &BinaryExpressionType, left: IntValue<'ctx>, right: IntValue<'ctx>, unsigned: bool) -> (IntValue<'ctx>, IntValue<'ctx>) {
+        let operation = match (binary_type, unsigned) {
+            (BinaryExpressionType::Addition, false) => "sadd",
+            (BinaryExpressionType::Addition, true) => "uadd",
+            (BinaryExpressionType::Subtraction, false) => "ssub",
+            (BinaryExpressionType::Subtraction, true) => "usub",
+            (BinaryExpressionType::Multiplication, false) => "smul",
+            (BinaryExpressionType::Multiplication, true) => "umul",
+            _ => unreachable!()
+        };
+        let intrinsic = self.overflow_intrinsic(operation, left.get_type());
+        let result = self.builder.build_call(intrinsic, &[left.into(), right.into()], "__tmp__")
+            .try_as_basic_value().left().unwrap().into_struct_value();
+        let value = self.builder.build_extract_value(result, 0, "__tmp__").unwrap().into_int_value();
+        let overflowed = self.builder.build_extract_value(result, 1, "__tmp__").unwrap().into_int_value();
+        (value, overflowed)
+    }
+
+    // What the checked_* builtins return, nothing traps
+    fn build_fits(&self, binary_type: &BinaryExpressionType, left: IntValue<'ctx>, right: IntValue<'ctx>, unsigned: bool) -> IntValue<'ctx> {
+        if !matches!(binary_type, BinaryExpressionType::Division | BinaryExpressionType::Modulo) {
+            let (_, overflowed) = self.build_with_overflow(binary_type, left, right, unsigned);
+            return self.builder.build_not(overflowed, "__tmp__");
+        }
+        let nonzero = self.builder.build_int_compare(IntPredicate::NE, right, right.get_type().const_zero(), "__tmp__");
+        if unsigned {
+            return nonzero;
+        }
+        let fits = self.builder.build_not(self.build_division_overflows(left, right), "__tmp__");
+        self.builder.build_and(nonzero, fits, "__tmp__")
+    }
+
+    // Division by zero is reported here too, the smallest value divided by -1 wraps instead of being undefined
+    fn build_wrapping_division(&self, binary_type: &BinaryExpressionType, left: IntValue<'ctx>, right: IntValue<'ctx>, unsigned: bool) -> IntValue<'ctx> {
+        let int_type = right.get_type();
+        let nonzero = self.builder.build_int_compare(IntPredicate::NE, right, int_type.const_zero(), "__tmp__");
+        self.build_runtime_check(nonzero, "division by zero");
+        let division = matches!(binary_type, BinaryExpressionType::Division);
+        if unsigned {
+            return match division {
+                true => self.builder.build_int_unsigned_div(left, right, "__tmp__"),
+                false => self.builder.build_int_unsigned_rem(left, right, "__tmp__"),
+            };
+        }
+
+        // x / -1 is -x and x % -1 is 0, dividing by 1 instead keeps sdiv defined
+        let is_negative_one = self.builder.build_int_compare(IntPredicate::EQ, right, int_type.const_all_ones(), "__tmp__");
+        let divisor = self.builder.build_select(is_negative_one, int_type.const_int(1, false), right, "__tmp__").into_int_value();
+        let (result, negative_one_result) = match division {
+            true => (self.builder.build_int_signed_div(left, divisor, "__tmp__"), self.builder.build_int_neg(left, "__tmp__")),
+            false => (self.builder.build_int_signed_rem(left, divisor, "__tmp__"), int_type.const_zero()),
+        };
+        self.builder.build_select(is_negative_one, negative_one_result, result, "__tmp__").into_int_value()
+    }
+
+    pub fn build_binary(&self, binary_type: &BinaryExpressionType, parsed_left: BasicValueEnum<'ctx>, parsed_right: BasicValueEnum<'ctx>, unsigned: bool, checked: bool) -> BasicValueEnum<'ctx> {
+        if let (BasicValueEnum::IntValue(int_left), BasicValueEnum::IntValue(int_right)) = (parsed_left, parsed_right) {
+            let value = match binary_type {
+                BinaryExpressionType::Addition | BinaryExpressionType::Subtraction |
+                BinaryExpressionType::Multiplication | BinaryExpressionType::Division |
+                BinaryExpressionType::Modulo | BinaryExpressionType::ShiftLeft |
+                BinaryExpressionType::ShiftRight if checked => {
+                    self.build_checked_int_operation(binary_type, int_left, int_right, unsigned)
+                },
+                BinaryExpressionType::Addition => self.builder.build_int_add(int_left, int_right, "__tmp__"),
+                BinaryExpressionType::Subtraction => self.builder.build_int_sub(int_left, int_right, "__tmp__"),
+                BinaryExpressionType::Multiplication => self.builder.build_int_mul(int_left, int_right, "__tmp__"),
+                BinaryExpressionType::Division | BinaryExpressionType::Modulo => self.build_wrapping_division(binary_type, int_left, int_right, unsigned),
+                BinaryExpressionType::BitwiseAnd => self.builder.build_and(int_left, int_right, "__tmp__"),
+                BinaryExpressionType::BitwiseOr => self.builder.build_or(int_left, int_right, "__tmp__"),
+                BinaryExpressionType::BitwiseXor => self.builder.build_xor(int_left, int_right, "__tmp__"),
+                BinaryExpressionType::ShiftLeft => self.builder.build_left_shift(int_left, int_right, "__tmp__"),
+                BinaryExpressionType::ShiftRight => self.builder.build_right_shift(int_left, int_right, !unsigned, "__tmp__"),
+                _ => {
+                    let predicate = match binary_type {
+                        BinaryExpressionType::Equal => IntPredicate::EQ,
+                        BinaryExpressionType::NotEqual => IntPredicate::NE,
+                        BinaryExpressionType::Less => IntPredicate::SLT,
+                        BinaryExpressionType::LessEqual => IntPredicate::SLE,
+                        BinaryExpressionType::Greater => IntPredicate::SGT,
+                        BinaryExpressionType::GreaterEqual => IntPredicate::SGE,
+                        _ => unreachable!()
+                    };
+                    self.builder.build_int_compare(predicate, int_left, int_right, "__tmp__")
+                }
+            };
+
+            return value.into();
+        }
+        if let (BasicValueEnum::FloatValue(float_left), BasicValueEnum::FloatValue(float_right)) = (parsed_left, parsed_right) {
+            let value = match binary_type {
+                BinaryExpressionType::Addition => self.builder.build_float_add(float_left, float_right, "__tmp__").into(),
+                BinaryExpressionType::Subtraction => self.builder.build_float_sub(float_left, float_right, "__tmp__").into(),
+                BinaryExpressionType::Multiplication => self.builder.build_float_mul(float_left, float_right, "__tmp__").into(),
+                BinaryExpressionType::Division => self.builder.build_float_div(float_left, float_right, "__tmp__").into(),
+                BinaryExpressionType::Modulo => self.builder.build_float_rem(float_left, float_right, "__tmp__").into(),
+                _ => {
+                    let predicate = match binary_type {
+                        BinaryExpressionType::Equal => FloatPredicate::OEQ,
+                        BinaryExpressionType::NotEqual => FloatPredicate::ONE,
+                        BinaryExpressionType::Less => FloatPredicate::OLT,
+                        BinaryExpressionType::LessEqual => FloatPredicate::OLE,
+                        BinaryExpressionType::Greater => FloatPredicate::OGT,
+                        BinaryExpressionType::GreaterEqual => FloatPredicate::OGE,
+                        _ => unreachable!()
+                    };
+                    self.builder.build_float_compare(predicate, float_left, float_right, "__tmp__").into()
+                }
+            };
+
+            return value;
+        }
+        unimplemented!()
+    }
+
+    // Comparisons produce i1, it's widened without sign so true stays 1
+    fn build_cast(&self, integer: IntValue<'ctx>, resultant: &str) -> BasicValueEnum<'ctx> {
+        let from_bool = integer.get_type().get_bit_width() == 1;
+        match resultant {
+            "f64" if from_bool => self.builder.build_unsigned_int_to_float(integer, self.context.f64_type(), "__tmp__").into(),
+            "f64" => self.builder.build_signed_int_to_float(integer, self.context.f64_type(), "__tmp__").into(),
+            "i64" if from_bool => self.builder.build_int_z_extend(integer, self.context.i64_type(), "__tmp__").into(),
+            "i64" => self.builder.build_int_cast(integer, self.context.i64_type(), "__tmp__").into(),
+            "char" if from_bool => self.builder.build_int_z_extend(integer, self.context.i8_type(), "__tmp__").into(),
+            "char" => self.builder.build_int_cast(integer, self.context.i8_type(), "__tmp__").into(),
+            _ => unimplemented!()
+        }
+    }
+}
@@ -0,0 +1,188 @@
+mod debug_info;
+mod expression;
+mod passes;
+mod runtime_functions;
+mod statement;
+mod target;
+
+pub use passes::{engine_level, optimize};
+pub use target::{set_target, target_machine};
+
+use std::{collections::HashMap, cell::RefCell};
+
+use inkwell::{attributes::{Attribute, AttributeLoc}, module::Module, context::Context, builder::Builder, basic_block::BasicBlock, targets::TargetData, types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum}, values::{PointerValue, FunctionValue, GlobalValue}};
+
+use self::debug_info::DebugInfo;
+use crate::{ast::{CompilerOptions, DataType, Function, GlobalVariable, RootScope}, mir::{self, Body, Lowering}};
+
+// Translates the mid-level IR to LLVM IR, the tree is lowered to it first
+pub struct Compiler<'ctx> {
+    pub context: &'ctx Context,
+    pub module: Module<'ctx>,
+    pub builder: Builder<'ctx>,
+    pub options: CompilerOptions,
+    // From the module's data layout, set_target has to come before new for other targets
+    pub pointer_size: u64,
+    pub lowering: RefCell<Lowering>,
+    // Storage of every local and the blocks of the body being translated
+    pub locals: RefCell<Vec<PointerValue<'ctx>>>,
+    pub blocks: RefCell<Vec<BasicBlock<'ctx>>>,
+    pub current_line: RefCell<usize>,
+    // Only with -g
+    pub debug_info: Option<DebugInfo<'ctx>>,
+}
+
+impl<'ctx> Compiler<'ctx> {
+    pub fn new(context: &'ctx Context, module: Module<'ctx>, data_types: HashMap<String, DataType>, options: CompilerOptions) -> Self {
+        let debug_info = options.debug_info.then(|| DebugInfo::new(context, &module, &options));
+        let pointer_size = TargetData::create(module.get_data_layout().as_str().to_str().unwrap()).get_pointer_byte_size(None) as u64;
+        Self {
+            context,
+            module,
+            builder: context.create_builder(),
+            pointer_size,
+            lowering: RefCell::new(Lowering::new(data_types, options.clone())),
+            options,
+            locals: RefCell::new(Vec::new()),
+            blocks: RefCell::new(Vec::new()),
+            current_line: RefCell::new(0),
+            debug_info,
+        }
+    }
+
+    // Adds the signature to the module so calls can be built before the body is translated
+    pub fn declare_function(&self, function: &Function) -> FunctionValue<'ctx> {
+        self.lowering.borrow_mut().declare_function(function);
+        let params: Vec<DataType> = function.params.iter().map(|(_, data_type)| data_type.clone()).collect();
+        match function.external {
+            true => self.declare_extern(&function.name, &params, function.return_type.as_ref()),
+            false => self.declare(&function.name, &params, function.return_type.as_ref()),
+        }
+    }
+
+    // Adds the global without a value, enough for modules that only refer to it
+    pub fn declare_global(&self, global: &GlobalVariable) -> GlobalValue<'ctx> {
+        self.lowering.borrow_mut().declare_global(global);
+        self.add_global(&global.name, &global.data_type)
+    }
+
+    // Lowers the tree, checks the result and translates it, the IR is returned for callers that inspect it
+    pub fn compile_root(&self, root: &RootScope) -> mir::Program {
+        let program = self.lowering.borrow_mut().lower(root);
+        if let Err(error) = mir::verify(&program) {
+            panic!("Invalid mir in {}\n{}", error, program);
+        }
+        for function in &program.externs {
+            self.declare_extern(&function.name, &function.params, function.return_type.as_ref());
+        }
+        // Declare every function up front so they can be used before their definition
+        for body in &program.bodies {
+            let params: Vec<DataType> = body.locals.iter().take(body.params as usize).map(|decl| decl.data_type.clone()).collect();
+            self.declare(&body.name, &params, body.return_type.as_ref());
+        }
+        for global in &program.globals {
+            self.compile_global(global);
+        }
+        for body in &program.bodies {
+            self.compile_body(body);
+        }
+        self.finalize_debug_info();
+        program
+    }
+
+    pub fn llvm_type(&self, data_type: &DataType) -> BasicTypeEnum<'ctx> {
+        data_type.produce_llvm_type(self.context, self.pointer_size).as_basic_type_enum()
+    }
+
+    fn declare(&self, name: &str, params: &[DataType], return_type: Option<&DataType>) -> FunctionValue<'ctx> {
+        if let Some(fn_value) = self.module.get_function(name) {
+            return fn_value;
+        }
+        let param_types: Vec<BasicMetadataTypeEnum> = params.iter().map(|data_type| self.llvm_type(data_type).into()).collect();
+        let fn_type = match return_type {
+            Some(data_type) => self.llvm_type(data_type).fn_type(&param_types, false),
+            None => self.context.void_type().fn_type(&param_types, false),
+        };
+        self.module.add_function(name, fn_type, None)
+    }
+
+    // Externs only take numbers and pointers, which llvm already passes by the target's C convention,
+    // except that char has to be widened like C's unsigned char
+    fn declare_extern(&self, name: &str, params: &[DataType], return_type: Option<&DataType>) -> FunctionValue<'ctx> {
+        let fn_value = self.declare(name, params, return_type);
+        let zero_extend = self.context.create_enum_attribute(Attribute::get_named_enum_kind_id("zeroext"), 0);
+        for (index, param) in params.iter().enumerate() {
+            if param.symbol == "char" {
+                fn_value.add_attribute(AttributeLoc::Param(index as u32), zero_extend);
+            }
+        }
+        if return_type.is_some_and(|data_type| data_type.symbol == "char") {
+            fn_value.add_attribute(AttributeLoc::Return, zero_extend);
+        }
+        fn_value
+    }
+
+    fn add_global(&self, name: &str, data_type: &DataType) -> GlobalValue<'ctx> {
+        if let Some(value) = self.module.get_global(name) {
+            return value;
+        }
+        self.module.add_global(self.llvm_type(data_type), None, name)
+    }
+
+    fn compile_global(&self, global: &mir::Global) {
+        let value = self.add_global(&global.name, &global.data_type);
+        if value.get_initializer().is_some() {
+            return;
+        }
+        let initial_value = match global.initializer {
+            Some(ref initializer) => self.compile_constant(initializer),
+            None => self.zero(&global.data_type),
+        };
+        value.set_initializer(&initial_value);
+    }
+
+    fn compile_body(&self, body: &Body) {
+        let fn_value = self.module.get_function(&body.name).unwrap();
+        self.begin_debug_scope(fn_value, body);
+        let entry = self.context.append_basic_block(fn_value, "entry");
+        self.builder.position_at_end(entry);
+        let locals: Vec<PointerValue> = body.locals.iter().enumerate().map(|(index, decl)| {
+            let name = decl.name.clone().unwrap_or_else(|| format!("_{}", index));
+            self.builder.build_alloca(self.llvm_type(&decl.data_type), &name)
+        }).collect();
+        for (local, param) in locals.iter().zip(fn_value.get_params()) {
+            self.builder.build_store(*local, param);
+        }
+        self.declare_debug_locals(body, &locals);
+        let blocks: Vec<BasicBlock> = (0..body.blocks.len()).map(|index| self.context.append_basic_block(fn_value, &format!("bb{}", index))).collect();
+        self.builder.build_unconditional_branch(blocks[0]);
+        self.locals.replace(locals);
+        self.blocks.replace(blocks);
+
+        for (index, block) in body.blocks.iter().enumerate() {
+            let llvm_block = self.blocks.borrow()[index];
+            self.builder.position_at_end(llvm_block);
+            for statement in &block.statements {
+                self.current_line.replace(statement.line);
+                self.set_debug_line(statement.line);
+                self.compile_statement(body, &statement.kind);
+            }
+            self.current_line.replace(block.terminator.line);
+            self.set_debug_line(block.terminator.line);
+            self.compile_terminator(body, &block.terminator.kind);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::conformance;
+
+    #[test]
+    fn passes_conformance_suite() {
+        for (name, source, expected) in conformance::PROGRAMS {
+            assert_eq!(conformance::run_llvm(source, CompilerOptions::default()), *expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use inkwell::{module::Module, passes::PassManager};
+
+use crate::ast::OptimizationLevel;
+
+// Level the execution engine generates machine code at, -Os only changes the passes
+pub fn engine_level(level: OptimizationLevel) -> inkwell::OptimizationLevel {
+    match level {
+        OptimizationLevel::None => inkwell::OptimizationLevel::None,
+        OptimizationLevel::Less => inkwell::OptimizationLevel::Less,
+        OptimizationLevel::Default | OptimizationLevel::Size => inkwell::OptimizationLevel::Default,
+        OptimizationLevel::Aggressive => inkwell::OptimizationLevel::Aggressive,
+    }
+}
+
+// Function passes clean up the stack slots and loads codegen produces, module passes then inline
+// across functions and drop what inlining made unused
+pub fn optimize(module: &Module, level: OptimizationLevel) {
+    if level == OptimizationLevel::None {
+        return;
+    }
+    let functions = PassManager::create(module);
+    functions.add_promote_memory_to_register_pass();
+    functions.add_instruction_combining_pass();
+    functions.add_reassociate_pass();
+    functions.add_gvn_pass();
+    functions.add_cfg_simplification_pass();
+    if level != OptimizationLevel::Less {
+        functions.add_loop_rotate_pass();
+        functions.add_licm_pass();
+        functions.add_ind_var_simplify_pass();
+        functions.add_loop_deletion_pass();
+    }
+    if level == OptimizationLevel::Aggressive {
+        functions.add_loop_unroll_pass();
+    }
+    functions.initialize();
+    // Runtime functions are only declared
+    for function in module.get_functions().filter(|function| function.count_basic_blocks() > 0) {
+        functions.run_on(&function);
+    }
+    functions.finalize();
+
+    let passes = PassManager::create(());
+    match level {
+        OptimizationLevel::Less | OptimizationLevel::Size => passes.add_always_inliner_pass(),
+        _ => passes.add_function_inlining_pass(),
+    }
+    passes.add_global_dce_pass();
+    passes.add_promote_memory_to_register_pass();
+    passes.add_instruction_combining_pass();
+    passes.add_cfg_simplification_pass();
+    passes.run_on(module);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::CompilerOptions, codegen::Compiler, conformance, parsing::Parser};
+    use inkwell::context::Context;
+
+    #[test]
+    fn optimized_programs_keep_their_results() {
+        let levels = [OptimizationLevel::Less, OptimizationLevel::Default, OptimizationLevel::Aggressive, OptimizationLevel::Size];
+        for level in levels {
+            for (name, source, expected) in conformance::PROGRAMS {
+                let options = CompilerOptions { optimization: level, ..Default::default() };
+                assert_eq!(conformance::run_llvm(source, options), *expected, "{} at {:?}", name, level);
+            }
+        }
+    }
+
+    #[test]
+    fn promotes_locals_to_registers() {
+        let mut parser = Parser::new("def main(): i64 {\n    x = 2\n    y = x * 3\n    return y\n}\n".to_string());
+        let root = parser.parse().unwrap();
+        let context = Context::create();
+        let compiler = Compiler::new(&context, context.create_module("main"), parser.data_types.clone(), CompilerOptions::default());
+        compiler.compile_root(&root);
+        optimize(&compiler.module, OptimizationLevel::Less);
+        let ir = compiler.module.get_function("main").unwrap().print_to_string().to_string();
+        assert!(!ir.contains("alloca"), "{}", ir);
+        assert!(ir.contains("ret i64 6"), "{}", ir);
+    }
+}
@@ -0,0 +1,146 @@
+use inkwell::{module::Linkage, values::{FunctionValue, PointerValue, BasicValueEnum, IntValue}, types::{BasicTypeEnum, BasicType, IntType}, AddressSpace, IntPredicate};
+
+use crate::ast::DataType;
+
+use super::Compiler;
+
+impl<'ctx> Compiler<'ctx> {
+    // Declares a function implemented in ss_runtime, the jit maps it to its address and objects link libss_runtime.a
+    pub fn runtime_function(&self, name: &str) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(name) {
+            return function;
+        }
+        let i8_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let fn_type = match name {
+            "ss_alloc" | "ss_alloc_owner" => i8_ptr.fn_type(&[self.context.i64_type().into()], false),
+            "ss_retain" | "ss_release" | "ss_release_unowned" => self.context.void_type().fn_type(&[i8_ptr.into()], false),
+            "ss_vec_new" => i8_ptr.fn_type(&[], false),
+            "ss_vec_push" => self.context.void_type().fn_type(&[i8_ptr.into(), i8_ptr.into(), self.context.i64_type().into()], false),
+            "ss_vec_pop" => {
+                let params = [i8_ptr.into(), i8_ptr.into(), self.context.i64_type().into(), i8_ptr.into(), self.context.i64_type().into()];
+                self.context.void_type().fn_type(&params, false)
+            },
+            "ss_vec_len" => self.context.i64_type().fn_type(&[i8_ptr.into()], false),
+            "ss_vec_data" => i8_ptr.fn_type(&[i8_ptr.into()], false),
+            "ss_panic" => self.context.void_type().fn_type(&[i8_ptr.into(), i8_ptr.into(), self.context.i64_type().into()], false),
+            _ => panic!("Unknown runtime function {}", name),
+        };
+        self.module.add_function(name, fn_type, Some(Linkage::External))
+    }
+
+    // The payload words of an enum are reinterpreted as a struct of the variant's fields
+    pub fn build_enum_payload(&self, location: PointerValue<'ctx>, enum_type: &DataType, tag: u64) -> PointerValue<'ctx> {
+        let payload = self.builder.build_struct_gep(location, 1, "__tmp__").unwrap();
+        let variant_type = enum_type.produce_variant_llvm_type(self.context, tag, self.pointer_size);
+        self.builder.build_pointer_cast(payload, variant_type.ptr_type(AddressSpace::default()), "__tmp__")
+    }
+
+    pub fn build_entry_alloca(&self, data_type: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let builder = self.context.create_builder();
+        let function = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let entry = function.get_first_basic_block().unwrap();
+        match entry.get_first_instruction() {
+            Some(ref instruction) => builder.position_before(instruction),
+            None => builder.position_at_end(entry),
+        }
+        let allocation = builder.build_alloca(data_type, name);
+        if let BasicTypeEnum::PointerType(ptr_type) = data_type {
+            builder.build_store(allocation, ptr_type.const_null());
+        }
+        allocation
+    }
+
+    pub fn build_retain(&self, value: PointerValue<'ctx>) {
+        self.build_rc_call("ss_retain", value);
+    }
+
+    pub fn build_release(&self, value: PointerValue<'ctx>) {
+        self.build_rc_call("ss_release", value);
+    }
+
+    pub fn build_release_unowned(&self, value: PointerValue<'ctx>) {
+        self.build_rc_call("ss_release_unowned", value);
+    }
+
+    pub fn build_vector_data(&self, handle: PointerValue<'ctx>, element_type: BasicTypeEnum<'ctx>) -> PointerValue<'ctx> {
+        let raw = self.builder.build_call(self.runtime_function("ss_vec_data"), &[handle.into()], "__tmp__")
+            .try_as_basic_value().left().unwrap().into_pointer_value();
+        self.builder.build_pointer_cast(raw, element_type.ptr_type(AddressSpace::default()), "__tmp__")
+    }
+
+    pub fn build_vector_push(&self, handle: PointerValue<'ctx>, value: BasicValueEnum<'ctx>) {
+        let slot = self.build_entry_alloca(value.get_type(), "__tmp__");
+        self.builder.build_store(slot, value);
+        let i8_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let raw = self.builder.build_pointer_cast(slot, i8_ptr, "__tmp__");
+        let size = value.get_type().size_of().unwrap();
+        self.builder.build_call(self.runtime_function("ss_vec_push"), &[handle.into(), raw.into(), size.into()], "");
+    }
+
+    // Continues when condition holds, otherwise reports the current line and aborts
+    pub fn build_runtime_check(&self, condition: IntValue<'ctx>, message: &str) {
+        let current_block = self.builder.get_insert_block().unwrap();
+        let ok_block = self.context.insert_basic_block_after(current_block, "0");
+        let fail_block = self.context.insert_basic_block_after(current_block, "0");
+        self.builder.build_conditional_branch(condition, ok_block, fail_block);
+
+        self.builder.position_at_end(fail_block);
+        let message = self.builder.build_global_string_ptr(message, "__message__").as_pointer_value();
+        let (file, line) = self.build_location();
+        self.builder.build_call(self.runtime_function("ss_panic"), &[message.into(), file.into(), line.into()], "");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(ok_block);
+    }
+
+    // File and line of the code being compiled, for runtime functions that can fail
+    pub fn build_location(&self) -> (PointerValue<'ctx>, IntValue<'ctx>) {
+        let file = self.builder.build_global_string_ptr(&self.options.source_name, "__file__").as_pointer_value();
+        let line = self.context.i64_type().const_int(*self.current_line.borrow() as u64, false);
+        (file, line)
+    }
+
+    pub fn build_bounds_check(&self, index: IntValue<'ctx>, len: IntValue<'ctx>) {
+        let index = self.builder.build_int_cast(index, self.context.i64_type(), "__tmp__");
+        // Unsigned comparison also rejects negative indices
+        let in_bounds = self.builder.build_int_compare(IntPredicate::ULT, index, len, "__tmp__");
+        self.build_runtime_check(in_bounds, "index out of bounds");
+    }
+
+    // Declares llvm.<operation>.with.overflow for the given integer width
+    pub fn overflow_intrinsic(&self, operation: &str, int_type: IntType<'ctx>) -> FunctionValue<'ctx> {
+        let name = format!("llvm.{}.with.overflow.i{}", operation, int_type.get_bit_width());
+        if let Some(function) = self.module.get_function(&name) {
+            return function;
+        }
+        let result_type = self.context.struct_type(&[int_type.into(), self.context.bool_type().into()], false);
+        self.module.add_function(&name, result_type.fn_type(&[int_type.into(), int_type.into()], false), None)
+    }
+
+    pub fn build_division_check(&self, left: IntValue<'ctx>, right: IntValue<'ctx>, signed: bool) {
+        let int_type = right.get_type();
+        let nonzero = self.builder.build_int_compare(IntPredicate::NE, right, int_type.const_zero(), "__tmp__");
+        self.build_runtime_check(nonzero, "division by zero");
+        if !signed {
+            return;
+        }
+
+        let fits = self.builder.build_not(self.build_division_overflows(left, right), "__tmp__");
+        self.build_runtime_check(fits, "integer overflow");
+    }
+
+    // The smallest value divided by -1 doesn't fit either
+    pub fn build_division_overflows(&self, left: IntValue<'ctx>, right: IntValue<'ctx>) -> IntValue<'ctx> {
+        let int_type = right.get_type();
+        let min = int_type.const_int(1 << (int_type.get_bit_width() - 1), false);
+        let is_min = self.builder.build_int_compare(IntPredicate::EQ, left, min, "__tmp__");
+        let is_negative_one = self.builder.build_int_compare(IntPredicate::EQ, right, int_type.const_all_ones(), "__tmp__");
+        self.builder.build_and(is_min, is_negative_one, "__tmp__")
+    }
+
+    fn build_rc_call(&self, name: &str, value: PointerValue<'ctx>) {
+        let i8_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let raw = self.builder.build_pointer_cast(value, i8_ptr, "__tmp__");
+        self.builder.build_call(self.runtime_function(name), &[raw.into()], "");
+    }
+}
@@ -0,0 +1,78 @@
+use inkwell::values::{BasicMetadataValueEnum, CallableValue};
+
+use crate::mir::{Body, Constant, Operand, StatementKind, TerminatorKind};
+
+use super::Compiler;
+
+impl<'ctx> Compiler<'ctx> {
+    pub fn compile_statement(&self, body: &Body, kind: &StatementKind) {
+        match kind {
+            StatementKind::Assign(place, rvalue) => {
+                let data_type = place.data_type(body).unwrap();
+                let value = self.compile_rvalue(body, rvalue, &data_type);
+                let location = self.place_address(body, place);
+                self.builder.build_store(location, value);
+            },
+            StatementKind::BoundsCheck(index, len) => {
+                let index = self.compile_operand(body, index).into_int_value();
+                let len = self.compile_operand(body, len).into_int_value();
+                self.build_bounds_check(index, len);
+            },
+            StatementKind::Retain(operand) => self.build_retain(self.compile_operand(body, operand).into_pointer_value()),
+            StatementKind::Release(operand) => self.build_release(self.compile_operand(body, operand).into_pointer_value()),
+            StatementKind::ReleaseUnowned(operand) => self.build_release_unowned(self.compile_operand(body, operand).into_pointer_value()),
+            StatementKind::Call(callee, args, destination) => {
+                let args: Vec<BasicMetadataValueEnum> = args.iter().map(|arg| self.compile_operand(body, arg).into()).collect();
+                // Void calls can't be named
+                let name = if destination.is_some() { "__tmp__" } else { "" };
+                let call = match callee {
+                    Operand::Constant(Constant::Function(function, _)) => {
+                        self.builder.build_call(self.module.get_function(function).unwrap(), &args, name)
+                    },
+                    _ => {
+                        let pointer = self.compile_operand(body, callee).into_pointer_value();
+                        self.builder.build_call(CallableValue::try_from(pointer).unwrap(), &args, name)
+                    },
+                };
+                if let Some(destination) = destination {
+                    let value = call.try_as_basic_value().left().unwrap();
+                    let location = self.place_address(body, destination);
+                    self.builder.build_store(location, value);
+                }
+            },
+            StatementKind::Push(handle, value) => {
+                let handle = self.compile_operand(body, handle).into_pointer_value();
+                let value = self.compile_operand(body, value);
+                self.build_vector_push(handle, value);
+            },
+        }
+    }
+
+    pub fn compile_terminator(&self, body: &Body, kind: &TerminatorKind) {
+        let blocks = self.blocks.borrow();
+        match kind {
+            TerminatorKind::Goto(target) => {
+                self.builder.build_unconditional_branch(blocks[target.index()]);
+            },
+            TerminatorKind::Branch(condition, then, otherwise) => {
+                let condition = self.compile_operand(body, condition).into_int_value();
+                self.builder.build_conditional_branch(condition, blocks[then.index()], blocks[otherwise.index()]);
+            },
+            TerminatorKind::Switch(tag, targets, otherwise) => {
+                let tag = self.compile_operand(body, tag).into_int_value();
+                let cases: Vec<_> = targets.iter().map(|(value, target)| (self.context.i64_type().const_int(*value, false), blocks[target.index()])).collect();
+                self.builder.build_switch(tag, blocks[otherwise.index()], &cases);
+            },
+            TerminatorKind::Return(Some(value)) => {
+                let value = self.compile_operand(body, value);
+                self.builder.build_return(Some(&value));
+            },
+            TerminatorKind::Return(None) => {
+                self.builder.build_return(None);
+            },
+            TerminatorKind::Unreachable => {
+                self.builder.build_unreachable();
+            },
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use inkwell::{module::Module, targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple}};
+
+use crate::ast::CompilerOptions;
+
+use super::engine_level;
+
+// Machine for --target, --cpu and --features, each defaults to the host's
+pub fn target_machine(options: &CompilerOptions) -> Result<TargetMachine, String> {
+    Target::initialize_all(&InitializationConfig::default());
+    let (triple, cpu, features) = match options.target {
+        Some(ref triple) => (TargetTriple::create(triple), "generic".to_string(), String::new()),
+        None => (
+            TargetMachine::get_default_triple(),
+            TargetMachine::get_host_cpu_name().to_string(),
+            TargetMachine::get_host_cpu_features().to_string(),
+        ),
+    };
+    let cpu = options.cpu.clone().unwrap_or(cpu);
+    let features = options.target_features.clone().unwrap_or(features);
+    let name = triple.as_str().to_string_lossy().to_string();
+    let target = Target::from_triple(&triple).map_err(|error| format!("Unknown target {}: {}", name, error))?;
+    target.create_target_machine(&triple, &cpu, &features, engine_level(options.optimization), RelocMode::PIC, CodeModel::Default)
+        .ok_or_else(|| format!("Can't generate code for {} with cpu {}", name, cpu))
+}
+
+// Has to happen before codegen, sizes of pointers and struct layouts come from the data layout
+pub fn set_target(module: &Module, machine: &TargetMachine) {
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{ast::{DataType, DataTypeEnum}, codegen::Compiler, conformance};
+    use inkwell::{context::Context, targets::FileType};
+
+    // ELF e_machine values
+    const EM_386: u16 = 3;
+    const EM_AARCH64: u16 = 183;
+    const EM_RISCV: u16 = 243;
+
+    #[test]
+    fn emits_objects_for_other_targets() {
+        let targets = [("aarch64-unknown-linux-gnu", EM_AARCH64, 64), ("riscv64-unknown-linux-gnu", EM_RISCV, 64), ("i686-unknown-linux-gnu", EM_386, 32)];
+        for (triple, machine_type, bits) in targets {
+            for (name, source, _) in conformance::PROGRAMS {
+                let options = CompilerOptions { target: Some(triple.to_string()), ..Default::default() };
+                let machine = target_machine(&options).unwrap();
+                let context = Context::create();
+                let module = context.create_module("main");
+                set_target(&module, &machine);
+                let module = conformance::compile_llvm(&context, module, source, options);
+                assert_eq!(module.get_triple().as_str().to_str().unwrap(), triple);
+
+                let object = machine.write_to_memory_buffer(&module, FileType::Object).unwrap();
+                let bytes = object.as_slice();
+                assert_eq!(&bytes[..4], b"\x7fELF", "{} on {}", name, triple);
+                // EI_CLASS is 1 for 32 bit objects and 2 for 64 bit ones
+                assert_eq!(bytes[4], bits / 32, "{} on {}", name, triple);
+                assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), machine_type, "{} on {}", name, triple);
+            }
+        }
+    }
+
+    #[test]
+    fn takes_pointer_sizes_from_the_target() {
+        let pointers = DataType::array(DataType::pointer(DataType::primitive("i64")), 4);
+        let refs = DataType { symbol: "Refs".to_string(), value: DataTypeEnum::Enum(vec![("Many".to_string(), vec![pointers])]) };
+        for (triple, pointer_size) in [("i686-unknown-linux-gnu", 4), ("x86_64-unknown-linux-gnu", 8)] {
+            let options = CompilerOptions { target: Some(triple.to_string()), ..Default::default() };
+            let machine = target_machine(&options).unwrap();
+            let context = Context::create();
+            let module = context.create_module("main");
+            set_target(&module, &machine);
+            let compiler = Compiler::new(&context, module, HashMap::new(), options);
+            assert_eq!(compiler.pointer_size, pointer_size, "{}", triple);
+            // The tag and a payload of four pointers
+            let size = machine.get_target_data().get_abi_size(&compiler.llvm_type(&refs));
+            assert_eq!(size, 8 + 4 * pointer_size, "{}", triple);
+        }
+    }
+
+    #[test]
+    fn calls_externs_with_the_c_abi() {
+        // labs comes from the C library the tests are linked with
+        let source = "extern def labs(x: i64): i64\ndef main(): i64 {\n    return labs(-5)\n}\n";
+        assert_eq!(conformance::run_llvm(source, CompilerOptions::default()), 5);
+
+        // Objects for other targets leave the symbol to their linker
+        let options = CompilerOptions { target: Some("aarch64-unknown-linux-gnu".to_string()), ..Default::default() };
+        let machine = target_machine(&options).unwrap();
+        let context = Context::create();
+        let module = context.create_module("main");
+        set_target(&module, &machine);
+        let module = conformance::compile_llvm(&context, module, source, options);
+        let object = machine.write_to_memory_buffer(&module, FileType::Object).unwrap();
+        assert!(object.as_slice().windows(4).any(|bytes| bytes == b"labs"));
+    }
+
+    #[test]
+    fn rejects_unknown_targets() {
+        let options = CompilerOptions { target: Some("nonsense-unknown-none".to_string()), ..Default::default() };
+        assert!(target_machine(&options).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+#[cfg(feature = "llvm")]
+use inkwell::{context::Context, module::Module};
+
+use crate::{ast::DataType, parsing::Parser};
+#[cfg(feature = "llvm")]
+use crate::{ast::CompilerOptions, codegen::{Compiler, engine_level, optimize}, runner::map_runtime_functions};
+
+// Programs every backend must agree on, as name, source and what main returns
+pub const PROGRAMS: &[(&str, &str, i64)] = &[
+    ("arithmetic", "def main(): i64 {
+    x = 7 * 6 - 10 / 3
+    y = x % 5 + (2 << 3) - (-9 >> 1)
+    if 3 > 3 {
+        y = 0
+    }
+    if 3 >= 3 {
+        y = y + 100
+    }
+    if 2 < 3 {
+        y = y + (6 & 3 | 8 ^ 1)
+    }
+    return y
+}
+", 136),
+    ("floats", "def main(): i64 {
+    x = 1.5 * 4.0 - 0.5
+    result = 0
+    if x > 5.0 {
+        result = 1
+    }
+    if x == 5.5 {
+        result = result + 2
+    }
+    return result
+}
+", 3),
+    ("arrays", "def main(): i64 {
+    values = [1, 2, 3]
+    values[1] = 10
+    values[2] += 5
+    total = 0
+    for v in values {
+        total += v
+    }
+    return total * 10 + values[1]
+}
+", 200),
+    ("loop variable shadows", "def find_two(values: [i64:3]): i64 {
+    v = 100
+    for v in values {
+        if v == 2 {
+            return v
+        }
+    }
+    return v
+}
+def main(): i64 {
+    v = 5
+    total = 0
+    for v in [1, 3, 5] {
+        total += v
+    }
+    return v * 1000 + total * 10 + find_two([1, 2, 3]) + find_two([1, 1, 1]) / 100
+}
+", 5093),
+    ("nested indexing", "def row(): [i64:2] {
+    return [5, 6]
+}
+def main(): i64 {
+    grid = [[1, 2], [3, 4]]
+    grid[1][0] = 7
+    grid[0][1] += 10
+    rows = vec[[1, 2]]
+    rows[0][1] = 9
+    return grid[1][0] * 1000 + grid[0][1] * 10 + row()[1] + rows[0][1] * 100
+}
+", 8026),
+    ("strings", "def main(): i64 {
+    c = \"hi\"
+    c[0] = char(90)
+    return c[0] as i64 + c[1] as i64
+}
+", 195),
+    ("pointers", "def bump(p: &i64) {
+    *p += 1
+}
+def main(): i64 {
+    x = 5
+    p = &x
+    *p = 8
+    *p += 2
+    bump(p)
+    values = [1, 2, 3]
+    q = &values[2]
+    *q = 30
+    return x + values[2]
+}
+", 41),
+    ("recursion", "def fib(n: i64): i64 {
+    result = n
+    if n > 1 {
+        result = fib(n - 1) + fib(n - 2)
+    }
+    return result
+}
+def main(): i64 {
+    return fib(10)
+}
+", 55),
+    ("function values", "def square(x: i64): i64 {
+    return x * x
+}
+def apply(f: fn(i64): i64, x: i64): i64 {
+    return f(x)
+}
+handler: fn(i64): i64 = square
+def main(): i64 {
+    handlers: [fn(i64): i64:2] = [square, handler]
+    g = handlers[1]
+    g(2)
+    return apply(g, 3) + handlers[0](4)
+}
+", 25),
+    ("function value arguments", "def sub(a: i64, b: i64): i64 {
+    return a - b
+}
+op: fn(i64, i64): i64 = sub
+def main(): i64 {
+    return op(10, 3)
+}
+", 7),
+    ("globals", "const SIZE: i64 = 4 * 8
+counter = SIZE + 1
+def main(): i64 {
+    counter = counter + LIMIT
+    return counter
+}
+const LIMIT: i64 = -SIZE
+", 1),
+    ("overflow builtins", "def main(): i64 {
+    x = wrapping_add(9223372036854775807, 1)
+    y = wrapping_div(x, -1)
+    c = char(0)
+    if checked_add(x, -1, &x) {
+        return -1
+    }
+    if checked_add(char(100), char(100), &c) {
+        if x == y {
+            return (c as i64) & 255
+        }
+    }
+    return 0
+}
+", 200),
+    ("nested fields", "line: Line
+points: [Point:2]
+def main(): i64 {
+    line.end.x = 4
+    line.end.y = line.end.x * 2
+    points[1].y = 3
+    points[1].x = points[1].y + line.end.y
+    return line.end.x + line.end.y * 10 + points[1].x * 100 + points[0].x
+}
+", 1184),
+    ("enums and match", "enum Shape {
+    Circle(i64)
+    Rect(i64, i64)
+    Empty
+}
+def area(shape: Shape): i64 {
+    match shape {
+        Circle(radius) {
+            return 3 * radius * radius
+        }
+        Rect(width, height) {
+            return width * height
+        }
+        else {
+        }
+    }
+    return 0
+}
+def main(): i64 {
+    return area(Shape.Circle(2)) + area(Shape.Rect(3, 4)) * 10 + area(Shape.Empty)
+}
+", 132),
+    ("match bindings shadow", "enum Shape {
+    Circle(i64)
+    Rect(i64, i64)
+}
+def main(): i64 {
+    x = 5
+    width = 1
+    match Shape.Rect(3, 4) {
+        Rect(x, y) {
+            width = x * y
+        }
+        Circle(width) {
+            x = width
+        }
+    }
+    return x * 100 + width
+}
+", 512),
+    ("vectors", "def main(): i64 {
+    values = vec[1, 2, 3]
+    push(values, 10)
+    last = pop(values)
+    push(values, last * 2)
+    values[0] = 5
+    total = 0
+    for v in values {
+        total += v
+    }
+    return total * 10 + len(values)
+}
+", 304),
+    ("boxes", "def bump(b: box[i64]) {
+    *b += 1
+}
+def main(): i64 {
+    b = box(41)
+    bump(b)
+    c = b
+    *c = *c * 2
+    return *b
+}
+", 84),
+    ("nested boxes", "def main(): i64 {
+    inner = box(4)
+    outer = box(inner)
+    **outer += 1
+    nested = box(box(10))
+    return *inner * 100 + **nested
+}
+", 510),
+    ("heap strings", "def main(): i64 {
+    s = string(\"hey\")
+    push(s, '!')
+    return len(s) * 1000 + (s[3] as i64)
+}
+", 4033),
+];
+
+// Parser for one of the programs, struct types can't be declared in source yet so they are registered here
+pub fn parser(source: &str) -> Parser {
+    let mut parser = Parser::new(source.to_string());
+    let i64_type = DataType::primitive("i64");
+    let point = DataType::structure("Point", vec![("x", i64_type.clone()), ("y", i64_type)]);
+    let line = DataType::structure("Line", vec![("start", point.clone()), ("end", point.clone())]);
+    for data_type in [point, line] {
+        parser.data_types.insert(data_type.symbol.clone(), data_type);
+    }
+    parser
+}
+
+// Compiles a program into module, verified and then optimized at options.optimization
+#[cfg(feature = "llvm")]
+pub fn compile_llvm<'ctx>(context: &'ctx Context, module: Module<'ctx>, source: &str, options: CompilerOptions) -> Module<'ctx> {
+    let mut parser = parser(source);
+    let root = parser.parse().unwrap();
+    let level = options.optimization;
+    let compiler = Compiler::new(context, module, parser.data_types.clone(), options);
+    compiler.compile_root(&root);
+    compiler.module.verify().unwrap();
+    optimize(&compiler.module, level);
+    compiler.module
+}
+
+// What main returns when the program runs in the jit
+#[cfg(feature = "llvm")]
+pub fn run_llvm(source: &str, options: CompilerOptions) -> i64 {
+    let context = Context::create();
+    let module = context.create_module("main");
+    let engine = module.create_jit_execution_engine(engine_level(options.optimization)).unwrap();
+    let module = compile_llvm(&context, module, source, options);
+    map_runtime_functions(&engine, &module);
+    unsafe { engine.get_function::<unsafe extern "C" fn() -> i64>("main").unwrap().call() }
+}
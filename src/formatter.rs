@@ -0,0 +1,329 @@
+use std::error::Error;
+
+use crate::lexing::{Lexer, Token};
+
+const INDENT: &str = "    ";
+
+// Rewrites the file in canonical style, with check it is only compared and true means it was already formatted
+pub fn run(file_path: &str, check: bool) -> Result<bool, Box<dyn Error>> {
+    let source = std::fs::read_to_string(file_path)?;
+    let formatted = format(&source);
+    if formatted == source {
+        return Ok(true);
+    }
+    if !check {
+        std::fs::write(file_path, formatted)?;
+    }
+    Ok(false)
+}
+
+// Re-prints the token stream line by line, statements end at newlines so the lines themselves are kept
+pub fn format(source: &str) -> String {
+    let mut lexer = Lexer::with_comments(source.to_string());
+    let mut lines: Vec<Vec<Token>> = vec![Vec::new()];
+    let mut token = lexer.next();
+    while token != Token::EOF {
+        match token {
+            Token::EOL => lines.push(Vec::new()),
+            token => lines.last_mut().unwrap().push(token),
+        }
+        token = lexer.next();
+    }
+
+    let mut output = String::new();
+    let mut depth: usize = 0;
+    let mut blank = false;
+    for line in lines {
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        let closes = line.first() == Some(&Token::ClosedCurly);
+        // At most one blank line in a row, none at the start or end of a block
+        if blank && !output.is_empty() && !output.ends_with("{\n") && !closes {
+            output.push('\n');
+        }
+        blank = false;
+
+        let indent = depth.saturating_sub(closes as usize);
+        output.push_str(&INDENT.repeat(indent));
+        output.push_str(&format_line(&line));
+        output.push('\n');
+        for token in &line {
+            match token {
+                Token::OpenCurly => depth += 1,
+                Token::ClosedCurly => depth = depth.saturating_sub(1),
+                _ => {},
+            }
+        }
+    }
+    output
+}
+
+fn format_line(tokens: &[Token]) -> String {
+    let mut line = String::new();
+    // Open brackets, a colon directly inside [ ] is an array size and stays tight
+    let mut brackets = Vec::new();
+    let mut previous: Option<&Token> = None;
+    let mut previous_unary = false;
+    for token in tokens {
+        let unary = is_prefix(token, previous);
+        if let Some(previous) = previous {
+            if space_between(previous, previous_unary, token, brackets.last()) {
+                line.push(' ');
+            }
+        }
+        line.push_str(&token_text(token));
+        match token {
+            Token::OpenParenth | Token::OpenSquare | Token::OpenCurly => brackets.push(token.clone()),
+            Token::CloseParenth | Token::CloseSquare | Token::ClosedCurly => {
+                brackets.pop();
+            },
+            _ => {},
+        }
+        previous = Some(token);
+        previous_unary = unary;
+    }
+    line
+}
+
+// Ex: the - in x = -y or the & in x: &i64
+fn is_prefix(token: &Token, previous: Option<&Token>) -> bool {
+    if !matches!(token, Token::Minus | Token::Star | Token::Ampersand | Token::Tilde) {
+        return false;
+    }
+    match previous {
+        None => true,
+        Some(Token::Identifier(_) | Token::String(_) | Token::Char(_) | Token::Integer(_) | Token::Float(_)) => false,
+        Some(Token::CloseParenth | Token::CloseSquare) => false,
+        Some(_) => true,
+    }
+}
+
+fn space_between(previous: &Token, previous_unary: bool, token: &Token, bracket: Option<&Token>) -> bool {
+    if previous_unary {
+        return false;
+    }
+    match (previous, token) {
+        (Token::OpenParenth | Token::OpenSquare | Token::Dot, _) => false,
+        (Token::OpenCurly, Token::ClosedCurly) => false,
+        (_, Token::CloseParenth | Token::CloseSquare | Token::Comma | Token::Dot) => false,
+        (_, Token::Colon) => false,
+        (Token::Colon, _) => bracket != Some(&Token::OpenSquare),
+        // Calls, casts and indexing, Ex: char(90), values[0], def first[T](..), static_assert(..)
+        (Token::Identifier(_) | Token::CloseParenth | Token::CloseSquare | Token::StaticAssert, Token::OpenParenth | Token::OpenSquare) => false,
+        _ => true,
+    }
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Identifier(name) => name.clone(),
+        Token::String(value) => format!("\"{}\"", escape(value, '"')),
+        Token::Char(value) => format!("'{}'", escape(&(*value as char).to_string(), '\'')),
+        Token::Integer(value) => value.to_string(),
+        // Floats always keep a decimal point so they lex as floats again
+        Token::Float(value) if value.fract() == 0.0 => format!("{}.0", value),
+        Token::Float(value) => value.to_string(),
+        Token::Comment(text) => format!("//{}", text),
+        Token::Def => "def".to_string(),
+        Token::Pub => "pub".to_string(),
+        Token::Extern => "extern".to_string(),
+        Token::Import => "import".to_string(),
+        Token::As => "as".to_string(),
+        Token::Return => "return".to_string(),
+        Token::OpenCurly => "{".to_string(),
+        Token::ClosedCurly => "}".to_string(),
+        Token::OpenSquare => "[".to_string(),
+        Token::CloseSquare => "]".to_string(),
+        Token::OpenParenth => "(".to_string(),
+        Token::CloseParenth => ")".to_string(),
+        Token::Plus => "+".to_string(),
+        Token::Minus => "-".to_string(),
+        Token::Star => "*".to_string(),
+        Token::Slash => "/".to_string(),
+        Token::Percent => "%".to_string(),
+        Token::Ampersand => "&".to_string(),
+        Token::Pipe => "|".to_string(),
+        Token::Caret => "^".to_string(),
+        Token::Tilde => "~".to_string(),
+        Token::ShiftLeft => "<<".to_string(),
+        Token::ShiftRight => ">>".to_string(),
+        Token::Greater => ">".to_string(),
+        Token::Lesser => "<".to_string(),
+        Token::GreaterEqual => ">=".to_string(),
+        Token::LesserEqual => "<=".to_string(),
+        Token::DoubleEqual => "==".to_string(),
+        Token::NotEqual => "!=".to_string(),
+        Token::If => "if".to_string(),
+        Token::Const => "const".to_string(),
+        Token::StaticAssert => "static_assert".to_string(),
+        Token::Enum => "enum".to_string(),
+        Token::Match => "match".to_string(),
+        Token::Else => "else".to_string(),
+        Token::For => "for".to_string(),
+        Token::In => "in".to_string(),
+        Token::Equal => "=".to_string(),
+        Token::PlusEqual => "+=".to_string(),
+        Token::MinusEqual => "-=".to_string(),
+        Token::StarEqual => "*=".to_string(),
+        Token::SlashEqual => "/=".to_string(),
+        Token::PercentEqual => "%=".to_string(),
+        Token::AmpersandEqual => "&=".to_string(),
+        Token::PipeEqual => "|=".to_string(),
+        Token::CaretEqual => "^=".to_string(),
+        Token::ShiftLeftEqual => "<<=".to_string(),
+        Token::ShiftRightEqual => ">>=".to_string(),
+        Token::Colon => ":".to_string(),
+        Token::Dot => ".".to_string(),
+        Token::Comma => ",".to_string(),
+        Token::EOL => "\n".to_string(),
+        Token::EOF => String::new(),
+    }
+}
+
+// The lexer only understands \\, \n and an escaped double quote
+fn escape(value: &str, quote: char) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quote == '"' => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::Parser;
+
+    const MESSY: &str = "
+// Shapes we know about
+enum Shape {
+  Circle(f64)
+    Rect(f64,f64)
+}
+const N:i64=4
+static_assert( N>2 , \"need room\" )
+total:i64=0
+
+
+def area( s:Shape ):f64{
+match s {
+Circle(r) {
+return r*r*3.0 // close enough
+}
+Rect(w,h) {
+return w*h
+}
+}
+}
+
+def first[T]( values:[T:2] ):T{
+    return values[ 0 ]
+}
+def apply(f:fn(i64):i64,x:i64):i64{
+    return f( x )
+}
+def twice(x:i64):i64{
+
+    return x*2
+
+}
+def main():char{
+    c=\"hi\\n\"
+    c[0]=char(90)
+    letters:[char:4]=['a','b','c','d']
+    p:&i64=&total
+    *p+=-1
+    x = -(~3) & 7 << 1
+    for v in vec[ 1,2 ] {
+        total+=v
+    }
+    if x>=2 {
+        x=apply( twice , x )as i64
+    }
+    return char( 3 )
+}
+";
+
+    const FORMATTED: &str = "// Shapes we know about
+enum Shape {
+    Circle(f64)
+    Rect(f64, f64)
+}
+const N: i64 = 4
+static_assert(N > 2, \"need room\")
+total: i64 = 0
+
+def area(s: Shape): f64 {
+    match s {
+        Circle(r) {
+            return r * r * 3.0 // close enough
+        }
+        Rect(w, h) {
+            return w * h
+        }
+    }
+}
+
+def first[T](values: [T:2]): T {
+    return values[0]
+}
+def apply(f: fn(i64): i64, x: i64): i64 {
+    return f(x)
+}
+def twice(x: i64): i64 {
+    return x * 2
+}
+def main(): char {
+    c = \"hi\\n\"
+    c[0] = char(90)
+    letters: [char:4] = ['a', 'b', 'c', 'd']
+    p: &i64 = &total
+    *p += -1
+    x = -(~3) & 7 << 1
+    for v in vec[1, 2] {
+        total += v
+    }
+    if x >= 2 {
+        x = apply(twice, x) as i64
+    }
+    return char(3)
+}
+";
+
+    fn code_tokens(source: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new(source.to_string());
+        let mut tokens = Vec::new();
+        let mut token = lexer.next();
+        while token != Token::EOF {
+            if token != Token::EOL {
+                tokens.push(token);
+            }
+            token = lexer.next();
+        }
+        tokens
+    }
+
+    #[test]
+    fn prints_canonical_style() {
+        assert_eq!(format(MESSY), FORMATTED);
+    }
+
+    #[test]
+    fn is_idempotent() {
+        assert_eq!(format(FORMATTED), FORMATTED);
+        assert_eq!(format(&format(MESSY)), format(MESSY));
+    }
+
+    #[test]
+    fn keeps_the_program() {
+        assert_eq!(code_tokens(&format(MESSY)), code_tokens(MESSY));
+        assert!(Parser::new(FORMATTED.to_string()).parse().is_ok());
+    }
+}
@@ -0,0 +1,222 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::ast::{DataType, DataTypeEnum, Expression, BinaryExpressionType, UnaryExpressionType, is_builtin};
+
+use super::{Interpreter, InterpretResult, Value, Pointer, binary, signed, unsigned};
+
+impl<'a> Interpreter<'a> {
+    pub fn evaluate(&mut self, expression: &Expression) -> InterpretResult<Value> {
+        match expression {
+            Expression::Binary(Some(left), Some(right), operation) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                self.binary(operation, left, right, self.options.overflow_checks)
+            },
+            Expression::Binary(..) | Expression::Unary(None, _) => Err(self.error("incomplete expression")),
+            Expression::Unary(Some(interior), operation) => match operation {
+                UnaryExpressionType::Reference => Ok(Value::Pointer(self.location(interior)?)),
+                UnaryExpressionType::Dereference => {
+                    let pointer = self.pointer(interior)?;
+                    Ok(pointer.load())
+                },
+                UnaryExpressionType::BitwiseNot => match self.evaluate(interior)?.as_integer() {
+                    Some((bits, width)) => Ok(Value::from_integer(!bits, width)),
+                    None => Err(self.error("~ needs an integer")),
+                },
+                UnaryExpressionType::Negation => match self.evaluate(interior)? {
+                    Value::Float(value) => Ok(Value::Float(-value)),
+                    value => match value.as_integer() {
+                        Some((bits, width)) => Ok(Value::from_integer(bits.wrapping_neg(), width)),
+                        None => Err(self.error("- needs a number")),
+                    },
+                },
+            },
+            Expression::VariableRead(name) => Ok(self.variable(name)?.cell.borrow().clone()),
+            Expression::IntegerLiteral(literal) => Ok(Value::Int(*literal)),
+            Expression::FloatLiteral(literal) => Ok(Value::Float(*literal)),
+            Expression::StringLiteral(str) => Ok(Value::Array(str.bytes().map(Value::Char).collect())),
+            Expression::CharLiteral(c) => Ok(Value::Char(*c)),
+            Expression::Array(values) => {
+                let values = values.iter().map(|value| self.evaluate(value)).collect::<InterpretResult<Vec<_>>>()?;
+                Ok(Value::Array(values))
+            },
+            Expression::VectorLiteral(values) => {
+                let values = values.iter().map(|value| self.evaluate(value)).collect::<InterpretResult<Vec<_>>>()?;
+                Ok(Value::vector(values))
+            },
+            Expression::FieldAccess(..) | Expression::VariableExtract(..) => Ok(self.location(expression)?.load()),
+            Expression::FunctionCall(name, args) if is_builtin(name) => self.builtin(name, args),
+            Expression::FunctionCall(name, args) => {
+                let args = args.iter().map(|arg| self.evaluate(arg)).collect::<InterpretResult<Vec<_>>>()?;
+                self.call_value(name, args)
+            },
+            Expression::IndirectCall(callee, args) => {
+                let Value::Function(name) = self.evaluate(callee)? else {
+                    return Err(self.error("called a null function pointer"));
+                };
+                let args = args.iter().map(|arg| self.evaluate(arg)).collect::<InterpretResult<Vec<_>>>()?;
+                self.call_value(&name, args)
+            },
+            Expression::FunctionReference(name, _) => Ok(Value::Function(name.clone())),
+            Expression::ExpressionCast(interior, resultant) => {
+                let Some((bits, width)) = self.evaluate(interior)?.as_integer() else {
+                    return Err(self.error("only integers can be cast"));
+                };
+                match resultant.as_str() {
+                    "f64" => Ok(Value::Float(signed(bits, width) as f64)),
+                    "i64" => Ok(Value::Int(signed(bits, width))),
+                    "char" => Ok(Value::Char(bits as u8)),
+                    _ => Err(self.error(&format!("can't cast to {}", resultant))),
+                }
+            },
+            Expression::EnumVariant(name, variant, payload) => {
+                let Some((tag, _)) = self.data_types.get(name).and_then(|enum_type| enum_type.variant(variant)) else {
+                    return Err(self.error(&format!("unknown variant {}.{}", name, variant)));
+                };
+                let payload = payload.iter().map(|value| self.evaluate(value)).collect::<InterpretResult<Vec<_>>>()?;
+                Ok(Value::Enum(tag, payload))
+            },
+        }
+    }
+
+    // Where an expression that can be assigned to or referenced is stored
+    pub fn location(&mut self, expression: &Expression) -> InterpretResult<Pointer> {
+        match expression {
+            Expression::VariableRead(name) => Ok(Pointer::new(self.variable(name)?.cell.clone())),
+            Expression::VariableExtract(base, slot) => {
+                let base = self.place(base)?;
+                let index = self.evaluate(slot)?;
+                // The elements of a vector are behind its handle
+                let handle = base.read(|value| match value {
+                    Value::Array(_) => Ok(None),
+                    Value::Vector(handle) => Ok(Some(handle.clone())),
+                    _ => Err(()),
+                });
+                let pointer = match handle {
+                    Ok(handle) => handle.map_or(base, Pointer::new),
+                    Err(()) => return Err(self.error("only arrays and vectors can be indexed")),
+                };
+                let len = pointer.element_count();
+                // Out of bounds accesses are checked even with --release, there is no memory to read past
+                match index.as_integer() {
+                    Some((bits, width)) if (unsigned(bits, width) as usize) < len => Ok(pointer.element(unsigned(bits, width) as usize)),
+                    _ => Err(self.error("index out of bounds")),
+                }
+            },
+            Expression::FieldAccess(base, field) => {
+                let data_type = self.location_type(base)?;
+                let Some((index, _)) = data_type.field(field) else {
+                    return Err(self.error(&format!("{} has no field {}", data_type.symbol, field)));
+                };
+                Ok(self.location(base)?.element(index as usize))
+            },
+            Expression::Unary(Some(interior), UnaryExpressionType::Dereference) => self.pointer(interior),
+            _ => Err(self.error("expression can't be assigned to")),
+        }
+    }
+
+    // Like location, values that aren't stored anywhere are copied into a new cell, Ex: f()[0]
+    fn place(&mut self, expression: &Expression) -> InterpretResult<Pointer> {
+        match expression {
+            Expression::VariableRead(_) | Expression::VariableExtract(..) | Expression::FieldAccess(..) |
+            Expression::Unary(Some(_), UnaryExpressionType::Dereference) => self.location(expression),
+            _ => Ok(Pointer::new(Rc::new(RefCell::new(self.evaluate(expression)?)))),
+        }
+    }
+
+    // Type of what location() points at, the base of a field can be any place
+    fn location_type(&self, expression: &Expression) -> InterpretResult<DataType> {
+        let data_type = match expression {
+            Expression::VariableRead(name) => Some(self.variable(name)?.data_type.clone()),
+            Expression::VariableExtract(base, _) => self.location_type(base)?.element_type().cloned(),
+            Expression::FieldAccess(base, field) => self.location_type(base)?.field(field).map(|(_, data_type)| data_type.clone()),
+            Expression::Unary(Some(interior), UnaryExpressionType::Dereference) => match self.location_type(interior)?.value {
+                DataTypeEnum::Pointer(interior) | DataTypeEnum::Heap(interior) => Some(*interior),
+                _ => None,
+            },
+            _ => None,
+        };
+        data_type.ok_or_else(|| self.error("expression can't be assigned to"))
+    }
+
+    fn pointer(&mut self, expression: &Expression) -> InterpretResult<Pointer> {
+        match self.evaluate(expression)? {
+            Value::Pointer(pointer) => Ok(pointer),
+            _ => Err(self.error("null pointer dereference")),
+        }
+    }
+
+    fn call_value(&mut self, name: &str, args: Vec<Value>) -> InterpretResult<Value> {
+        let line = self.current_line;
+        let result = self.call(name, args)?;
+        self.current_line = line;
+        Ok(result.unwrap_or(Value::Void))
+    }
+
+    pub fn binary(&self, operation: &BinaryExpressionType, left: Value, right: Value, checked: bool) -> InterpretResult<Value> {
+        binary(operation, left, right, checked).map_err(|message| self.error(&message))
+    }
+
+    fn builtin(&mut self, name: &str, args: &[Box<Expression>]) -> InterpretResult<Value> {
+        match name {
+            "box" => {
+                let value = self.evaluate(&args[0])?;
+                Ok(Value::Pointer(Pointer::new(Rc::new(RefCell::new(value)))))
+            },
+            "push" => {
+                let handle = self.vector(&args[0])?;
+                let value = self.evaluate(&args[1])?;
+                handle.borrow_mut().elements_mut().push(value);
+                Ok(Value::Void)
+            },
+            "pop" => {
+                let handle = self.vector(&args[0])?;
+                let value = handle.borrow_mut().elements_mut().pop();
+                value.ok_or_else(|| self.error("Popped from an empty vector"))
+            },
+            "len" => match self.evaluate(&args[0])? {
+                Value::Array(elements) => Ok(Value::Int(elements.len() as i64)),
+                Value::Vector(handle) => Ok(Value::Int(handle.borrow().elements().len() as i64)),
+                _ => Err(self.error("len needs an array or vector")),
+            },
+            "string" => match args.first().map(|arg| &**arg) {
+                Some(Expression::StringLiteral(value)) => Ok(Value::vector(value.bytes().map(Value::Char).collect())),
+                _ => Err(self.error("string needs a string literal")),
+            },
+            _ if name.starts_with("wrapping_") || name.starts_with("checked_") => {
+                let left = self.evaluate(&args[0])?;
+                let right = self.evaluate(&args[1])?;
+                let (mode, operation) = name.split_once('_').unwrap();
+                let operation = match operation {
+                    "add" => BinaryExpressionType::Addition,
+                    "sub" => BinaryExpressionType::Subtraction,
+                    "mul" => BinaryExpressionType::Multiplication,
+                    "div" => BinaryExpressionType::Division,
+                    _ => unreachable!()
+                };
+                if mode == "wrapping" {
+                    return self.binary(&operation, left, right, false);
+                }
+                let Value::Pointer(out) = self.evaluate(&args[2])? else {
+                    return Err(self.error(&format!("{} needs a pointer to store the result", name)));
+                };
+                let fits = match binary(&operation, left, right, true) {
+                    Ok(value) => {
+                        out.store(value);
+                        true
+                    },
+                    Err(_) => false,
+                };
+                Ok(Value::Bool(fits))
+            },
+            _ => Err(self.error(&format!("unknown builtin {}", name))),
+        }
+    }
+
+    fn vector(&mut self, expression: &Expression) -> InterpretResult<Rc<RefCell<Value>>> {
+        match self.evaluate(expression)? {
+            Value::Vector(handle) => Ok(handle),
+            _ => Err(self.error("null pointer dereference")),
+        }
+    }
+}
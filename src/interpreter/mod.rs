@@ -0,0 +1,226 @@
+mod expression;
+mod operations;
+mod statement;
+mod value;
+
+pub use operations::binary;
+pub use value::*;
+
+use std::{cell::RefCell, collections::HashMap, error::Error, fmt::Display, rc::Rc};
+
+use crate::ast::{CompilerOptions, DataType, Function, Item, RootScope};
+
+pub type InterpretResult<T> = Result<T, RuntimeError>;
+
+// Reported the same way as the checks compiled into LLVM programs
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl Error for RuntimeError {}
+
+// Storage of a variable, pointers to it share the cell
+#[derive(Clone)]
+struct Slot {
+    cell: Rc<RefCell<Value>>,
+    data_type: DataType,
+}
+
+impl Slot {
+    fn new(value: Value, data_type: DataType) -> Self {
+        Self { cell: Rc::new(RefCell::new(value)), data_type }
+    }
+}
+
+// Runs the tree directly, the backend used when LLVM isn't available
+pub struct Interpreter<'a> {
+    functions: HashMap<String, &'a Function>,
+    globals: HashMap<String, Slot>,
+    // Variables of the functions being called, innermost last
+    frames: Vec<HashMap<String, Slot>>,
+    data_types: HashMap<String, DataType>,
+    options: CompilerOptions,
+    current_line: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(root: &'a RootScope, data_types: HashMap<String, DataType>, options: CompilerOptions) -> InterpretResult<Self> {
+        let mut interpreter = Self {
+            functions: HashMap::new(),
+            globals: HashMap::new(),
+            frames: Vec::new(),
+            data_types,
+            options,
+            current_line: 0,
+        };
+        for item in &root.items {
+            if let Item::Function(function) = item {
+                if function.external {
+                    interpreter.current_line = function.line;
+                    return Err(interpreter.error(&format!("extern function {} needs the llvm backend", function.name)));
+                }
+                interpreter.functions.insert(function.name.clone(), function);
+            }
+        }
+        for item in &root.items {
+            if let Item::Global(global) = item {
+                let value = match global.initializer {
+                    Some(ref initializer) => interpreter.evaluate(initializer)?,
+                    None => Value::zero(&global.data_type),
+                };
+                interpreter.globals.insert(global.name.clone(), Slot::new(value, global.data_type.clone()));
+            }
+        }
+        Ok(interpreter)
+    }
+
+    // None for functions without a return type
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> InterpretResult<Option<Value>> {
+        let Some(function) = self.functions.get(name).copied() else {
+            return Err(self.error(&format!("unknown function {}", name)));
+        };
+        let mut frame = HashMap::new();
+        for ((param, data_type), value) in function.params.iter().zip(args) {
+            frame.insert(param.clone(), Slot::new(value, data_type.clone()));
+        }
+        self.frames.push(frame);
+        let flow = self.execute_body(&function.body);
+        self.frames.pop();
+        match flow? {
+            Some(value) => Ok(Some(value)),
+            None if function.return_type.is_none() => Ok(None),
+            None => Err(self.error(&format!("{} ended without returning a value", name))),
+        }
+    }
+
+    fn error(&self, message: &str) -> RuntimeError {
+        RuntimeError {
+            file: self.options.source_name.clone(),
+            line: self.current_line,
+            message: message.to_string(),
+        }
+    }
+
+    fn variable(&self, name: &str) -> InterpretResult<&Slot> {
+        let local = self.frames.last().and_then(|frame| frame.get(name));
+        local.or_else(|| self.globals.get(name)).ok_or_else(|| self.error(&format!("unknown variable {}", name)))
+    }
+
+    fn define(&mut self, name: &str, value: Value, data_type: &DataType) {
+        let frame = self.frames.last_mut().expect("statements only run inside functions");
+        frame.insert(name.to_string(), Slot::new(value, data_type.clone()));
+    }
+
+    // The local a loop variable or match binding hides, put back by restore
+    fn shadowed(&self, name: &str) -> Option<Slot> {
+        self.frames.last().and_then(|frame| frame.get(name).cloned())
+    }
+
+    fn restore(&mut self, name: &str, shadowed: Option<Slot>) {
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        match shadowed {
+            Some(slot) => frame.insert(name.to_string(), slot),
+            None => frame.remove(name),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{conformance, parsing::Parser};
+
+    fn run(source: &str, options: CompilerOptions) -> InterpretResult<Option<Value>> {
+        let mut parser = conformance::parser(source);
+        let root = parser.parse().unwrap();
+        Interpreter::new(&root, parser.data_types.clone(), options)?.call("main", Vec::new())
+    }
+
+    #[test]
+    fn passes_conformance_suite() {
+        for (name, source, expected) in conformance::PROGRAMS {
+            let result = run(source, CompilerOptions::default());
+            assert_eq!(result.unwrap(), Some(Value::Int(*expected)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn restores_shadowed_variables() {
+        let source = "enum Shape {\n    Rect(i64, i64)\n}\ndef first(values: [i64:2]): i64 {\n    v = 5\n    for v in values {\n        return v\n    }\n    return v\n}\ndef main(): i64 {\n    v = 5\n    for v in [1, 2] {\n        v = v\n    }\n    x = 3\n    match Shape.Rect(1, 2) {\n        Rect(x, y) {\n            v += x\n        }\n    }\n    return v * 100 + x * 10 + first([7, 8])\n}\n";
+        assert_eq!(run(source, CompilerOptions::default()).unwrap(), Some(Value::Int(637)));
+    }
+
+    #[test]
+    fn reports_runtime_checks_with_their_line() {
+        let source = "def main(): i64 {\n    values = [1, 2, 3]\n    i = 3\n    return values[i]\n}\n";
+        let error = run(source, CompilerOptions::default()).err().unwrap();
+        assert_eq!(error.to_string(), "main:4: index out of bounds");
+
+        let source = "def main(): i64 {\n    x = 9223372036854775807\n    return x + 1\n}\n";
+        assert_eq!(run(source, CompilerOptions::default()).unwrap(), Some(Value::Int(i64::MIN)));
+        let options = CompilerOptions { overflow_checks: true, ..Default::default() };
+        assert_eq!(run(source, options).err().unwrap().message, "integer overflow");
+    }
+
+    #[test]
+    fn rejects_extern_functions() {
+        let source = "extern def labs(x: i64): i64\ndef main(): i64 {\n    return labs(-5)\n}\n";
+        let error = run(source, CompilerOptions::default()).err().unwrap();
+        assert_eq!((error.line, error.message.as_str()), (1, "extern function labs needs the llvm backend"));
+    }
+
+    #[test]
+    fn runs_overflow_builtins() {
+        let cases: &[(&str, Result<i64, &str>)] = &[
+            ("return wrapping_add(9223372036854775807, 1)", Ok(i64::MIN)),
+            ("return wrapping_div(-9223372036854775807 - 1, -1)", Ok(i64::MIN)),
+            ("return wrapping_div(1, 0)", Err("division by zero")),
+        ];
+        for (body, expected) in cases {
+            let source = format!("def main(): i64 {{\n    {}\n}}\n", body);
+            let result = run(&source, CompilerOptions::default()).map_err(|error| error.message);
+            assert_eq!(result, expected.map(|value| Some(Value::Int(value))).map_err(str::to_string), "{}", body);
+        }
+
+        // checked_* store the result and report whether it fit instead of trapping
+        let checked = [
+            ("x = 7", "checked_mul(3, 4, &x)", 112),
+            ("x = 7", "checked_add(9223372036854775807, 1, &x)", 7),
+            ("x = 7", "checked_div(-9223372036854775807 - 1, -1, &x)", 7),
+            ("x = 7", "checked_div(1, 0, &x)", 7),
+            // char is unsigned
+            ("x = char(7)", "checked_add(char(100), char(100), &x)", 300),
+            ("x = char(7)", "checked_sub(char(1), char(2), &x)", 7),
+        ];
+        for (declaration, call, expected) in checked {
+            let source = format!("def main(): i64 {{\n    {}\n    if {} {{\n        return ((x as i64) & 255) + 100\n    }}\n    return (x as i64) & 255\n}}\n", declaration, call);
+            assert_eq!(run(&source, CompilerOptions::default()).unwrap(), Some(Value::Int(expected)), "{}", call);
+        }
+    }
+
+    #[test]
+    fn accesses_fields_of_nested_places() {
+        let source = "outer: Outer\nitems: [Point:2]\ndef main(): i64 {\n    outer.inner.c = 5\n    items[1].y = 7\n    return outer.inner.c * 10 + items[1].y\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        let point = DataType::structure("Point", vec![("x", DataType::primitive("i64")), ("y", DataType::primitive("i64"))]);
+        let inner = DataType::structure("Inner", vec![("c", DataType::primitive("i64"))]);
+        let outer = DataType::structure("Outer", vec![("inner", inner.clone())]);
+        for data_type in [point, inner, outer] {
+            parser.data_types.insert(data_type.symbol.clone(), data_type);
+        }
+        let root = parser.parse().unwrap();
+        let result = Interpreter::new(&root, parser.data_types.clone(), CompilerOptions::default()).unwrap().call("main", Vec::new());
+        assert_eq!(result.unwrap(), Some(Value::Int(57)));
+    }
+}
@@ -0,0 +1,107 @@
+use std::cmp::Ordering;
+
+use crate::ast::BinaryExpressionType;
+
+use super::{Value, signed, unsigned};
+
+// Shared by every backend without LLVM, errors are the message of the runtime check that failed
+pub fn binary(operation: &BinaryExpressionType, left: Value, right: Value, checked: bool) -> Result<Value, String> {
+    if let (Value::Float(left), Value::Float(right)) = (&left, &right) {
+        return float_binary(operation, *left, *right);
+    }
+    let is_unsigned = matches!(left, Value::Char(_) | Value::Bool(_));
+    match (left.as_integer(), right.as_integer()) {
+        (Some(left), Some(right)) if left.1 == right.1 => integer_binary(operation, left, right, is_unsigned, checked),
+        _ => Err(format!("mismatched operands {:?} and {:?}", left, right)),
+    }
+}
+
+// char and bool divide and shift unsigned, every comparison is signed
+fn integer_binary(operation: &BinaryExpressionType, left: (i64, u32), right: (i64, u32), is_unsigned: bool, checked: bool) -> Result<Value, String> {
+    let width = left.1;
+    let (l, r) = (signed(left.0, width), signed(right.0, width));
+    let fits = |value: i128| match is_unsigned {
+        true => value >= 0 && value < (1i128 << width),
+        false => value >= -(1i128 << (width - 1)) && value < (1i128 << (width - 1)),
+    };
+    let bits = match operation {
+        BinaryExpressionType::Addition | BinaryExpressionType::Subtraction | BinaryExpressionType::Multiplication => {
+            let (wide_l, wide_r) = match is_unsigned {
+                true => (unsigned(left.0, width) as i128, unsigned(right.0, width) as i128),
+                false => (l as i128, r as i128),
+            };
+            let exact = match operation {
+                BinaryExpressionType::Addition => wide_l + wide_r,
+                BinaryExpressionType::Subtraction => wide_l - wide_r,
+                _ => wide_l * wide_r,
+            };
+            if checked && !fits(exact) {
+                return Err("integer overflow".to_string());
+            }
+            exact as i64
+        },
+        // Division by zero is always reported, unchecked the smallest value divided by -1 wraps
+        BinaryExpressionType::Division | BinaryExpressionType::Modulo => {
+            if r == 0 {
+                return Err("division by zero".to_string());
+            }
+            let division = matches!(operation, BinaryExpressionType::Division);
+            if is_unsigned {
+                let (l, r) = (unsigned(left.0, width), unsigned(right.0, width));
+                (if division { l / r } else { l % r }) as i64
+            } else if checked && !fits(l as i128 / r as i128) {
+                return Err("integer overflow".to_string());
+            } else if division {
+                l.wrapping_div(r)
+            } else {
+                l.wrapping_rem(r)
+            }
+        },
+        BinaryExpressionType::BitwiseAnd => l & r,
+        BinaryExpressionType::BitwiseOr => l | r,
+        BinaryExpressionType::BitwiseXor => l ^ r,
+        BinaryExpressionType::ShiftLeft | BinaryExpressionType::ShiftRight => {
+            let amount = unsigned(right.0, width);
+            if checked && amount >= width as u64 {
+                return Err("shift amount out of range".to_string());
+            }
+            let amount = (amount % width as u64) as u32;
+            match operation {
+                BinaryExpressionType::ShiftLeft => l << amount,
+                _ if is_unsigned => (unsigned(left.0, width) >> amount) as i64,
+                _ => l >> amount,
+            }
+        },
+        _ => return Ok(Value::Bool(compare(operation, l.partial_cmp(&r)))),
+    };
+    Ok(Value::from_integer(bits, width))
+}
+
+fn float_binary(operation: &BinaryExpressionType, left: f64, right: f64) -> Result<Value, String> {
+    let value = match operation {
+        BinaryExpressionType::Addition => left + right,
+        BinaryExpressionType::Subtraction => left - right,
+        BinaryExpressionType::Multiplication => left * right,
+        BinaryExpressionType::Division => left / right,
+        BinaryExpressionType::Modulo => left % right,
+        _ if operation.is_comparison() => return Ok(Value::Bool(compare(operation, left.partial_cmp(&right)))),
+        _ => return Err(format!("{:?} needs integers", operation)),
+    };
+    Ok(Value::Float(value))
+}
+
+// Comparisons involving NaN are false, like LLVM's ordered predicates
+fn compare(operation: &BinaryExpressionType, ordering: Option<Ordering>) -> bool {
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match operation {
+        BinaryExpressionType::Equal => ordering == Ordering::Equal,
+        BinaryExpressionType::NotEqual => ordering != Ordering::Equal,
+        BinaryExpressionType::Less => ordering == Ordering::Less,
+        BinaryExpressionType::LessEqual => ordering != Ordering::Greater,
+        BinaryExpressionType::Greater => ordering == Ordering::Greater,
+        BinaryExpressionType::GreaterEqual => ordering != Ordering::Less,
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,120 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::ast::{ForLoop, InsertVariable, MatchStatement, SetVariable, Stmt};
+
+use super::{Interpreter, InterpretResult, Pointer, Slot, Value};
+
+impl<'a> Interpreter<'a> {
+    // Some once a return statement ran, the rest of the body is skipped
+    pub fn execute_body(&mut self, body: &[Stmt]) -> InterpretResult<Option<Value>> {
+        for stmt in body {
+            if let Some(value) = self.execute(stmt)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn execute(&mut self, stmt: &Stmt) -> InterpretResult<Option<Value>> {
+        match stmt {
+            Stmt::Set(set) => self.execute_set_variable(set),
+            Stmt::Insert(insert) => self.execute_insert_variable(insert),
+            Stmt::Return(ret) => Ok(Some(self.evaluate(&ret.value)?)),
+            Stmt::If(condition) => {
+                self.current_line = condition.line;
+                let Some((bits, _)) = self.evaluate(&condition.condition)?.as_integer() else {
+                    return Err(self.error("if needs a condition"));
+                };
+                if bits == 0 {
+                    return Ok(None);
+                }
+                self.execute_body(&condition.body)
+            },
+            Stmt::For(for_loop) => self.execute_for(for_loop),
+            Stmt::Match(statement) => self.execute_match(statement),
+            Stmt::Expression(expression) => {
+                self.evaluate(expression)?;
+                Ok(None)
+            },
+            Stmt::Located(located) => {
+                self.current_line = located.line;
+                self.execute(&located.statement)
+            },
+        }
+    }
+
+    // Assigning an existing variable keeps its cell, pointers to it see the new value
+    fn execute_set_variable(&mut self, set: &SetVariable) -> InterpretResult<Option<Value>> {
+        let value = self.evaluate(&set.value)?;
+        match self.variable(&set.name).ok().map(|slot| slot.cell.clone()) {
+            Some(cell) => *cell.borrow_mut() = value,
+            None => self.define(&set.name, value, &set.data_type),
+        }
+        Ok(None)
+    }
+
+    fn execute_insert_variable(&mut self, insert: &InsertVariable) -> InterpretResult<Option<Value>> {
+        if let Some(ref operation) = insert.operation {
+            let location = self.location(&insert.location)?;
+            let value = self.evaluate(&insert.value)?;
+            let result = self.binary(operation, location.load(), value, self.options.overflow_checks)?;
+            location.store(result);
+            return Ok(None);
+        }
+        let value = self.evaluate(&insert.value)?;
+        self.location(&insert.location)?.store(value);
+        Ok(None)
+    }
+
+    fn execute_for(&mut self, for_loop: &ForLoop) -> InterpretResult<Option<Value>> {
+        self.current_line = for_loop.line;
+        // Elements are read every iteration, so writes and pushes made by the body are seen
+        let elements = match self.location(&for_loop.iterable) {
+            Ok(location) => match location.load() {
+                Value::Vector(handle) => Pointer::new(handle),
+                _ => location,
+            },
+            Err(_) => match self.evaluate(&for_loop.iterable)? {
+                Value::Vector(handle) => Pointer::new(handle),
+                value => Pointer::new(Rc::new(RefCell::new(value))),
+            },
+        };
+        let shadowed = self.shadowed(&for_loop.variable);
+        let result = self.iterate(for_loop, &elements);
+        self.restore(&for_loop.variable, shadowed);
+        result
+    }
+
+    fn iterate(&mut self, for_loop: &ForLoop, elements: &Pointer) -> InterpretResult<Option<Value>> {
+        let mut index = 0;
+        while index < elements.element_count() {
+            self.define(&for_loop.variable, elements.element(index).load(), &for_loop.element_type);
+            if let Some(value) = self.execute_body(&for_loop.body)? {
+                return Ok(Some(value));
+            }
+            index += 1;
+        }
+        Ok(None)
+    }
+
+    fn execute_match(&mut self, statement: &MatchStatement) -> InterpretResult<Option<Value>> {
+        self.current_line = statement.line;
+        let Value::Enum(tag, payload) = self.evaluate(&statement.scrutinee)? else {
+            return Err(self.error("match needs an enum"));
+        };
+        let arm = statement.arms.iter().find(|arm| arm.tag == Some(tag))
+            .or_else(|| statement.arms.iter().find(|arm| arm.tag.is_none()));
+        let Some(arm) = arm else {
+            return Err(self.error("no arm matched"));
+        };
+        let shadowed: Vec<Option<Slot>> = arm.bindings.iter().map(|(name, _)| self.shadowed(name)).collect();
+        for ((name, data_type), value) in arm.bindings.iter().zip(payload) {
+            self.define(name, value, data_type);
+        }
+        let result = self.execute_body(&arm.body);
+        for ((name, _), slot) in arm.bindings.iter().zip(shadowed).rev() {
+            self.restore(name, slot);
+        }
+        result
+    }
+}
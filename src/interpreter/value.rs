@@ -0,0 +1,165 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::ast::{DataType, DataTypeEnum};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Char(u8),
+    // Comparisons produce a bool, like LLVM's i1
+    Bool(bool),
+    Array(Vec<Value>),
+    Struct(Vec<Value>),
+    // Tag and payload
+    Enum(u64, Vec<Value>),
+    Pointer(Pointer),
+    // Shared handle, the cell holds an Array so elements can be pointed into
+    Vector(Rc<RefCell<Value>>),
+    Function(String),
+    // Zeroed pointers, vectors and function values
+    Null,
+    // Result of calls to functions without a return type
+    Void,
+}
+
+// A variable or heap cell and the array and struct indices leading into it
+#[derive(Clone, Debug)]
+pub struct Pointer {
+    pub cell: Rc<RefCell<Value>>,
+    pub path: Vec<usize>,
+}
+
+impl PartialEq for Pointer {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.cell, &other.cell) && self.path == other.path
+    }
+}
+
+impl Pointer {
+    pub fn new(cell: Rc<RefCell<Value>>) -> Self {
+        Self { cell, path: Vec::new() }
+    }
+
+    pub fn element(&self, index: usize) -> Pointer {
+        let mut path = self.path.clone();
+        path.push(index);
+        Pointer { cell: self.cell.clone(), path }
+    }
+
+    pub fn load(&self) -> Value {
+        self.read(|value| value.clone())
+    }
+
+    // Number of elements of the array or vector pointed to
+    pub fn element_count(&self) -> usize {
+        self.read(|value| value.elements().len())
+    }
+
+    pub fn read<T>(&self, read: impl FnOnce(&Value) -> T) -> T {
+        let cell = self.cell.borrow();
+        let mut value = &*cell;
+        for index in &self.path {
+            value = &value.elements()[*index];
+        }
+        read(value)
+    }
+
+    pub fn store(&self, new_value: Value) {
+        let mut cell = self.cell.borrow_mut();
+        let mut value = &mut *cell;
+        for index in &self.path {
+            value = &mut value.elements_mut()[*index];
+        }
+        *value = new_value;
+    }
+}
+
+impl Value {
+    // Value of a variable that was declared without one, Ex: buffer: [i64:4]
+    pub fn zero(data_type: &DataType) -> Value {
+        match data_type.value {
+            DataTypeEnum::Primitive => match data_type.symbol.as_str() {
+                "f64" => Value::Float(0.0),
+                "char" => Value::Char(0),
+                "bool" => Value::Bool(false),
+                _ => Value::Int(0),
+            },
+            DataTypeEnum::Array(ref interior, len) => Value::Array(vec![Value::zero(interior); len as usize]),
+            DataTypeEnum::Struct(ref data_types, _) => Value::Struct(data_types.iter().map(|data_type| Value::zero(data_type)).collect()),
+            DataTypeEnum::Enum(ref variants) => Value::Enum(0, variants[0].1.iter().map(Value::zero).collect()),
+            DataTypeEnum::Pointer(_) | DataTypeEnum::Heap(_) | DataTypeEnum::Vector(_) | DataTypeEnum::Function(..) => Value::Null,
+        }
+    }
+
+    pub fn vector(elements: Vec<Value>) -> Value {
+        Value::Vector(Rc::new(RefCell::new(Value::Array(elements))))
+    }
+
+    pub fn elements(&self) -> &Vec<Value> {
+        match self {
+            Value::Array(elements) | Value::Struct(elements) | Value::Enum(_, elements) => elements,
+            _ => panic!("{:?} has no elements", self),
+        }
+    }
+
+    pub fn elements_mut(&mut self) -> &mut Vec<Value> {
+        match self {
+            Value::Array(elements) | Value::Struct(elements) | Value::Enum(_, elements) => elements,
+            _ => panic!("{:?} has no elements", self),
+        }
+    }
+
+    // Raw bits and width, char and bool are computed as 8 and 1 bit integers
+    pub fn as_integer(&self) -> Option<(i64, u32)> {
+        match *self {
+            Value::Int(value) => Some((value, 64)),
+            Value::Char(value) => Some((value as i64, 8)),
+            Value::Bool(value) => Some((value as i64, 1)),
+            _ => None,
+        }
+    }
+
+    // Truncates bits to the width, wrapping like the fixed width LLVM types
+    pub fn from_integer(bits: i64, width: u32) -> Value {
+        match width {
+            64 => Value::Int(bits),
+            8 => Value::Char(bits as u8),
+            _ => Value::Bool(bits & 1 == 1),
+        }
+    }
+}
+
+pub fn signed(bits: i64, width: u32) -> i64 {
+    let shift = 64 - width;
+    (bits << shift) >> shift
+}
+
+pub fn unsigned(bits: i64, width: u32) -> u64 {
+    match width {
+        64 => bits as u64,
+        _ => bits as u64 & ((1 << width) - 1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pointers_reach_into_aggregates() {
+        let cell = Rc::new(RefCell::new(Value::Array(vec![Value::Struct(vec![Value::Int(1), Value::Char(b'a')]), Value::Int(2)])));
+        let field = Pointer::new(cell.clone()).element(0).element(1);
+        field.store(Value::Char(b'z'));
+        assert_eq!(field.load(), Value::Char(b'z'));
+        assert_eq!(Pointer::new(cell).element(1).load(), Value::Int(2));
+    }
+
+    #[test]
+    fn narrow_integers_wrap() {
+        assert_eq!(Value::from_integer(300, 8), Value::Char(44));
+        assert_eq!(signed(200, 8), -56);
+        assert_eq!(unsigned(-1, 8), 255);
+        assert_eq!(Value::from_integer(2, 1), Value::Bool(false));
+    }
+}
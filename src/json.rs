@@ -0,0 +1,319 @@
+use std::{error::Error, fmt::Display, iter::Peekable, str::Chars};
+
+// Just enough JSON for the language server protocol and tree dumps, objects keep their key order
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug)]
+pub enum JsonError {
+    UnexpectedEnd,
+    UnexpectedCharacter(char),
+    InvalidNumber(String),
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::UnexpectedEnd => write!(f, "Unexpected end of JSON"),
+            JsonError::UnexpectedCharacter(c) => write!(f, "Unexpected character {:?} in JSON", c),
+            JsonError::InvalidNumber(number) => write!(f, "Invalid JSON number {}", number),
+        }
+    }
+}
+
+impl Error for JsonError {}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(c) => Err(JsonError::UnexpectedCharacter(c)),
+            None => Ok(value),
+        }
+    }
+
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    // Missing keys read as null so lookups can be chained, Ex: message.get("params").get("textDocument")
+    pub fn get(&self, key: &str) -> &Json {
+        let Json::Object(fields) = self else {
+            return &NULL;
+        };
+        fields.iter().find(|(name, _)| name == key).map_or(&NULL, |(_, value)| value)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(value) if value.fract() == 0.0 => Some(*value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Self {
+        Json::Array(values)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) if value.fract() == 0.0 && value.abs() < 1e15 => write!(f, "{}", *value as i64),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), JsonError> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(JsonError::UnexpectedCharacter(c)),
+        None => Err(JsonError::UnexpectedEnd),
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Json, JsonError> {
+    skip_whitespace(chars);
+    match chars.peek().copied() {
+        None => Err(JsonError::UnexpectedEnd),
+        Some('{') => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                expect(chars, '"')?;
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                expect(chars, ':')?;
+                fields.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {},
+                    Some('}') => return Ok(Json::Object(fields)),
+                    Some(c) => return Err(JsonError::UnexpectedCharacter(c)),
+                    None => return Err(JsonError::UnexpectedEnd),
+                }
+            }
+        },
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Json::Array(values));
+            }
+            loop {
+                values.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {},
+                    Some(']') => return Ok(Json::Array(values)),
+                    Some(c) => return Err(JsonError::UnexpectedCharacter(c)),
+                    None => return Err(JsonError::UnexpectedEnd),
+                }
+            }
+        },
+        Some('"') => {
+            chars.next();
+            Ok(Json::String(parse_string(chars)?))
+        },
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(c) = chars.peek().copied().filter(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                number.push(c);
+                chars.next();
+            }
+            number.parse().map(Json::Number).map_err(|_| JsonError::InvalidNumber(number))
+        },
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(c) = chars.peek().copied().filter(|c| c.is_ascii_alphabetic()) {
+                word.push(c);
+                chars.next();
+            }
+            match word.as_str() {
+                "null" => Ok(Json::Null),
+                "true" => Ok(Json::Bool(true)),
+                "false" => Ok(Json::Bool(false)),
+                _ => Err(JsonError::UnexpectedCharacter(word.chars().next().unwrap_or_else(|| chars.peek().copied().unwrap_or(' ')))),
+            }
+        },
+    }
+}
+
+// The opening quote has already been consumed
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, JsonError> {
+    let mut string = String::new();
+    loop {
+        match chars.next().ok_or(JsonError::UnexpectedEnd)? {
+            '"' => return Ok(string),
+            '\\' => match chars.next().ok_or(JsonError::UnexpectedEnd)? {
+                'n' => string.push('\n'),
+                'r' => string.push('\r'),
+                't' => string.push('\t'),
+                'b' => string.push('\u{8}'),
+                'f' => string.push('\u{c}'),
+                'u' => {
+                    let mut code = parse_hex(chars)?;
+                    // Characters outside the basic plane are written as a surrogate pair
+                    if (0xD800..0xDC00).contains(&code) && chars.next() == Some('\\') && chars.next() == Some('u') {
+                        let low = parse_hex(chars)?;
+                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                    }
+                    string.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                },
+                c => string.push(c),
+            },
+            c => string.push(c),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>) -> Result<u32, JsonError> {
+    let digits: String = (0..4).filter_map(|_| chars.next()).collect();
+    u32::from_str_radix(&digits, 16).map_err(|_| JsonError::InvalidNumber(digits))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_values() {
+        let text = r#"{"id":1,"method":"initialize","params":{"rootUri":null,"capabilities":[true,false,-2.5]},"text":"a\"b\\c\nd"}"#;
+        let value = Json::parse(text).unwrap();
+        assert_eq!(value.get("id").as_i64(), Some(1));
+        assert_eq!(value.get("params").get("capabilities").as_array().unwrap()[2], Json::Number(-2.5));
+        assert_eq!(value.get("text").as_str(), Some("a\"b\\c\nd"));
+        assert!(value.get("missing").get("deeper").is_null());
+        assert_eq!(value.to_string(), text);
+    }
+
+    #[test]
+    fn decodes_unicode_escapes() {
+        let value = Json::parse(r#" [ "é", "😀" ] "#).unwrap();
+        assert_eq!(value, Json::Array(vec![Json::from("é"), Json::from("😀")]));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(Json::parse("{\"a\":1").is_err());
+        assert!(Json::parse("[1 2]").is_err());
+        assert!(Json::parse("nope").is_err());
+        assert!(Json::parse("{} {}").is_err());
+    }
+}
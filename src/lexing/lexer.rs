@@ -2,15 +2,48 @@ use super::Token;
 
 pub struct Lexer {
     raw_text: String,
+    line: usize,
+    token_line: usize,
+    keep_comments: bool,
 }
 
 impl Lexer {
     pub fn new(raw_text: String) -> Self {
         Self {
-            raw_text
+            raw_text,
+            line: 1,
+            token_line: 1,
+            keep_comments: false,
         }
     }
 
+    // For source cut out of a larger file, Ex: the definition of a generic function
+    pub fn starting_at(raw_text: String, line: usize) -> Self {
+        Self {
+            line,
+            token_line: line,
+            ..Self::new(raw_text)
+        }
+    }
+
+    // Comments are skipped unless the tokens are going to be printed again, Ex: by the formatter
+    pub fn with_comments(raw_text: String) -> Self {
+        Self {
+            keep_comments: true,
+            ..Self::new(raw_text)
+        }
+    }
+
+    // Line of the most recently lexed token, starting at 1
+    pub fn line(&self) -> usize {
+        self.token_line
+    }
+
+    // Bytes of the source that haven't been lexed yet
+    pub fn remaining(&self) -> usize {
+        self.raw_text.len()
+    }
+
     fn empty(&self) -> bool {
         self.raw_text.is_empty()
     }
@@ -28,16 +61,50 @@ impl Lexer {
             }
             current = self.peek().unwrap();
         }
+        self.token_line = self.line;
         if current == '\n' {
             self.pop();
             return Token::EOL;
         }
+        if current == '/' && self.peek_next() == Some('/') {
+            self.pop();
+            self.pop();
+            let mut comment = String::new();
+            while self.peek().map_or(false, |c| c != '\n') {
+                comment.push(self.pop());
+            }
+            if self.keep_comments {
+                return Token::Comment(comment.trim_end().to_string());
+            }
+            return self.next();
+        }
+
+        let compound_token = match current {
+            '+' => Some(Token::PlusEqual),
+            '-' => Some(Token::MinusEqual),
+            '*' => Some(Token::StarEqual),
+            '/' => Some(Token::SlashEqual),
+            '%' => Some(Token::PercentEqual),
+            '&' => Some(Token::AmpersandEqual),
+            '|' => Some(Token::PipeEqual),
+            '^' => Some(Token::CaretEqual),
+            _ => None
+        };
+        if compound_token.is_some() && self.peek_next() == Some('=') {
+            self.pop();
+            self.pop();
+            return compound_token.unwrap();
+        }
 
         let sc_token = match current {
             '+' => Some(Token::Plus),
             '-' => Some(Token::Minus),
             '*' => Some(Token::Star),
             '/' => Some(Token::Slash),
+            '%' => Some(Token::Percent),
+            '|' => Some(Token::Pipe),
+            '^' => Some(Token::Caret),
+            '~' => Some(Token::Tilde),
             '(' => Some(Token::OpenParenth),
             ')' => Some(Token::CloseParenth),
             '{' => Some(Token::OpenCurly),
@@ -48,11 +115,29 @@ impl Lexer {
                 self.pop();
                 Some(Token::DoubleEqual)
             },
+            '<' if self.peek_next() == Some('<') && self.peek_nth(2) == Some('=') => {
+                self.pop();
+                self.pop();
+                Some(Token::ShiftLeftEqual)
+            },
+            '<' if self.peek_next() == Some('<') => {
+                self.pop();
+                Some(Token::ShiftLeft)
+            },
             '<' if self.peek_next() != Some('=') => Some(Token::Lesser),
             '<' if self.peek_next() == Some('=') => {
                 self.pop();
                 Some(Token::LesserEqual)
             },
+            '>' if self.peek_next() == Some('>') && self.peek_nth(2) == Some('=') => {
+                self.pop();
+                self.pop();
+                Some(Token::ShiftRightEqual)
+            },
+            '>' if self.peek_next() == Some('>') => {
+                self.pop();
+                Some(Token::ShiftRight)
+            },
             '>' if self.peek_next() != Some('=') => Some(Token::Greater),
             '>' if self.peek_next() == Some('=') => {
                 self.pop();
@@ -63,6 +148,7 @@ impl Lexer {
                 Some(Token::NotEqual)
             },
             ':' => Some(Token::Colon),
+            '.' => Some(Token::Dot),
             '[' => Some(Token::OpenSquare),
             ']' => Some(Token::CloseSquare),
             ',' => Some(Token::Comma),
@@ -145,8 +231,8 @@ impl Lexer {
             return Token::Integer(current_string.parse().unwrap());
         }
 
-        if current.is_alphabetic() {
-            while current.is_alphanumeric() && !self.empty() {
+        if current.is_alphabetic() || current == '_' {
+            while (current.is_alphanumeric() || current == '_') && !self.empty() {
                 current_string.push(self.pop());
                 if self.peek().is_none() {
                     break;
@@ -155,10 +241,19 @@ impl Lexer {
             }
             return match current_string.as_str() {
                 "def" => Token::Def,
+                "pub" => Token::Pub,
+                "extern" => Token::Extern,
+                "import" => Token::Import,
                 "if" => Token::If,
                 "as" => Token::As,
                 "else" => Token::Else,
                 "return" => Token::Return,
+                "for" => Token::For,
+                "enum" => Token::Enum,
+                "const" => Token::Const,
+                "static_assert" => Token::StaticAssert,
+                "match" => Token::Match,
+                "in" => Token::In,
                 _ => Token::Identifier(current_string)
             };
         }
@@ -166,7 +261,11 @@ impl Lexer {
     }
 
     fn pop(&mut self) -> char {
-        self.raw_text.remove(0)
+        let popped = self.raw_text.remove(0);
+        if popped == '\n' {
+            self.line += 1;
+        }
+        popped
     }
 
     fn peek(&self) -> Option<char> {
@@ -176,6 +275,10 @@ impl Lexer {
     fn peek_next(&self) -> Option<char> {
         self.raw_text.chars().skip(1).next()
     }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.raw_text.chars().nth(n)
+    }
 }
 
 #[cfg(test)]
@@ -195,4 +298,112 @@ mod test {
             assert_eq!(lexer.next(), *expected);
         }
     }
+
+    #[test]
+    fn test_for_loop() {
+        let raw = "for x in vec[1, 2] {".to_string();
+
+        let mut lexer = Lexer::new(raw);
+        let expected_tokens = &[For, Identifier("x".into()), In, Identifier("vec".into()),
+            OpenSquare, Integer(1), Comma, Integer(2), CloseSquare, OpenCurly, EOF];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_imports() {
+        let raw = "import math\nimport \"lib/util.ss\"\npub def".to_string();
+
+        let mut lexer = Lexer::new(raw);
+        let expected_tokens = &[Import, Identifier("math".into()), EOL, Import, String("lib/util.ss".into()), EOL, Pub, Def, EOF];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_static_assert() {
+        let raw = "static_assert(max_value > 0)".to_string();
+
+        let mut lexer = Lexer::new(raw);
+        let expected_tokens = &[StaticAssert, OpenParenth, Identifier("max_value".into()), Greater, Integer(0), CloseParenth, EOF];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_operators() {
+        let raw = "a % b & c | ~d ^ e << 2 >> 1 <= 3".to_string();
+
+        let mut lexer = Lexer::new(raw);
+        let expected_tokens = &[Identifier("a".into()), Percent, Identifier("b".into()), Ampersand,
+            Identifier("c".into()), Pipe, Tilde, Identifier("d".into()), Caret, Identifier("e".into()),
+            ShiftLeft, Integer(2), ShiftRight, Integer(1), LesserEqual, Integer(3), EOF];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let raw = "a += 1\nb[2] <<= c >> 1".to_string();
+
+        let mut lexer = Lexer::new(raw);
+        let expected_tokens = &[Identifier("a".into()), PlusEqual, Integer(1), EOL, Identifier("b".into()),
+            OpenSquare, Integer(2), CloseSquare, ShiftLeftEqual, Identifier("c".into()), ShiftRight, Integer(1), EOF];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let raw = "a = 1 // one\n// alone\nb".to_string();
+
+        let mut lexer = Lexer::new(raw.clone());
+        let expected_tokens = &[Identifier("a".into()), Equal, Integer(1), EOL, EOL, Identifier("b".into()), EOF];
+        for expected in expected_tokens {
+            assert_eq!(lexer.next(), *expected);
+        }
+
+        let mut lexer = Lexer::with_comments(raw);
+        let expected_tokens = &[Identifier("a".into()), Equal, Integer(1), Comment(" one".into()), EOL,
+            Comment(" alone".into()), EOL, Identifier("b".into()), EOF];
+        for expected in expected_tokens {
+            assert_eq!(lexer.next(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let raw = "a\n\nb = 2".to_string();
+
+        let mut lexer = Lexer::new(raw);
+        let expected_lines = &[1, 1, 2, 3, 3, 3];
+
+        for expected in expected_lines {
+            lexer.next();
+            assert_eq!(lexer.line(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_underscore_identifiers() {
+        let raw = "wrapping_add(max_value, _x)".to_string();
+
+        let mut lexer = Lexer::new(raw);
+        let expected_tokens = &[Identifier("wrapping_add".into()), OpenParenth, Identifier("max_value".into()), Comma,
+            Identifier("_x".into()), CloseParenth, EOF];
+
+        for expected in expected_tokens {
+            assert_eq!(lexer.next(), *expected);
+        }
+    }
 }
\ No newline at end of file
@@ -5,7 +5,11 @@ pub enum Token {
     Char(u8),
     Integer(i64),
     Float(f64),
+    Comment(String),
     Def,
+    Pub,
+    Extern,
+    Import,
     As,
     Return,
     OpenCurly,
@@ -18,7 +22,13 @@ pub enum Token {
     Minus,
     Star,
     Slash,
+    Percent,
     Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
     Greater,
     Lesser,
     GreaterEqual,
@@ -26,9 +36,26 @@ pub enum Token {
     DoubleEqual,
     NotEqual,
     If,
+    Const,
+    StaticAssert,
+    Enum,
+    Match,
     Else,
+    For,
+    In,
     Equal,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    AmpersandEqual,
+    PipeEqual,
+    CaretEqual,
+    ShiftLeftEqual,
+    ShiftRightEqual,
     Colon,
+    Dot,
     Comma,
     EOL,
     EOF,
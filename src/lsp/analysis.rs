@@ -0,0 +1,284 @@
+use std::{any::Any, panic::{catch_unwind, AssertUnwindSafe}, path::{Path, PathBuf}};
+
+use crate::{ast::{CompilerOptions, RootScope}, json::Json, mir, parsing::{ModuleLoader, Parser, ParsingResult, Symbol, SymbolKind}};
+
+// Completion item kinds from the protocol
+const FUNCTION_ITEM: i64 = 3;
+const VARIABLE_ITEM: i64 = 6;
+const CONSTANT_ITEM: i64 = 21;
+const TYPE_ITEM: i64 = 22;
+const FUNCTION_SYMBOL: i64 = 12;
+const ERROR: i64 = 1;
+const WARNING: i64 = 2;
+
+// An open document, lines are 0 based like the protocol while the parser's are 1 based
+pub struct Document<'a> {
+    pub uri: &'a str,
+    pub text: &'a str,
+}
+
+impl<'a> Document<'a> {
+    fn directory(&self) -> PathBuf {
+        self.uri.strip_prefix("file://")
+            .and_then(|path| Path::new(path).parent())
+            .map_or(PathBuf::from("."), Path::to_path_buf)
+    }
+
+    // Parses as far as parse gets, errors and panics are reported with the line the parser stopped on
+    fn analyze(&self, parse: impl FnOnce(&mut Parser) -> ParsingResult<()>) -> (Option<Parser>, Option<(usize, String)>) {
+        self.analyze_with(&mut ModuleLoader::default(), parse)
+    }
+
+    fn analyze_with(&self, loader: &mut ModuleLoader, parse: impl FnOnce(&mut Parser) -> ParsingResult<()>) -> (Option<Parser>, Option<(usize, String)>) {
+        let mut parser = match loader.parser_for(self.text.to_string(), &self.directory()) {
+            Ok(parser) => parser,
+            Err(error) => return (None, Some((0, error.to_string()))),
+        };
+        let error = match catch_unwind(AssertUnwindSafe(|| parse(&mut parser))) {
+            Ok(Ok(())) => None,
+            Ok(Err(error)) => Some(error.to_string()),
+            Err(payload) => Some(panic_message(payload)),
+        };
+        let error = error.map(|message| (parser.current_line().saturating_sub(1), message));
+        (Some(parser), error)
+    }
+
+    fn symbols(&self) -> Vec<Symbol> {
+        self.analyze(|parser| parser.parse().map(|_| ())).0.map_or(Vec::new(), |parser| parser.symbols)
+    }
+
+    // The parser stops at the first error, a document that parses is lowered to report what lowering and lint find
+    pub fn diagnostics(&self) -> Json {
+        let mut loader = ModuleLoader::default();
+        let mut root = None;
+        let (parser, error) = self.analyze_with(&mut loader, |parser| parser.parse().map(|parsed| root = Some(parsed)));
+        let mut diagnostics = Vec::new();
+        if let Some((line, message)) = error {
+            diagnostics.push(self.diagnostic(line, ERROR, message));
+        }
+        if let (Some(parser), Some(root)) = (parser, root) {
+            for (line, severity, message) in lowering_errors(&mut loader, &parser, root) {
+                diagnostics.push(self.diagnostic(line, severity, message));
+            }
+        }
+        Json::Array(diagnostics)
+    }
+
+    fn diagnostic(&self, line: usize, severity: i64, message: String) -> Json {
+        Json::object(vec![
+            ("range", range(line, 0, utf16_length(self.line(line)))),
+            ("severity", Json::from(severity)),
+            ("source", Json::from("ss")),
+            ("message", Json::from(message)),
+        ])
+    }
+
+    pub fn hover(&self, line: usize, character: usize) -> Json {
+        let Some(word) = word_at(self.line(line), character) else {
+            return Json::Null;
+        };
+        let (parser, _) = self.analyze(|parser| parser.parse_until(line + 1));
+        let Some(parser) = parser else {
+            return Json::Null;
+        };
+        let data_type = parser.lookup_type(word).or_else(|| {
+            parser.symbols.iter().rev().find(|symbol| symbol.name == word).and_then(|symbol| symbol.data_type.clone())
+        });
+        let Some(data_type) = data_type else {
+            return Json::Null;
+        };
+        Json::object(vec![
+            ("contents", Json::object(vec![
+                ("kind", Json::from("markdown")),
+                ("value", Json::from(format!("```\n{}: {}\n```", word, data_type.symbol))),
+            ])),
+        ])
+    }
+
+    pub fn definition(&self, line: usize, character: usize) -> Json {
+        let Some(word) = word_at(self.line(line), character) else {
+            return Json::Null;
+        };
+        let symbols = self.symbols();
+        let in_scope = visible_symbols(&symbols, line + 1);
+        // The closest earlier definition wins, Ex: a local shadowing a global
+        match in_scope.iter().rev().find(|symbol| symbol.name == word) {
+            Some(symbol) => self.location(symbol),
+            None => Json::Null,
+        }
+    }
+
+    pub fn completion(&self, line: usize) -> Json {
+        let (parser, _) = self.analyze(|parser| parser.parse().map(|_| ()));
+        let Some(parser) = parser else {
+            return Json::Array(Vec::new());
+        };
+        let mut items: Vec<(String, i64, Option<String>)> = Vec::new();
+        for symbol in visible_symbols(&parser.symbols, line + 1) {
+            if items.iter().any(|(name, ..)| *name == symbol.name) {
+                continue;
+            }
+            let kind = match symbol.kind {
+                SymbolKind::Function => FUNCTION_ITEM,
+                SymbolKind::Variable | SymbolKind::Global => VARIABLE_ITEM,
+                SymbolKind::Constant => CONSTANT_ITEM,
+            };
+            items.push((symbol.name.clone(), kind, symbol.data_type.as_ref().map(|data_type| data_type.symbol.clone())));
+        }
+        let mut names: Vec<&String> = parser.data_types.keys().collect();
+        names.sort();
+        for name in names {
+            items.push((name.clone(), TYPE_ITEM, None));
+        }
+        Json::Array(items.into_iter().map(|(label, kind, detail)| {
+            let mut item = vec![("label", Json::from(label)), ("kind", Json::from(kind))];
+            if let Some(detail) = detail {
+                item.push(("detail", Json::from(detail)));
+            }
+            Json::object(item)
+        }).collect())
+    }
+
+    pub fn document_symbols(&self) -> Json {
+        Json::Array(self.symbols().iter().filter(|symbol| symbol.kind == SymbolKind::Function).map(|symbol| {
+            Json::object(vec![
+                ("name", Json::from(symbol.name.as_str())),
+                ("kind", Json::from(FUNCTION_SYMBOL)),
+                ("location", self.location(symbol)),
+            ])
+        }).collect())
+    }
+
+    fn location(&self, symbol: &Symbol) -> Json {
+        let line = symbol.line.saturating_sub(1);
+        let text = self.line(line);
+        let character = find_word(text, &symbol.name).map_or(0, |i| utf16_length(&text[..i]));
+        Json::object(vec![
+            ("uri", Json::from(self.uri)),
+            ("range", range(line, character, character + utf16_length(&symbol.name))),
+        ])
+    }
+
+    fn line(&self, line: usize) -> &str {
+        self.text.lines().nth(line).unwrap_or("")
+    }
+}
+
+// Lines are 0 based, lowering panics have no line and are reported on the first one
+fn lowering_errors(loader: &mut ModuleLoader, parser: &Parser, root: RootScope) -> Vec<(usize, i64, String)> {
+    let root = match loader.link_with(root) {
+        Ok(root) => root,
+        Err(error) => return vec![(0, ERROR, error.to_string())],
+    };
+    let mut data_types = loader.data_types.clone();
+    data_types.extend(parser.data_types.clone());
+    let lowered = catch_unwind(AssertUnwindSafe(|| mir::lower(&root, &data_types, &CompilerOptions::default())));
+    let program = match lowered {
+        Ok(program) => program,
+        Err(payload) => return vec![(0, ERROR, panic_message(payload))],
+    };
+    let mut errors = Vec::new();
+    if let Err(error) = mir::verify(&program) {
+        errors.push((0, ERROR, error.to_string()));
+    }
+    for warning in mir::lint(&program) {
+        errors.push((warning.line.saturating_sub(1), WARNING, warning.message));
+    }
+    errors
+}
+
+// Globals, constants and functions are visible everywhere, variables only after their definition in the same function
+fn visible_symbols(symbols: &[Symbol], line: usize) -> Vec<&Symbol> {
+    let function_line = symbols.iter()
+        .filter(|symbol| symbol.kind == SymbolKind::Function && symbol.line <= line)
+        .map(|symbol| symbol.line)
+        .max()
+        .unwrap_or(0);
+    symbols.iter().filter(|symbol| match symbol.kind {
+        SymbolKind::Variable => symbol.line >= function_line && symbol.line <= line,
+        _ => true,
+    }).collect()
+}
+
+fn range(line: usize, start: usize, end: usize) -> Json {
+    let position = |character: usize| Json::object(vec![("line", Json::from(line)), ("character", Json::from(character))]);
+    Json::object(vec![("start", position(start)), ("end", position(end))])
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Positions count UTF-16 code units like the protocol, Rust strings are indexed by bytes
+fn utf16_length(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+fn byte_offset(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+// The identifier under or just before the cursor
+fn word_at(line: &str, character: usize) -> Option<&str> {
+    let character = byte_offset(line, character);
+    let start = line[..character].char_indices().rev()
+        .find(|&(_, c)| !is_word_char(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let end = line[character..].find(|c: char| !is_word_char(c)).map_or(line.len(), |i| character + i);
+    let word = &line[start..end];
+    if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(word)
+}
+
+fn find_word(line: &str, word: &str) -> Option<usize> {
+    line.match_indices(word).map(|(i, _)| i).find(|&i| {
+        !line[..i].ends_with(is_word_char) && !line[i + word.len()..].starts_with(is_word_char)
+    })
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    payload.downcast_ref::<String>().cloned().unwrap_or("Couldn't parse document".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_units() {
+        // é is one UTF-16 unit but two bytes, 😀 is two units and four bytes
+        let line = "    s = \"é😀\" + name";
+        assert_eq!(word_at(line, 17), Some("name"));
+        assert_eq!(word_at(line, 10), Some("é"));
+        assert_eq!(word_at(line, 11), None);
+        assert_eq!(word_at("😀x", 2), Some("x"));
+        assert_eq!(find_word(line, "name").map(|i| utf16_length(&line[..i])), Some(16));
+    }
+
+    #[test]
+    fn reports_every_lint_warning() {
+        let text = "def main(): i64 {\n    y = 1\n    if y > 0 {\n        x = 2\n    }\n    p = &y\n    return x\n}\ndef keep(): &i64 {\n    z = 1\n    return &z\n}\n";
+        let diagnostics = Document { uri: "file:///tmp/ss_lsp/lint.ss", text }.diagnostics();
+        let found: Vec<(i64, i64, &str)> = diagnostics.as_array().unwrap().iter().map(|diagnostic| (
+            diagnostic.get("range").get("start").get("line").as_i64().unwrap(),
+            diagnostic.get("severity").as_i64().unwrap(),
+            diagnostic.get("message").as_str().unwrap(),
+        )).collect();
+        assert_eq!(found, vec![
+            (6, WARNING, "x may be read before it is assigned"),
+            (10, WARNING, "a pointer to z outlives keep"),
+        ]);
+    }
+}
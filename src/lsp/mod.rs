@@ -0,0 +1,6 @@
+mod analysis;
+mod server;
+mod transport;
+
+pub use server::*;
+pub use transport::*;
@@ -0,0 +1,199 @@
+use std::{collections::HashMap, io::{self, BufRead, Write}};
+
+use crate::json::Json;
+
+use super::{analysis::Document, read_message, write_message};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// Keeps the full text of every open document, the client sends whole documents on change
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, String>,
+}
+
+pub fn run() {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    if let Err(error) = Server::default().serve(&mut stdin.lock(), &mut stdout) {
+        eprintln!("{}", error);
+    }
+}
+
+impl Server {
+    pub fn serve(&mut self, reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+        while let Some(message) = read_message(reader)? {
+            let method = message.get("method").as_str().unwrap_or_default();
+            let params = message.get("params");
+            if method == "exit" {
+                break;
+            }
+            // Requests carry an id and need a response, notifications don't
+            let id = message.get("id");
+            if id.is_null() {
+                if let Some(notification) = self.notify(method, params) {
+                    write_message(writer, &notification)?;
+                }
+                continue;
+            }
+            let response = match self.request(method, params) {
+                Some(result) => Json::object(vec![("jsonrpc", Json::from("2.0")), ("id", id.clone()), ("result", result)]),
+                None => Json::object(vec![
+                    ("jsonrpc", Json::from("2.0")),
+                    ("id", id.clone()),
+                    ("error", Json::object(vec![
+                        ("code", Json::from(METHOD_NOT_FOUND)),
+                        ("message", Json::from(format!("Unsupported method {}", method))),
+                    ])),
+                ]),
+            };
+            write_message(writer, &response)?;
+        }
+        Ok(())
+    }
+
+    fn request(&self, method: &str, params: &Json) -> Option<Json> {
+        if method == "initialize" {
+            return Some(Json::object(vec![
+                ("capabilities", Json::object(vec![
+                    ("textDocumentSync", Json::Number(1.0)),
+                    ("hoverProvider", Json::from(true)),
+                    ("definitionProvider", Json::from(true)),
+                    ("completionProvider", Json::object(vec![])),
+                    ("documentSymbolProvider", Json::from(true)),
+                ])),
+                ("serverInfo", Json::object(vec![("name", Json::from("ss-lsp"))])),
+            ]));
+        }
+        if method == "shutdown" {
+            return Some(Json::Null);
+        }
+
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default();
+        let document = self.documents.get(uri).map(|text| Document { uri, text });
+        let line = params.get("position").get("line").as_i64().unwrap_or(0) as usize;
+        let character = params.get("position").get("character").as_i64().unwrap_or(0) as usize;
+        // Requests about documents that were never opened get an empty answer
+        let answer = |respond: &dyn Fn(&Document) -> Json| Some(document.as_ref().map_or(Json::Null, respond));
+        match method {
+            "textDocument/hover" => answer(&|document| document.hover(line, character)),
+            "textDocument/definition" => answer(&|document| document.definition(line, character)),
+            "textDocument/completion" => answer(&|document| document.completion(line)),
+            "textDocument/documentSymbol" => answer(&|document| document.document_symbols()),
+            _ => None,
+        }
+    }
+
+    // Returns the diagnostics to publish when a document changed
+    fn notify(&mut self, method: &str, params: &Json) -> Option<Json> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default().to_string();
+        let text = match method {
+            "textDocument/didOpen" => params.get("textDocument").get("text").as_str(),
+            "textDocument/didChange" => params.get("contentChanges").as_array().and_then(|changes| changes.last()).and_then(|change| change.get("text").as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return Some(publish_diagnostics(&uri, Json::Array(Vec::new())));
+            },
+            _ => None,
+        }?;
+        let diagnostics = Document { uri: &uri, text }.diagnostics();
+        self.documents.insert(uri.clone(), text.to_string());
+        Some(publish_diagnostics(&uri, diagnostics))
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("method", Json::from("textDocument/publishDiagnostics")),
+        ("params", Json::object(vec![("uri", Json::from(uri)), ("diagnostics", diagnostics)])),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const URI: &str = "file:///tmp/ss_lsp/main.ss";
+    const SOURCE: &str = "def square(x: i64): i64 {\n    y = x * x\n    return y\n}\ndef main(): i64 {\n    return square(3)\n}\n";
+
+    fn position(id: i64, method: &str, line: i64, character: i64) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("id", Json::from(id)),
+            ("method", Json::from(method)),
+            ("params", Json::object(vec![
+                ("textDocument", Json::object(vec![("uri", Json::from(URI))])),
+                ("position", Json::object(vec![("line", Json::from(line)), ("character", Json::from(character))])),
+            ])),
+        ])
+    }
+
+    fn notification(method: &str, params: Json) -> Json {
+        Json::object(vec![("jsonrpc", Json::from("2.0")), ("method", Json::from(method)), ("params", params)])
+    }
+
+    // Plays a whole session against the server and returns everything it sent back
+    fn session(messages: Vec<Json>) -> Vec<Json> {
+        let mut input = Vec::new();
+        for message in &messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        Server::default().serve(&mut input.as_slice(), &mut output).unwrap();
+        let mut output = output.as_slice();
+        let mut responses = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            responses.push(message);
+        }
+        responses
+    }
+
+    #[test]
+    fn answers_a_scripted_client() {
+        let open = notification("textDocument/didOpen", Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(URI)), ("text", Json::from(SOURCE))])),
+        ]));
+        let broken = notification("textDocument/didChange", Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(URI))])),
+            ("contentChanges", Json::Array(vec![Json::object(vec![("text", Json::from("def main(): i64 {\n    return 1 +\n}\n"))])])),
+        ]));
+        let responses = session(vec![
+            Json::object(vec![("jsonrpc", Json::from("2.0")), ("id", Json::from(1i64)), ("method", Json::from("initialize")), ("params", Json::object(vec![]))]),
+            notification("initialized", Json::object(vec![])),
+            open,
+            position(2, "textDocument/hover", 2, 11),
+            position(3, "textDocument/definition", 5, 12),
+            position(4, "textDocument/completion", 2, 4),
+            position(5, "textDocument/documentSymbol", 0, 0),
+            position(6, "textDocument/formatting", 0, 0),
+            broken,
+            Json::object(vec![("jsonrpc", Json::from("2.0")), ("id", Json::from(7i64)), ("method", Json::from("shutdown"))]),
+            notification("exit", Json::Null),
+        ]);
+        assert_eq!(responses.len(), 9);
+
+        assert_eq!(responses[0].get("result").get("capabilities").get("hoverProvider"), &Json::Bool(true));
+        assert_eq!(responses[1].get("method").as_str(), Some("textDocument/publishDiagnostics"));
+        assert_eq!(responses[1].get("params").get("diagnostics"), &Json::Array(vec![]));
+
+        assert_eq!(responses[2].get("result").get("contents").get("value").as_str(), Some("```\ny: i64\n```"));
+
+        let definition = responses[3].get("result");
+        assert_eq!(definition.get("uri").as_str(), Some(URI));
+        assert_eq!(definition.get("range").get("start").get("line").as_i64(), Some(0));
+        assert_eq!(definition.get("range").get("start").get("character").as_i64(), Some(4));
+
+        let labels: Vec<&str> = responses[4].get("result").as_array().unwrap().iter().filter_map(|item| item.get("label").as_str()).collect();
+        for label in ["square", "main", "x", "y", "i64", "f64"] {
+            assert!(labels.contains(&label), "missing completion {}", label);
+        }
+
+        let symbols: Vec<&str> = responses[5].get("result").as_array().unwrap().iter().filter_map(|symbol| symbol.get("name").as_str()).collect();
+        assert_eq!(symbols, vec!["square", "main"]);
+
+        assert_eq!(responses[6].get("error").get("code").as_i64(), Some(METHOD_NOT_FOUND));
+        assert!(!responses[7].get("params").get("diagnostics").as_array().unwrap().is_empty());
+        assert!(responses[8].get("result").is_null());
+    }
+}
@@ -1,26 +1,158 @@
-#![feature(trait_upcasting)]
-
-use std::{fs::{File}, io::Read};
-
-use runner::run;
+use ast::{CompilerOptions, OptimizationLevel};
+use runner::{emit_ast, emit_bytecode, emit_disassembly, emit_mir, interpret, run_bytecode};
 
 mod ast;
+mod bytecode;
+#[cfg(feature = "llvm")]
+mod codegen;
+#[cfg(test)]
+mod conformance;
+mod formatter;
+mod interpreter;
+mod json;
 mod lexing;
+mod lsp;
+mod mir;
 mod parsing;
+#[cfg(feature = "llvm")]
+mod repl;
 mod runner;
 
+#[cfg(feature = "llvm")]
+use ss_runtime as runtime;
 
-fn load_file(relative_path: &str) -> String {
-    let mut f = File::open(relative_path).expect("Missing file");
-
-    let mut buf: String = "".to_string();
-
-    f.read_to_string(&mut buf).unwrap();
-
-    return buf;
-}
 
 fn main() {
-    let file_path = "./test/main.txt";
-    run(load_file(file_path));
+    let mut file_path = "./test/main.txt".to_string();
+    let mut options = CompilerOptions::default();
+    let mut interactive = false;
+    let mut language_server = false;
+    let mut formatting = false;
+    let mut check = false;
+    let mut emitting = false;
+    let mut ast = false;
+    let mut mir = false;
+    let mut json = false;
+    let mut interpreting = false;
+    let mut vm = false;
+    let mut bytecode = false;
+    let mut disassemble = false;
+    let mut building = false;
+    #[cfg(feature = "llvm")]
+    let mut assembly = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rc" => options.reference_counting = true,
+            "--leak-check" => options.leak_check = true,
+            "--release" => options.bounds_checks = false,
+            "--debug" => options.overflow_checks = true,
+            "-g" => options.debug_info = true,
+            "repl" => interactive = true,
+            "lsp" => language_server = true,
+            "fmt" => formatting = true,
+            "--check" => check = true,
+            "emit" => emitting = true,
+            "--ast" => ast = true,
+            "--mir" => mir = true,
+            "--json" => json = true,
+            "--interpret" => interpreting = true,
+            "--vm" => vm = true,
+            "--bytecode" => bytecode = true,
+            "--disassemble" => disassemble = true,
+            "build" => building = true,
+            #[cfg(feature = "llvm")]
+            "--asm" => assembly = true,
+            "--target" | "--cpu" | "--features" => {
+                let Some(value) = args.next() else {
+                    eprintln!("{} needs a value, Ex: --target aarch64-unknown-linux-gnu", arg);
+                    std::process::exit(1);
+                };
+                match arg.as_str() {
+                    "--target" => options.target = Some(value),
+                    "--cpu" => options.cpu = Some(value),
+                    _ => options.target_features = Some(value),
+                }
+            },
+            _ if arg.starts_with("-O") => match OptimizationLevel::from_flag(&arg) {
+                Some(level) => options.optimization = level,
+                None => {
+                    eprintln!("Unknown optimization level {}, Ex: -O2", arg);
+                    std::process::exit(1);
+                },
+            },
+            _ => file_path = arg,
+        }
+    }
+    if emitting {
+        options.source_name = file_path.clone();
+        if ast {
+            emit_ast(&file_path, json);
+        } else if mir {
+            emit_mir(&file_path, options);
+        } else if bytecode {
+            emit_bytecode(&file_path, options);
+        } else if disassemble {
+            emit_disassembly(&file_path, options);
+        } else {
+            eprintln!("emit needs something to emit, Ex: emit --ast");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if building {
+        options.source_name = file_path.clone();
+        #[cfg(feature = "llvm")]
+        {
+            runner::build(&file_path, options, assembly);
+            return;
+        }
+        #[cfg(not(feature = "llvm"))]
+        {
+            eprintln!("build needs the llvm feature");
+            std::process::exit(1);
+        }
+    }
+    if formatting {
+        match formatter::run(&file_path, check) {
+            Ok(false) if check => {
+                eprintln!("{} is not formatted", file_path);
+                std::process::exit(1);
+            },
+            Ok(_) => {},
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+    if language_server {
+        lsp::run();
+        return;
+    }
+    if interactive {
+        #[cfg(feature = "llvm")]
+        {
+            options.source_name = "repl".to_string();
+            repl::run(options);
+            return;
+        }
+        #[cfg(not(feature = "llvm"))]
+        {
+            eprintln!("The repl needs the llvm feature");
+            std::process::exit(1);
+        }
+    }
+    options.source_name = file_path.clone();
+    if vm || file_path.ends_with(".ssb") {
+        run_bytecode(&file_path, options);
+        return;
+    }
+    if interpreting || cfg!(not(feature = "llvm")) {
+        interpret(&file_path, options);
+        return;
+    }
+    #[cfg(feature = "llvm")]
+    runner::run(&file_path, options);
 }
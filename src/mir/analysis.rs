@@ -0,0 +1,242 @@
+use std::collections::BTreeSet;
+
+use super::{Body, Local, Operand, Place, Program, Projection, Root, Rvalue, StatementKind, TerminatorKind};
+
+pub struct Warning {
+    pub line: usize,
+    pub message: String,
+}
+
+// Reads of variables that may not have been assigned and pointers to locals that outlive them
+pub fn lint(program: &Program) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    for body in &program.bodies {
+        for (local, line) in uninitialized_reads(body) {
+            warnings.push(Warning { line, message: format!("{} may be read before it is assigned", body.local_name(local)) });
+        }
+        for (local, line) in escaping_locals(body) {
+            warnings.push(Warning { line, message: format!("a pointer to {} outlives {}", body.local_name(local), body.name) });
+        }
+    }
+    warnings.sort_by_key(|warning| warning.line);
+    warnings
+}
+
+// For each block, whether each local has been assigned on every path that reaches it
+pub fn definitely_assigned(body: &Body) -> Vec<Vec<bool>> {
+    let count = body.locals.len();
+    // Blocks nothing reaches keep everything assigned so they report nothing
+    let mut entry = vec![vec![true; count]; body.blocks.len()];
+    if let Some(first) = entry.first_mut() {
+        *first = (0..count).map(|local| local < body.params as usize).collect();
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, block) in body.blocks.iter().enumerate() {
+            let mut state = entry[index].clone();
+            for statement in &block.statements {
+                for local in assigned(&statement.kind) {
+                    state[local.index()] = true;
+                }
+            }
+            for successor in block.terminator.kind.successors() {
+                let merged: Vec<bool> = entry[successor.index()].iter().zip(&state).map(|(before, after)| *before && *after).collect();
+                if merged != entry[successor.index()] {
+                    entry[successor.index()] = merged;
+                    changed = true;
+                }
+            }
+        }
+    }
+    entry
+}
+
+// Named locals read before every path has assigned them, with the line of the first such read
+pub fn uninitialized_reads(body: &Body) -> Vec<(Local, usize)> {
+    let entry = definitely_assigned(body);
+    let mut reported = vec![false; body.locals.len()];
+    let mut reads = Vec::new();
+    for (block, mut state) in body.blocks.iter().zip(entry) {
+        let statements = block.statements.iter().map(|statement| (read_locals(&statement.kind), assigned(&statement.kind), statement.line));
+        let terminator = (block.terminator.kind.operands().into_iter().flat_map(operand_locals).collect(), Vec::new(), block.terminator.line);
+        for (read, written, line) in statements.chain([terminator]) {
+            for local in read {
+                let named = body.locals[local.index()].name.is_some();
+                if named && !state[local.index()] && !reported[local.index()] {
+                    reported[local.index()] = true;
+                    reads.push((local, line));
+                }
+            }
+            for local in written {
+                state[local.index()] = true;
+            }
+        }
+    }
+    reads
+}
+
+// Locals whose address may be used after the function returns, through a return value, a global,
+// a heap value or a store through a pointer. Calls are assumed not to keep their arguments
+pub fn escaping_locals(body: &Body) -> Vec<(Local, usize)> {
+    // Locals whose address each local may hold, ignoring the order statements run in
+    let mut points_to = vec![BTreeSet::new(); body.locals.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for statement in body.blocks.iter().flat_map(|block| &block.statements) {
+            let (target, sources) = match statement.kind {
+                StatementKind::Assign(ref place, ref rvalue) => (place.root_local(), rvalue_sources(rvalue, &points_to)),
+                StatementKind::Call(_, ref args, Some(ref destination)) => {
+                    (destination.root_local(), args.iter().flat_map(|arg| operand_sources(arg, &points_to)).collect())
+                },
+                _ => continue,
+            };
+            if let Some(target) = target {
+                let before = points_to[target.index()].len();
+                points_to[target.index()].extend(sources);
+                changed |= points_to[target.index()].len() != before;
+            }
+        }
+    }
+
+    let mut escaping: Vec<(Local, usize)> = Vec::new();
+    let mut escape = |sources: BTreeSet<Local>, line: usize| {
+        for local in sources {
+            if !escaping.iter().any(|(escaped, _)| *escaped == local) {
+                escaping.push((local, line));
+            }
+        }
+    };
+    for block in &body.blocks {
+        for statement in &block.statements {
+            match statement.kind {
+                StatementKind::Assign(ref place, ref rvalue) if stores_outside(place) => escape(rvalue_sources(rvalue, &points_to), statement.line),
+                StatementKind::Assign(_, Rvalue::Box(ref operand)) | StatementKind::Push(_, ref operand) => {
+                    escape(operand_sources(operand, &points_to), statement.line);
+                },
+                StatementKind::Assign(_, Rvalue::Vector(_, ref values)) => {
+                    escape(values.iter().flat_map(|value| operand_sources(value, &points_to)).collect(), statement.line);
+                },
+                _ => {},
+            }
+        }
+        if let TerminatorKind::Return(Some(ref value)) = block.terminator.kind {
+            escape(operand_sources(value, &points_to), block.terminator.line);
+        }
+    }
+    escaping
+}
+
+// Taking the address of a local counts as assigning it, it may be written through the pointer
+fn assigned(kind: &StatementKind) -> Vec<Local> {
+    let mut locals = Vec::new();
+    match kind {
+        StatementKind::Assign(place, rvalue) => {
+            if let Rvalue::Ref(referenced) = rvalue {
+                locals.extend(referenced.root_local());
+            }
+            if place.projections.is_empty() {
+                locals.extend(place.root_local());
+            }
+        },
+        StatementKind::Call(_, _, Some(place)) if place.projections.is_empty() => locals.extend(place.root_local()),
+        _ => {},
+    }
+    locals
+}
+
+// Assigning through a projection reads the value it projects from
+fn read_locals(kind: &StatementKind) -> Vec<Local> {
+    let mut locals: Vec<Local> = kind.operands().into_iter().flat_map(operand_locals).collect();
+    match kind {
+        StatementKind::Assign(place, rvalue) => {
+            if !place.projections.is_empty() {
+                locals.extend(place_locals(place));
+            }
+            if let Rvalue::Ref(referenced) = rvalue {
+                locals.extend(index_locals(referenced));
+            }
+        },
+        StatementKind::Call(_, _, Some(place)) if !place.projections.is_empty() => locals.extend(place_locals(place)),
+        _ => {},
+    }
+    locals
+}
+
+fn operand_locals(operand: &Operand) -> Vec<Local> {
+    operand.place().map(place_locals).unwrap_or_default()
+}
+
+fn place_locals(place: &Place) -> Vec<Local> {
+    place.root_local().into_iter().chain(index_locals(place)).collect()
+}
+
+fn index_locals(place: &Place) -> Vec<Local> {
+    place.projections.iter().filter_map(|projection| match projection {
+        Projection::Index(local) => Some(*local),
+        _ => None,
+    }).collect()
+}
+
+// Globals and anything reached through a pointer outlive the frame
+fn stores_outside(place: &Place) -> bool {
+    matches!(place.root, Root::Global(..)) || place.projections.contains(&Projection::Deref)
+}
+
+// Values loaded through a pointer aren't tracked
+fn operand_sources(operand: &Operand, points_to: &[BTreeSet<Local>]) -> BTreeSet<Local> {
+    match operand.place() {
+        Some(place) if !place.projections.contains(&Projection::Deref) => {
+            place.root_local().map(|local| points_to[local.index()].clone()).unwrap_or_default()
+        },
+        _ => BTreeSet::new(),
+    }
+}
+
+fn rvalue_sources(rvalue: &Rvalue, points_to: &[BTreeSet<Local>]) -> BTreeSet<Local> {
+    match rvalue {
+        // &(*p).0 points wherever p does
+        Rvalue::Ref(place) => match place.root_local() {
+            Some(local) if place.projections.contains(&Projection::Deref) => points_to[local.index()].clone(),
+            Some(local) => BTreeSet::from([local]),
+            None => BTreeSet::new(),
+        },
+        _ => rvalue.operands().into_iter().flat_map(|operand| operand_sources(operand, points_to)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::CompilerOptions, conformance, mir::lower};
+
+    fn lower_source(source: &str) -> Program {
+        let mut parser = conformance::parser(source);
+        let root = parser.parse().unwrap();
+        lower(&root, &parser.data_types, &CompilerOptions::default())
+    }
+
+    fn messages(program: &Program) -> Vec<String> {
+        lint(program).into_iter().map(|warning| format!("{}: {}", warning.line, warning.message)).collect()
+    }
+
+    #[test]
+    fn conformance_programs_are_clean() {
+        for (name, source, _) in conformance::PROGRAMS {
+            assert!(messages(&lower_source(source)).is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn finds_reads_of_unassigned_variables() {
+        let source = "def main(): i64 {\n    y = 1\n    if y > 0 {\n        x = 2\n    }\n    if y > 1 {\n        x = 3\n    }\n    return x\n}\n";
+        assert_eq!(messages(&lower_source(source)), ["9: x may be read before it is assigned"]);
+    }
+
+    #[test]
+    fn finds_pointers_to_locals_that_escape() {
+        let source = "def keep(): &i64 {\n    x = 1\n    p = &x\n    return p\n}\ndef store(out: &&i64) {\n    y = 2\n    *out = &y\n}\n";
+        assert_eq!(messages(&lower_source(source)), ["4: a pointer to x outlives keep", "8: a pointer to y outlives store"]);
+    }
+}
@@ -0,0 +1,768 @@
+use std::collections::HashMap;
+
+use crate::ast::{self, BinaryExpressionType, CompilerOptions, DataType, DataTypeEnum, Expression, ForLoop, GlobalVariable, Item, MatchStatement, RootScope, Stmt, UnaryExpressionType, is_builtin};
+
+use super::{BasicBlock, BlockId, Body, Constant, Extern, Global, Local, LocalDecl, Operand, Place, Program, Projection, Root, Rvalue, Statement, StatementKind, Terminator, TerminatorKind, UnaryOperation};
+
+pub fn lower(root: &RootScope, data_types: &HashMap<String, DataType>, options: &CompilerOptions) -> Program {
+    Lowering::new(data_types.clone(), options.clone()).lower(root)
+}
+
+// Functions and globals declared beforehand can be used without being lowered again, Ex: earlier repl inputs
+pub struct Lowering {
+    data_types: HashMap<String, DataType>,
+    options: CompilerOptions,
+    functions: HashMap<String, DataType>,
+    globals: HashMap<String, DataType>,
+}
+
+impl Lowering {
+    pub fn new(data_types: HashMap<String, DataType>, options: CompilerOptions) -> Self {
+        Self {
+            data_types,
+            options,
+            functions: HashMap::new(),
+            globals: HashMap::new(),
+        }
+    }
+
+    pub fn declare_function(&mut self, function: &ast::Function) {
+        let params = function.params.iter().map(|(_, data_type)| data_type.clone()).collect();
+        self.functions.insert(function.name.clone(), DataType::function(params, function.return_type.clone()));
+    }
+
+    pub fn declare_global(&mut self, global: &GlobalVariable) {
+        self.globals.insert(global.name.clone(), global.data_type.clone());
+    }
+
+    pub fn lower(&mut self, root: &RootScope) -> Program {
+        // Everything is declared up front so it can be used before its definition
+        for item in &root.items {
+            match item {
+                Item::Function(function) => self.declare_function(function),
+                Item::Global(global) => self.declare_global(global),
+            }
+        }
+        let mut program = Program { globals: Vec::new(), externs: Vec::new(), bodies: Vec::new() };
+        for item in &root.items {
+            match item {
+                Item::Function(function) if function.external => program.externs.push(Extern {
+                    name: function.name.clone(),
+                    params: function.params.iter().map(|(_, data_type)| data_type.clone()).collect(),
+                    return_type: function.return_type.clone(),
+                }),
+                Item::Function(function) => program.bodies.push(BodyBuilder::lower(self, function)),
+                Item::Global(global) => program.globals.push(Global {
+                    name: global.name.clone(),
+                    data_type: global.data_type.clone(),
+                    initializer: global.initializer.as_ref().map(|initializer| constant(initializer, &global.data_type)),
+                }),
+            }
+        }
+        program
+    }
+}
+
+// Global initializers have already been folded to literals
+fn constant(expression: &Expression, data_type: &DataType) -> Constant {
+    match expression {
+        Expression::IntegerLiteral(value) => Constant::Int(*value),
+        Expression::FloatLiteral(value) => Constant::Float(*value),
+        Expression::CharLiteral(value) => Constant::Char(*value),
+        Expression::StringLiteral(value) => Constant::Str(value.clone()),
+        Expression::FunctionReference(name, _) => Constant::Function(name.clone(), data_type.clone()),
+        Expression::Array(values) => {
+            let element = data_type.element_type().unwrap_or_else(|| panic!("{} can't hold an array", data_type.symbol));
+            Constant::Array(values.iter().map(|value| constant(value, element)).collect(), data_type.clone())
+        },
+        _ => panic!("global initializers must be constant"),
+    }
+}
+
+struct BodyBuilder<'a> {
+    lowering: &'a Lowering,
+    body: Body,
+    // Block statements go to, None after a terminator until something else is lowered
+    current: Option<BlockId>,
+    variables: HashMap<String, Local>,
+    // Named heap values, released when the function returns
+    counted: Vec<Local>,
+    // Heap values made by the statements being lowered, Ex: box(1) passed to a call, each holds one reference
+    // until its statement is done unless something kept the value
+    fresh: Vec<Local>,
+    line: usize,
+}
+
+impl<'a> BodyBuilder<'a> {
+    fn lower(lowering: &'a Lowering, function: &ast::Function) -> Body {
+        let mut builder = BodyBuilder {
+            lowering,
+            body: Body {
+                name: function.name.clone(),
+                params: function.params.len() as u32,
+                return_type: function.return_type.clone(),
+                line: function.line,
+                locals: Vec::new(),
+                blocks: Vec::new(),
+            },
+            current: None,
+            variables: HashMap::new(),
+            counted: Vec::new(),
+            fresh: Vec::new(),
+            line: function.line,
+        };
+        for (name, data_type) in &function.params {
+            builder.define(name, data_type);
+        }
+        for stmt in &function.body {
+            builder.statement(stmt);
+        }
+        if builder.current.is_some() && function.return_type.is_none() {
+            builder.release_counted();
+            builder.terminate(TerminatorKind::Return(None));
+        }
+        if builder.body.blocks.is_empty() {
+            builder.new_block();
+        }
+
+        // Counted locals start out null so their first assignment releases nothing
+        let line = builder.body.blocks[0].statements.first().map_or(0, |statement| statement.line);
+        let zeroed: Vec<Statement> = builder.counted.iter().map(|local| {
+            let zero = Constant::Zero(builder.body.locals[local.index()].data_type.clone());
+            Statement { kind: StatementKind::Assign(Place::local(*local), Rvalue::Use(Operand::Constant(zero))), line }
+        }).collect();
+        builder.body.blocks[0].statements.splice(0..0, zeroed);
+        builder.body
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.body.blocks.push(BasicBlock {
+            statements: Vec::new(),
+            terminator: Terminator { kind: TerminatorKind::Unreachable, line: self.line },
+        });
+        BlockId(self.body.blocks.len() as u32 - 1)
+    }
+
+    // Code after a return still needs a block, nothing branches to it
+    fn current_block(&mut self) -> BlockId {
+        match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+                self.current = Some(block);
+                block
+            },
+        }
+    }
+
+    fn push(&mut self, kind: StatementKind) {
+        let block = self.current_block();
+        let line = self.line;
+        self.body.blocks[block.index()].statements.push(Statement { kind, line });
+    }
+
+    fn terminate(&mut self, kind: TerminatorKind) {
+        let block = self.current_block();
+        self.body.blocks[block.index()].terminator = Terminator { kind, line: self.line };
+        self.current = None;
+    }
+
+    fn goto(&mut self, target: BlockId) {
+        if self.current.is_some() {
+            self.terminate(TerminatorKind::Goto(target));
+        }
+    }
+
+    fn temporary(&mut self, data_type: DataType) -> Local {
+        self.body.locals.push(LocalDecl { name: None, data_type, line: self.line });
+        Local(self.body.locals.len() as u32 - 1)
+    }
+
+    fn define(&mut self, name: &str, data_type: &DataType) -> Local {
+        self.body.locals.push(LocalDecl { name: Some(name.to_string()), data_type: data_type.clone(), line: self.line });
+        let local = Local(self.body.locals.len() as u32 - 1);
+        self.variables.insert(name.to_string(), local);
+        local
+    }
+
+    fn assign(&mut self, place: Place, rvalue: Rvalue) {
+        self.push(StatementKind::Assign(place, rvalue));
+    }
+
+    fn temporary_place(&mut self, rvalue: Rvalue) -> Place {
+        let data_type = rvalue.data_type(&self.body).unwrap_or_else(|| panic!("{} has no type", rvalue));
+        let boxed = matches!(rvalue, Rvalue::Box(_) | Rvalue::Vector(..));
+        let local = self.temporary(data_type);
+        self.assign(Place::local(local), rvalue);
+        if boxed {
+            self.own(local);
+        }
+        Place::local(local)
+    }
+
+    fn temporary_value(&mut self, rvalue: Rvalue) -> Operand {
+        Operand::Copy(self.temporary_place(rvalue))
+    }
+
+    fn operand_type(&self, operand: &Operand) -> DataType {
+        operand.data_type(&self.body).unwrap_or_else(|| panic!("{} has no type", operand))
+    }
+
+    fn variable(&self, name: &str) -> Place {
+        if let Some(local) = self.variables.get(name) {
+            return Place::local(*local);
+        }
+        match self.lowering.globals.get(name) {
+            Some(data_type) => Place { root: Root::Global(name.to_string(), data_type.clone()), projections: Vec::new() },
+            None => panic!("unknown variable {}", name),
+        }
+    }
+
+    fn function(&self, name: &str) -> Operand {
+        match self.lowering.functions.get(name) {
+            Some(function_type) => Operand::Constant(Constant::Function(name.to_string(), function_type.clone())),
+            None => panic!("unknown function {}", name),
+        }
+    }
+
+    // Comparisons are bools, the parser types them like their operands
+    fn coerce(&mut self, operand: Operand, data_type: &DataType) -> Operand {
+        let found = self.operand_type(&operand);
+        if found.symbol == "bool" && *data_type != found {
+            return self.temporary_value(Rvalue::Cast(operand, data_type.clone()));
+        }
+        operand
+    }
+
+    fn coerce_rvalue(&mut self, rvalue: Rvalue, data_type: &DataType) -> Rvalue {
+        match rvalue.data_type(&self.body) {
+            Some(found) if found.symbol == "bool" && *data_type != found => Rvalue::Cast(self.temporary_value(rvalue), data_type.clone()),
+            _ => rvalue,
+        }
+    }
+
+    // New values start out unowned, the statement holds them until it's done
+    fn own(&mut self, local: Local) {
+        if self.lowering.options.reference_counting && self.body.locals[local.index()].data_type.is_counted() {
+            self.push(StatementKind::Retain(Operand::Copy(Place::local(local))));
+            self.fresh.push(local);
+        }
+    }
+
+    // Aggregates don't count their elements, a value stored in one has to outlive the statement
+    fn keep(&mut self, operand: &Operand) {
+        if let Some(local) = operand.place().filter(|place| place.projections.is_empty()).and_then(Place::root_local) {
+            self.fresh.retain(|fresh| *fresh != local);
+        }
+    }
+
+    fn release_fresh(&mut self, from: usize) {
+        let releases: Vec<StatementKind> = self.fresh[from..].iter().map(|local| StatementKind::Release(Operand::Copy(Place::local(*local)))).collect();
+        for release in releases {
+            self.push(release);
+        }
+    }
+
+    fn release_counted(&mut self) {
+        for local in self.counted.clone() {
+            self.push(StatementKind::Release(Operand::Copy(Place::local(local))));
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Set(set) => {
+                let value = self.value(&set.value, &set.data_type);
+                let place = match self.variables.contains_key(&set.name) || self.lowering.globals.contains_key(&set.name) {
+                    true => self.variable(&set.name),
+                    false => {
+                        let local = self.define(&set.name, &set.data_type);
+                        if self.lowering.options.reference_counting && set.data_type.is_counted() {
+                            self.counted.push(local);
+                        }
+                        Place::local(local)
+                    },
+                };
+                self.store(place, value, &set.data_type);
+            },
+            // The location is evaluated first only for compound assignments, like the other backends
+            Stmt::Insert(insert) => match insert.operation {
+                Some(ref operation) => {
+                    let place = self.place(&insert.location);
+                    let value = self.operand(&insert.value);
+                    let result = self.binary(operation.clone(), Operand::Copy(place.clone()), value);
+                    self.assign(place, result);
+                },
+                None => {
+                    let value = match insert.data_type {
+                        Some(ref data_type) => self.value(&insert.value, data_type),
+                        None => self.rvalue(&insert.value),
+                    };
+                    if insert.data_type.is_none() {
+                        for operand in value.operands() {
+                            self.keep(operand);
+                        }
+                    }
+                    let place = self.place(&insert.location);
+                    match insert.data_type {
+                        Some(ref data_type) => self.store(place, value, data_type),
+                        None => self.assign(place, value),
+                    }
+                },
+            },
+            Stmt::Return(ret) => {
+                let mut value = self.operand(&ret.value);
+                let return_type = self.body.return_type.clone();
+                if let Some(ref return_type) = return_type {
+                    value = self.coerce(value, return_type);
+                }
+                if self.lowering.options.reference_counting {
+                    let counted = return_type.as_ref().is_some_and(|data_type| data_type.is_counted());
+                    // Keep the returned value alive past the release of the locals, the caller takes it unowned
+                    if counted {
+                        value = self.temporary_value(Rvalue::Use(value));
+                        self.push(StatementKind::Retain(value.clone()));
+                    }
+                    // Statements this return is nested in release their values on the paths that don't return
+                    self.release_fresh(0);
+                    self.release_counted();
+                    if counted {
+                        self.push(StatementKind::ReleaseUnowned(value.clone()));
+                    }
+                }
+                self.terminate(TerminatorKind::Return(Some(value)));
+            },
+            Stmt::If(condition) => {
+                self.line = condition.line;
+                let value = self.operand(&condition.condition);
+                let (then, after) = (self.new_block(), self.new_block());
+                self.terminate(TerminatorKind::Branch(value, then, after));
+                self.current = Some(then);
+                for stmt in &condition.body {
+                    self.statement(stmt);
+                }
+                self.goto(after);
+                self.current = Some(after);
+            },
+            Stmt::For(for_loop) => self.for_loop(for_loop),
+            Stmt::Match(statement) => self.match_statement(statement),
+            Stmt::Expression(expression) => self.effect(expression),
+            Stmt::Located(located) => {
+                self.line = located.line;
+                let start = self.fresh.len();
+                self.statement(&located.statement);
+                if self.current.is_some() {
+                    self.release_fresh(start);
+                }
+                self.fresh.truncate(start);
+            },
+        }
+    }
+
+    // Assignments of heap values retain the new value before releasing the old one
+    fn store(&mut self, place: Place, value: Rvalue, data_type: &DataType) {
+        if !self.lowering.options.reference_counting || !data_type.is_counted() {
+            self.assign(place, value);
+            return;
+        }
+        let local = self.temporary(data_type.clone());
+        self.assign(Place::local(local), value);
+        let value = Operand::Copy(Place::local(local));
+        self.push(StatementKind::Retain(value.clone()));
+        let old = self.temporary_value(Rvalue::Use(Operand::Copy(place.clone())));
+        self.assign(place, Rvalue::Use(value));
+        self.push(StatementKind::Release(old));
+    }
+
+    fn for_loop(&mut self, for_loop: &ForLoop) {
+        self.line = for_loop.line;
+        let i64_type = DataType::primitive("i64");
+        let iterable = match for_loop.iterable_type.value {
+            DataTypeEnum::Array(..) => self.place(&for_loop.iterable),
+            // The handle is read once, the vector behind it may still grow
+            _ => {
+                let handle = self.operand(&for_loop.iterable);
+                self.temporary_place(Rvalue::Use(handle))
+            },
+        };
+        let index = Place::local(self.temporary(i64_type));
+        self.assign(index.clone(), Rvalue::Use(Operand::Constant(Constant::Int(0))));
+        let shadowed = self.variables.get(&for_loop.variable).copied();
+        let variable = self.define(&for_loop.variable, &for_loop.element_type);
+
+        let (condition, body, after) = (self.new_block(), self.new_block(), self.new_block());
+        self.goto(condition);
+        self.current = Some(condition);
+        let len = match for_loop.iterable_type.value {
+            DataTypeEnum::Array(_, len) => Operand::Constant(Constant::Int(len as i64)),
+            _ => self.temporary_value(Rvalue::Len(Operand::Copy(iterable.clone()))),
+        };
+        let in_bounds = self.temporary_value(Rvalue::Binary(BinaryExpressionType::Less, Operand::Copy(index.clone()), len, false));
+        self.terminate(TerminatorKind::Branch(in_bounds, body, after));
+
+        self.current = Some(body);
+        let index_local = index.root_local().unwrap();
+        self.assign(Place::local(variable), Rvalue::Use(Operand::Copy(iterable.project(Projection::Index(index_local)))));
+        for stmt in &for_loop.body {
+            self.statement(stmt);
+        }
+        let next = Rvalue::Binary(BinaryExpressionType::Addition, Operand::Copy(index.clone()), Operand::Constant(Constant::Int(1)), false);
+        self.assign(index, next);
+        self.goto(condition);
+        self.current = Some(after);
+        self.restore(&for_loop.variable, shadowed);
+    }
+
+    // Scoped names go back to the local they shadowed, if any
+    fn restore(&mut self, name: &str, shadowed: Option<Local>) {
+        match shadowed {
+            Some(local) => self.variables.insert(name.to_string(), local),
+            None => self.variables.remove(name),
+        };
+    }
+
+    fn match_statement(&mut self, statement: &MatchStatement) {
+        self.line = statement.line;
+        let scrutinee = self.operand(&statement.scrutinee);
+        let scrutinee = self.temporary_place(Rvalue::Use(scrutinee));
+        let tag = self.temporary_value(Rvalue::Tag(Operand::Copy(scrutinee.clone())));
+        let arm_blocks: Vec<BlockId> = statement.arms.iter().map(|_| self.new_block()).collect();
+        let after = self.new_block();
+        // Exhaustive matches never take the default target
+        let otherwise = match statement.arms.iter().position(|arm| arm.tag.is_none()) {
+            Some(arm) => arm_blocks[arm],
+            None => self.new_block(),
+        };
+        let targets = statement.arms.iter().zip(&arm_blocks).filter_map(|(arm, block)| Some((arm.tag?, *block))).collect();
+        self.terminate(TerminatorKind::Switch(tag, targets, otherwise));
+
+        for (arm, block) in statement.arms.iter().zip(arm_blocks) {
+            self.current = Some(block);
+            self.line = statement.line;
+            let shadowed: Vec<Option<Local>> = arm.bindings.iter().map(|(name, _)| self.variables.get(name).copied()).collect();
+            if let Some(tag) = arm.tag {
+                for (i, (name, data_type)) in arm.bindings.iter().enumerate() {
+                    let binding = self.define(name, data_type);
+                    let field = scrutinee.clone().project(Projection::VariantField(tag, i as u32));
+                    self.assign(Place::local(binding), Rvalue::Use(Operand::Copy(field)));
+                }
+            }
+            for stmt in &arm.body {
+                self.statement(stmt);
+            }
+            self.goto(after);
+            for ((name, _), shadowed) in arm.bindings.iter().zip(shadowed) {
+                self.restore(name, shadowed);
+            }
+        }
+        self.current = Some(after);
+    }
+
+    // Expression statements, calls without a value are only valid here
+    fn effect(&mut self, expression: &Expression) {
+        match expression {
+            Expression::FunctionCall(name, args) if name == "push" => {
+                let handle = self.operand(&args[0]);
+                let value = self.element(&args[1]);
+                self.push(StatementKind::Push(handle, value));
+            },
+            Expression::FunctionCall(name, args) if !is_builtin(name) => {
+                let callee = self.function(name);
+                self.call(callee, args, false);
+            },
+            Expression::IndirectCall(callee, args) => {
+                let callee = self.operand(callee);
+                self.call(callee, args, false);
+            },
+            _ => {
+                self.operand(expression);
+            },
+        }
+    }
+
+    // Ex: x: vec[i64] = vec[], the literal alone has no type
+    fn value(&mut self, expression: &Expression, data_type: &DataType) -> Rvalue {
+        if let Expression::VectorLiteral(values) = expression {
+            let values = values.iter().map(|value| self.element(value)).collect();
+            return Rvalue::Vector(data_type.clone(), values);
+        }
+        let rvalue = self.rvalue(expression);
+        self.coerce_rvalue(rvalue, data_type)
+    }
+
+    fn call(&mut self, callee: Operand, args: &[Box<Expression>], keep_result: bool) -> Option<Operand> {
+        let function_type = self.operand_type(&callee);
+        let DataTypeEnum::Function(ref params, ref return_type) = function_type.value else {
+            panic!("{} is not a function", callee);
+        };
+        let args = args.iter().zip(params).map(|(arg, param)| {
+            let operand = self.operand(arg);
+            self.coerce(operand, param)
+        }).collect();
+        let destination = match return_type {
+            Some(return_type) if keep_result => Some(self.temporary(*return_type.clone())),
+            _ => None,
+        };
+        self.push(StatementKind::Call(callee, args, destination.map(Place::local)));
+        // Heap values are returned unowned
+        if let Some(local) = destination {
+            self.own(local);
+        }
+        destination.map(|local| Operand::Copy(Place::local(local)))
+    }
+
+    fn element(&mut self, expression: &Expression) -> Operand {
+        let operand = self.operand(expression);
+        self.keep(&operand);
+        operand
+    }
+
+    fn operand(&mut self, expression: &Expression) -> Operand {
+        match expression {
+            Expression::IntegerLiteral(value) => Operand::Constant(Constant::Int(*value)),
+            Expression::FloatLiteral(value) => Operand::Constant(Constant::Float(*value)),
+            Expression::CharLiteral(value) => Operand::Constant(Constant::Char(*value)),
+            Expression::StringLiteral(value) => Operand::Constant(Constant::Str(value.clone())),
+            Expression::FunctionReference(name, _) => self.function(name),
+            Expression::VariableRead(_) | Expression::VariableExtract(..) | Expression::FieldAccess(..) |
+            Expression::Unary(Some(_), UnaryExpressionType::Dereference) => Operand::Copy(self.place(expression)),
+            _ => {
+                let rvalue = self.rvalue(expression);
+                self.temporary_value(rvalue)
+            },
+        }
+    }
+
+    fn rvalue(&mut self, expression: &Expression) -> Rvalue {
+        match expression {
+            Expression::Binary(Some(left), Some(right), operation) => {
+                let left = self.operand(left);
+                let right = self.operand(right);
+                self.binary(operation.clone(), left, right)
+            },
+            Expression::Binary(..) | Expression::Unary(None, _) => panic!("incomplete expression"),
+            Expression::Unary(Some(interior), UnaryExpressionType::Reference) => Rvalue::Ref(self.place(interior)),
+            Expression::Unary(Some(interior), UnaryExpressionType::BitwiseNot) => Rvalue::Unary(UnaryOperation::Not, self.operand(interior)),
+            Expression::Unary(Some(interior), UnaryExpressionType::Negation) => Rvalue::Unary(UnaryOperation::Negate, self.operand(interior)),
+            Expression::Array(values) => Rvalue::Array(values.iter().map(|value| self.element(value)).collect()),
+            Expression::VectorLiteral(values) => {
+                let values: Vec<Operand> = values.iter().map(|value| self.element(value)).collect();
+                let element = values.first().map(|value| self.operand_type(value)).expect("an empty vector literal needs a type");
+                Rvalue::Vector(DataType::vector(element), values)
+            },
+            Expression::FunctionCall(name, args) if is_builtin(name) => self.builtin(name, args),
+            Expression::FunctionCall(name, args) => {
+                let callee = self.function(name);
+                Rvalue::Use(self.call(callee, args, true).unwrap_or_else(|| panic!("{} has no value", name)))
+            },
+            Expression::IndirectCall(callee, args) => {
+                let callee = self.operand(callee);
+                Rvalue::Use(self.call(callee, args, true).expect("the function has no value"))
+            },
+            Expression::ExpressionCast(interior, resultant) => Rvalue::Cast(self.operand(interior), DataType::primitive(resultant)),
+            Expression::EnumVariant(name, variant, payload) => {
+                let lowering = self.lowering;
+                let Some((enum_type, tag)) = lowering.data_types.get(name).and_then(|enum_type| Some((enum_type, enum_type.variant(variant)?.0))) else {
+                    panic!("unknown variant {}.{}", name, variant);
+                };
+                Rvalue::Variant(enum_type.clone(), tag, payload.iter().map(|value| self.element(value)).collect())
+            },
+            _ => Rvalue::Use(self.operand(expression)),
+        }
+    }
+
+    fn binary(&mut self, operation: BinaryExpressionType, left: Operand, right: Operand) -> Rvalue {
+        let integer = self.operand_type(&left).symbol != "f64";
+        let can_overflow = matches!(operation, BinaryExpressionType::Addition | BinaryExpressionType::Subtraction |
+            BinaryExpressionType::Multiplication | BinaryExpressionType::Division | BinaryExpressionType::Modulo |
+            BinaryExpressionType::ShiftLeft | BinaryExpressionType::ShiftRight);
+        Rvalue::Binary(operation, left, right, self.lowering.options.overflow_checks && integer && can_overflow)
+    }
+
+    fn builtin(&mut self, name: &str, args: &[Box<Expression>]) -> Rvalue {
+        match name {
+            "box" => {
+                let value = self.operand(&args[0]);
+                // A box of a heap value holds its own reference, the runtime releases it with the box
+                if self.lowering.options.reference_counting && self.operand_type(&value).is_counted() {
+                    self.push(StatementKind::Retain(value.clone()));
+                }
+                Rvalue::Box(value)
+            },
+            "pop" => Rvalue::Pop(self.operand(&args[0])),
+            "len" => Rvalue::Len(self.operand(&args[0])),
+            "push" => panic!("push has no value"),
+            "string" => {
+                let Some(Expression::StringLiteral(value)) = args.first().map(|arg| &**arg) else {
+                    panic!("string needs a string literal");
+                };
+                let chars = value.bytes().map(|byte| Operand::Constant(Constant::Char(byte))).collect();
+                Rvalue::Vector(DataType::vector(DataType::primitive("char")), chars)
+            },
+            _ if name.starts_with("wrapping_") || name.starts_with("checked_") => {
+                let (mode, operation) = name.split_once('_').unwrap();
+                let operation = match operation {
+                    "add" => BinaryExpressionType::Addition,
+                    "sub" => BinaryExpressionType::Subtraction,
+                    "mul" => BinaryExpressionType::Multiplication,
+                    "div" => BinaryExpressionType::Division,
+                    _ => unreachable!()
+                };
+                let left = self.operand(&args[0]);
+                let right = self.operand(&args[1]);
+                if mode == "wrapping" {
+                    return Rvalue::Binary(operation, left, right, false);
+                }
+
+                // The result is only stored when it fits, the check already ruled out every trap
+                let out = match self.operand(&args[2]) {
+                    Operand::Copy(place) => place,
+                    constant => self.temporary_place(Rvalue::Use(constant)),
+                };
+                let fits = self.temporary_value(Rvalue::Fits(operation.clone(), left.clone(), right.clone()));
+                let (store, after) = (self.new_block(), self.new_block());
+                self.terminate(TerminatorKind::Branch(fits.clone(), store, after));
+                self.current = Some(store);
+                self.assign(out.project(Projection::Deref), Rvalue::Binary(operation, left, right, false));
+                self.goto(after);
+                self.current = Some(after);
+                Rvalue::Use(fits)
+            },
+            _ => panic!("unknown builtin {}", name),
+        }
+    }
+
+    // Where the expression is stored, values that aren't stored anywhere get a temporary
+    fn place(&mut self, expression: &Expression) -> Place {
+        match expression {
+            Expression::VariableRead(name) => self.variable(name),
+            Expression::VariableExtract(base, index) => {
+                let base = self.place(base);
+                let index = self.operand(index);
+                let index = match index {
+                    Operand::Copy(Place { root: Root::Local(local), ref projections }) if projections.is_empty() => local,
+                    _ => self.temporary_place(Rvalue::Use(index)).root_local().unwrap(),
+                };
+                if self.lowering.options.bounds_checks {
+                    let len = match base.data_type(&self.body).map(|data_type| data_type.value) {
+                        Some(DataTypeEnum::Array(_, len)) => Operand::Constant(Constant::Int(len as i64)),
+                        _ => self.temporary_value(Rvalue::Len(Operand::Copy(base.clone()))),
+                    };
+                    self.push(StatementKind::BoundsCheck(Operand::Copy(Place::local(index)), len));
+                }
+                base.project(Projection::Index(index))
+            },
+            Expression::FieldAccess(base, field) => {
+                let base = self.place(base);
+                let data_type = base.data_type(&self.body).unwrap_or_else(|| panic!("{} has no type", base));
+                let Some((index, _)) = data_type.field(field) else {
+                    panic!("{} has no field {}", data_type.symbol, field);
+                };
+                base.project(Projection::Field(index as u32))
+            },
+            Expression::Unary(Some(interior), UnaryExpressionType::Dereference) => {
+                let pointer = match self.operand(interior) {
+                    Operand::Copy(place) => place,
+                    constant => self.temporary_place(Rvalue::Use(constant)),
+                };
+                pointer.project(Projection::Deref)
+            },
+            _ => {
+                let rvalue = self.rvalue(expression);
+                self.temporary_place(rvalue)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::Parser;
+
+    fn lower_source(source: &str, options: CompilerOptions) -> Program {
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        lower(&root, &parser.data_types, &options)
+    }
+
+    #[test]
+    fn lowers_control_flow_to_blocks() {
+        let source = "def main(): i64 {\n    x = 1\n    if x < 2 {\n        x = 5\n    }\n    return x\n}\n";
+        let program = lower_source(source, CompilerOptions::default());
+        assert_eq!(program.to_string(), "
+def main(): i64 { // line 1
+    let _0: i64 // x, line 2
+    let _1: bool
+    bb0: {
+        _0 = const 1 // line 2
+        _1 = Less(_0, const 2) // line 3
+        branch _1, bb1, bb2
+    }
+    bb1: {
+        _0 = const 5 // line 4
+        goto bb2
+    }
+    bb2: {
+        return _0 // line 6
+    }
+}
+");
+    }
+
+    #[test]
+    fn checks_are_explicit_statements() {
+        let source = "def get(values: [i64:3], i: i64): i64 {\n    return values[i] + 1\n}\n";
+        let program = lower_source(source, CompilerOptions { overflow_checks: true, ..Default::default() });
+        let body = program.to_string();
+        assert!(body.contains("bounds_check(_1, const 3) // line 2"));
+        assert!(body.contains("_2 = CheckedAddition(_0[_1], const 1)"));
+
+        let program = lower_source(source, CompilerOptions { bounds_checks: false, ..Default::default() });
+        assert!(!program.to_string().contains("bounds_check"));
+    }
+
+    #[test]
+    fn counts_references_of_heap_locals() {
+        let source = "def make(): box[i64] {\n    b = box(4)\n    return b\n}\n";
+        let program = lower_source(source, CompilerOptions { reference_counting: true, ..Default::default() });
+        let statements: Vec<String> = program.bodies[0].blocks[0].statements.iter().map(|statement| statement.kind.to_string()).collect();
+        assert_eq!(statements, [
+            "_0 = const zeroed box[i64]", "_1 = box(const 4)", "retain(_1)", "_2 = _0", "_0 = _1", "release(_2)",
+            "_3 = _0", "retain(_3)", "release(_0)", "release_unowned(_3)",
+        ]);
+    }
+
+    #[test]
+    fn boxes_hold_a_reference_to_heap_values() {
+        let source = "def main(): i64 {\n    b = box(box(4))\n    return **b\n}\n";
+        let program = lower_source(source, CompilerOptions { reference_counting: true, ..Default::default() });
+        let statements: Vec<String> = program.bodies[0].blocks[0].statements.iter().map(|statement| statement.kind.to_string()).collect();
+        assert_eq!(statements[1..6], ["_0 = box(const 4)", "retain(_0)", "retain(_0)", "_2 = box(_0)", "retain(_2)"]);
+    }
+
+    #[test]
+    fn releases_temporaries_at_the_end_of_the_statement() {
+        let source = "def main(): i64 {\n    x = *box(3)\n    return x\n}\n";
+        let program = lower_source(source, CompilerOptions { reference_counting: true, ..Default::default() });
+        let statements: Vec<String> = program.bodies[0].blocks[0].statements.iter().map(|statement| statement.kind.to_string()).collect();
+        assert_eq!(statements, ["_0 = box(const 3)", "retain(_0)", "_1 = (*_0)", "release(_0)"]);
+    }
+
+    #[test]
+    fn loop_variables_restore_shadowed_locals() {
+        let source = "def main(): i64 {\n    x = 1\n    for x in [2, 3] {\n    }\n    return x\n}\n";
+        let program = lower_source(source, CompilerOptions::default());
+        let body = &program.bodies[0];
+        let last = body.blocks.last().unwrap();
+        assert_eq!(last.terminator.kind.to_string(), "return _0");
+    }
+
+    #[test]
+    fn match_bindings_restore_shadowed_locals() {
+        let source = "enum Shape { Circle(f64), Rect(f64, f64) }\ndef main(): f64 {\n    width = 1.0\n    shape = Shape.Rect(2.0, 3.0)\n    match shape {\n        Rect(width, height) {\n        }\n        else {\n        }\n    }\n    return width\n}\n";
+        let program = lower_source(source, CompilerOptions::default());
+        let last = program.bodies[0].blocks.iter().rev().find(|block| matches!(block.terminator.kind, TerminatorKind::Return(_))).unwrap();
+        assert_eq!(last.terminator.kind.to_string(), "return _0");
+    }
+}
@@ -0,0 +1,512 @@
+mod analysis;
+mod lower;
+mod verify;
+
+pub use analysis::lint;
+pub use lower::lower;
+pub use verify::verify;
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::{BinaryExpressionType, DataType, DataTypeEnum};
+
+// Typed control flow graph between the tree and a backend, one Body per function
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Local(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub globals: Vec<Global>,
+    pub externs: Vec<Extern>,
+    pub bodies: Vec<Body>,
+}
+
+// Declared with extern, the definition comes from a library the program is linked with
+#[derive(Clone, Debug)]
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<DataType>,
+    pub return_type: Option<DataType>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Global {
+    pub name: String,
+    pub data_type: DataType,
+    // None is zeroed
+    pub initializer: Option<Constant>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Body {
+    pub name: String,
+    // Parameters are the first locals
+    pub params: u32,
+    pub return_type: Option<DataType>,
+    // Line of the definition
+    pub line: usize,
+    pub locals: Vec<LocalDecl>,
+    // Execution starts at bb0
+    pub blocks: Vec<BasicBlock>,
+}
+
+// Temporaries have no name
+#[derive(Clone, Debug)]
+pub struct LocalDecl {
+    pub name: Option<String>,
+    pub data_type: DataType,
+    // Line of the statement that introduced it, the definition's for parameters
+    pub line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicBlock {
+    pub statements: Vec<Statement>,
+    pub terminator: Terminator,
+}
+
+#[derive(Clone, Debug)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum StatementKind {
+    Assign(Place, Rvalue),
+    // Aborts unless 0 <= index < len
+    BoundsCheck(Operand, Operand),
+    Retain(Operand),
+    Release(Operand),
+    // Drops a reference without freeing, the caller takes over the value
+    ReleaseUnowned(Operand),
+    // Callee is a function constant for direct calls
+    Call(Operand, Vec<Operand>, Option<Place>),
+    // Vector handle and the value to append
+    Push(Operand, Operand),
+}
+
+#[derive(Clone, Debug)]
+pub struct Terminator {
+    pub kind: TerminatorKind,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum TerminatorKind {
+    Goto(BlockId),
+    // Condition, then and else
+    Branch(Operand, BlockId, BlockId),
+    // Enum tag, one target per tag and the target for every other tag
+    Switch(Operand, Vec<(u64, BlockId)>, BlockId),
+    Return(Option<Operand>),
+    Unreachable,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Root {
+    Local(Local),
+    Global(String, DataType),
+}
+
+// A location that can be read, assigned or referenced, Ex: (*_1).2[_3]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Place {
+    pub root: Root,
+    pub projections: Vec<Projection>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Projection {
+    Deref,
+    Field(u32),
+    // Element of an array or vector
+    Index(Local),
+    // Field of the payload of an enum with the given tag
+    VariantField(u64, u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    Copy(Place),
+    Constant(Constant),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Char(u8),
+    Str(String),
+    // Only global initializers hold constant arrays, other arrays are aggregates
+    Array(Vec<Constant>, DataType),
+    Function(String, DataType),
+    Zero(DataType),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOperation {
+    Not,
+    Negate,
+}
+
+#[derive(Clone, Debug)]
+pub enum Rvalue {
+    Use(Operand),
+    // The flag turns on overflow and shift checks
+    Binary(BinaryExpressionType, Operand, Operand, bool),
+    // Whether the checked operation neither overflows nor divides by zero
+    Fits(BinaryExpressionType, Operand, Operand),
+    Unary(UnaryOperation, Operand),
+    Ref(Place),
+    Cast(Operand, DataType),
+    Array(Vec<Operand>),
+    // Type of the vector, its literal may be empty
+    Vector(DataType, Vec<Operand>),
+    Variant(DataType, u64, Vec<Operand>),
+    Box(Operand),
+    Len(Operand),
+    Pop(Operand),
+    Tag(Operand),
+}
+
+impl Local {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl BlockId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Place {
+    pub fn local(local: Local) -> Self {
+        Place { root: Root::Local(local), projections: Vec::new() }
+    }
+
+    pub fn project(mut self, projection: Projection) -> Self {
+        self.projections.push(projection);
+        self
+    }
+
+    pub fn root_local(&self) -> Option<Local> {
+        match self.root {
+            Root::Local(local) => Some(local),
+            Root::Global(..) => None,
+        }
+    }
+
+    // None when a projection doesn't apply to the type it is used on
+    pub fn data_type(&self, body: &Body) -> Option<DataType> {
+        let mut data_type = match self.root {
+            Root::Local(local) => body.locals.get(local.index())?.data_type.clone(),
+            Root::Global(_, ref data_type) => data_type.clone(),
+        };
+        for projection in &self.projections {
+            data_type = projection.apply(&data_type)?;
+        }
+        Some(data_type)
+    }
+}
+
+impl Projection {
+    pub fn apply(&self, data_type: &DataType) -> Option<DataType> {
+        match (self, &data_type.value) {
+            (Projection::Deref, DataTypeEnum::Pointer(interior) | DataTypeEnum::Heap(interior)) => Some(*interior.clone()),
+            (Projection::Field(index), DataTypeEnum::Struct(fields, _)) => fields.get(*index as usize).map(|field| *field.clone()),
+            (Projection::Index(_), _) => data_type.element_type().cloned(),
+            (Projection::VariantField(tag, index), DataTypeEnum::Enum(variants)) => {
+                variants.get(*tag as usize)?.1.get(*index as usize).cloned()
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Constant {
+    pub fn data_type(&self) -> DataType {
+        match self {
+            Constant::Int(_) => DataType::primitive("i64"),
+            Constant::Float(_) => DataType::primitive("f64"),
+            Constant::Char(_) => DataType::primitive("char"),
+            Constant::Str(value) => DataType::array(DataType::primitive("char"), value.len() as u64),
+            Constant::Array(_, data_type) | Constant::Function(_, data_type) | Constant::Zero(data_type) => data_type.clone(),
+        }
+    }
+}
+
+impl Operand {
+    pub fn data_type(&self, body: &Body) -> Option<DataType> {
+        match self {
+            Operand::Copy(place) => place.data_type(body),
+            Operand::Constant(constant) => Some(constant.data_type()),
+        }
+    }
+
+    pub fn place(&self) -> Option<&Place> {
+        match self {
+            Operand::Copy(place) => Some(place),
+            Operand::Constant(_) => None,
+        }
+    }
+}
+
+impl Rvalue {
+    pub fn data_type(&self, body: &Body) -> Option<DataType> {
+        match self {
+            Rvalue::Use(operand) | Rvalue::Unary(_, operand) => operand.data_type(body),
+            Rvalue::Binary(operation, _, _, _) if operation.is_comparison() => Some(DataType::primitive("bool")),
+            Rvalue::Fits(..) => Some(DataType::primitive("bool")),
+            Rvalue::Binary(_, left, _, _) => left.data_type(body),
+            Rvalue::Ref(place) => Some(DataType::pointer(place.data_type(body)?)),
+            Rvalue::Cast(_, data_type) | Rvalue::Vector(data_type, _) | Rvalue::Variant(data_type, _, _) => Some(data_type.clone()),
+            Rvalue::Array(values) => Some(DataType::array(values.first()?.data_type(body)?, values.len() as u64)),
+            Rvalue::Box(operand) => Some(DataType::heap(operand.data_type(body)?)),
+            Rvalue::Len(_) | Rvalue::Tag(_) => Some(DataType::primitive("i64")),
+            Rvalue::Pop(operand) => operand.data_type(body)?.element_type().cloned(),
+        }
+    }
+
+    // Operands read by the rvalue, places that are only referenced aren't included
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Rvalue::Use(operand) | Rvalue::Unary(_, operand) | Rvalue::Cast(operand, _) | Rvalue::Box(operand) |
+            Rvalue::Len(operand) | Rvalue::Pop(operand) | Rvalue::Tag(operand) => vec![operand],
+            Rvalue::Binary(_, left, right, _) | Rvalue::Fits(_, left, right) => vec![left, right],
+            Rvalue::Array(values) | Rvalue::Vector(_, values) | Rvalue::Variant(_, _, values) => values.iter().collect(),
+            Rvalue::Ref(_) => vec![],
+        }
+    }
+}
+
+impl StatementKind {
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            StatementKind::Assign(_, rvalue) => rvalue.operands(),
+            StatementKind::BoundsCheck(index, len) | StatementKind::Push(index, len) => vec![index, len],
+            StatementKind::Retain(operand) | StatementKind::Release(operand) | StatementKind::ReleaseUnowned(operand) => vec![operand],
+            StatementKind::Call(callee, args, _) => std::iter::once(callee).chain(args).collect(),
+        }
+    }
+}
+
+impl TerminatorKind {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            TerminatorKind::Goto(target) => vec![*target],
+            TerminatorKind::Branch(_, then, otherwise) => vec![*then, *otherwise],
+            TerminatorKind::Switch(_, targets, otherwise) => targets.iter().map(|(_, target)| *target).chain([*otherwise]).collect(),
+            TerminatorKind::Return(_) | TerminatorKind::Unreachable => vec![],
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            TerminatorKind::Branch(operand, _, _) | TerminatorKind::Switch(operand, _, _) | TerminatorKind::Return(Some(operand)) => vec![operand],
+            _ => vec![],
+        }
+    }
+}
+
+impl Body {
+    pub fn local_name(&self, local: Local) -> String {
+        match self.locals.get(local.index()).and_then(|decl| decl.name.clone()) {
+            Some(name) => name,
+            None => local.to_string(),
+        }
+    }
+}
+
+impl Display for Local {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "_{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl Display for Place {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut text = match self.root {
+            Root::Local(local) => local.to_string(),
+            Root::Global(ref name, _) => format!("@{}", name),
+        };
+        for projection in &self.projections {
+            text = match projection {
+                Projection::Deref => format!("(*{})", text),
+                Projection::Field(index) => format!("{}.{}", text, index),
+                Projection::Index(local) => format!("{}[{}]", text, local),
+                Projection::VariantField(tag, index) => format!("({} as {}).{}", text, tag, index),
+            };
+        }
+        write!(f, "{}", text)
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(value) => write!(f, "{}", value),
+            Constant::Float(value) => write!(f, "{:?}", value),
+            Constant::Char(value) => write!(f, "{:?}", *value as char),
+            Constant::Str(value) => write!(f, "{:?}", value),
+            Constant::Array(values, _) => write!(f, "[{}]", join(values)),
+            Constant::Function(name, _) => write!(f, "fn {}", name),
+            Constant::Zero(data_type) => write!(f, "zeroed {}", data_type.symbol),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Copy(place) => write!(f, "{}", place),
+            Operand::Constant(constant) => write!(f, "const {}", constant),
+        }
+    }
+}
+
+impl Display for Rvalue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Rvalue::Use(operand) => write!(f, "{}", operand),
+            Rvalue::Binary(operation, left, right, checked) => {
+                write!(f, "{}{:?}({}, {})", if *checked { "Checked" } else { "" }, operation, left, right)
+            },
+            Rvalue::Fits(operation, left, right) => write!(f, "Fits{:?}({}, {})", operation, left, right),
+            Rvalue::Unary(operation, operand) => write!(f, "{:?}({})", operation, operand),
+            Rvalue::Ref(place) => write!(f, "&{}", place),
+            Rvalue::Cast(operand, data_type) => write!(f, "{} as {}", operand, data_type.symbol),
+            Rvalue::Array(values) => write!(f, "[{}]", join(values)),
+            Rvalue::Vector(_, values) => write!(f, "vec[{}]", join(values)),
+            Rvalue::Variant(data_type, tag, values) => {
+                let DataTypeEnum::Enum(ref variants) = data_type.value else {
+                    return write!(f, "{}::{}({})", data_type.symbol, tag, join(values));
+                };
+                write!(f, "{}.{}({})", data_type.symbol, variants[*tag as usize].0, join(values))
+            },
+            Rvalue::Box(operand) => write!(f, "box({})", operand),
+            Rvalue::Len(operand) => write!(f, "len({})", operand),
+            Rvalue::Pop(operand) => write!(f, "pop({})", operand),
+            Rvalue::Tag(operand) => write!(f, "tag({})", operand),
+        }
+    }
+}
+
+impl Display for StatementKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StatementKind::Assign(place, rvalue) => write!(f, "{} = {}", place, rvalue),
+            StatementKind::BoundsCheck(index, len) => write!(f, "bounds_check({}, {})", index, len),
+            StatementKind::Retain(operand) => write!(f, "retain({})", operand),
+            StatementKind::Release(operand) => write!(f, "release({})", operand),
+            StatementKind::ReleaseUnowned(operand) => write!(f, "release_unowned({})", operand),
+            StatementKind::Call(callee, args, destination) => {
+                if let Some(destination) = destination {
+                    write!(f, "{} = ", destination)?;
+                }
+                match callee {
+                    Operand::Constant(Constant::Function(name, _)) => write!(f, "call {}({})", name, join(args)),
+                    _ => write!(f, "call ({})({})", callee, join(args)),
+                }
+            },
+            StatementKind::Push(handle, value) => write!(f, "push({}, {})", handle, value),
+        }
+    }
+}
+
+impl Display for TerminatorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TerminatorKind::Goto(target) => write!(f, "goto {}", target),
+            TerminatorKind::Branch(condition, then, otherwise) => write!(f, "branch {}, {}, {}", condition, then, otherwise),
+            TerminatorKind::Switch(tag, targets, otherwise) => {
+                let targets: Vec<String> = targets.iter().map(|(value, target)| format!("{}: {}", value, target)).collect();
+                write!(f, "switch {} [{}, otherwise: {}]", tag, targets.join(", "), otherwise)
+            },
+            TerminatorKind::Return(Some(value)) => write!(f, "return {}", value),
+            TerminatorKind::Return(None) => write!(f, "return"),
+            TerminatorKind::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+// Ex:
+// def square(_0: i64): i64 { // line 2
+//     let _1: i64 // y, line 3
+//     bb0: {
+//         _1 = Multiplication(_0, _0) // line 3
+//         return _1
+//     }
+// }
+impl Display for Body {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.locals.iter().take(self.params as usize).enumerate()
+            .map(|(index, decl)| format!("_{}: {}", index, decl.data_type.symbol))
+            .collect();
+        write!(f, "def {}({})", self.name, params.join(", "))?;
+        if let Some(ref return_type) = self.return_type {
+            write!(f, ": {}", return_type.symbol)?;
+        }
+        writeln!(f, " {{ // line {}", self.line)?;
+        for (index, decl) in self.locals.iter().enumerate().skip(self.params as usize) {
+            write!(f, "    let _{}: {}", index, decl.data_type.symbol)?;
+            match decl.name {
+                Some(ref name) => writeln!(f, " // {}, line {}", name, decl.line)?,
+                None => writeln!(f)?,
+            }
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "    bb{}: {{", index)?;
+            let mut line = None;
+            let lines = block.statements.iter().map(|statement| (statement.kind.to_string(), statement.line));
+            for (text, statement_line) in lines.chain([(block.terminator.kind.to_string(), block.terminator.line)]) {
+                // Lines are only marked where they change
+                match line.replace(statement_line) {
+                    Some(previous) if previous == statement_line => writeln!(f, "        {}", text)?,
+                    _ => writeln!(f, "        {} // line {}", text, statement_line)?,
+                }
+            }
+            writeln!(f, "    }}")?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for global in &self.globals {
+            match global.initializer {
+                Some(ref initializer) => writeln!(f, "global @{}: {} = {}", global.name, global.data_type.symbol, initializer)?,
+                None => writeln!(f, "global @{}: {}", global.name, global.data_type.symbol)?,
+            }
+        }
+        for function in &self.externs {
+            let params: Vec<&str> = function.params.iter().map(|data_type| data_type.symbol.as_str()).collect();
+            match function.return_type {
+                Some(ref return_type) => writeln!(f, "extern def {}({}): {}", function.name, params.join(", "), return_type.symbol)?,
+                None => writeln!(f, "extern def {}({})", function.name, params.join(", "))?,
+            }
+        }
+        for body in &self.bodies {
+            writeln!(f)?;
+            write!(f, "{}", body)?;
+        }
+        Ok(())
+    }
+}
+
+fn join<T: Display>(values: &[T]) -> String {
+    values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", ")
+}
@@ -0,0 +1,252 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::{DataType, DataTypeEnum};
+
+use super::{Body, Operand, Place, Program, Projection, Rvalue, StatementKind, TerminatorKind, UnaryOperation};
+
+#[derive(Debug)]
+pub struct VerifyError {
+    pub function: String,
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.function, self.message)
+    }
+}
+
+// Every local and block exists and every statement is well typed, backends rely on it
+pub fn verify(program: &Program) -> Result<(), VerifyError> {
+    for body in &program.bodies {
+        verify_body(body).map_err(|message| VerifyError { function: body.name.clone(), message })?;
+    }
+    Ok(())
+}
+
+fn verify_body(body: &Body) -> Result<(), String> {
+    if body.blocks.is_empty() {
+        return Err("no blocks".to_string());
+    }
+    if body.params as usize > body.locals.len() {
+        return Err("more parameters than locals".to_string());
+    }
+    for (index, block) in body.blocks.iter().enumerate() {
+        let verify_block = || {
+            for statement in &block.statements {
+                verify_statement(body, &statement.kind).map_err(|message| format!("{}: {}", statement.kind, message))?;
+            }
+            let terminator = &block.terminator.kind;
+            verify_terminator(body, terminator).map_err(|message| format!("{}: {}", terminator, message))
+        };
+        verify_block().map_err(|message| format!("bb{}: {}", index, message))?;
+    }
+    Ok(())
+}
+
+fn verify_statement(body: &Body, kind: &StatementKind) -> Result<(), String> {
+    match kind {
+        StatementKind::Assign(place, rvalue) => expect(&rvalue_type(body, rvalue)?, &place_type(body, place)?),
+        StatementKind::BoundsCheck(index, len) => {
+            expect_integer(&operand_type(body, index)?)?;
+            expect_integer(&operand_type(body, len)?)
+        },
+        StatementKind::Retain(operand) | StatementKind::Release(operand) | StatementKind::ReleaseUnowned(operand) => {
+            match operand_type(body, operand)? {
+                data_type if data_type.is_counted() => Ok(()),
+                data_type => Err(format!("{} isn't reference counted", data_type.symbol)),
+            }
+        },
+        StatementKind::Call(callee, args, destination) => {
+            let function_type = operand_type(body, callee)?;
+            let DataTypeEnum::Function(ref params, ref return_type) = function_type.value else {
+                return Err(format!("{} isn't a function", function_type.symbol));
+            };
+            if params.len() != args.len() {
+                return Err(format!("expected {} arguments", params.len()));
+            }
+            for (param, arg) in params.iter().zip(args) {
+                expect(&operand_type(body, arg)?, param)?;
+            }
+            match (destination, return_type) {
+                (Some(destination), Some(return_type)) => expect(return_type, &place_type(body, destination)?),
+                (Some(_), None) => Err("the function has no value".to_string()),
+                (None, _) => Ok(()),
+            }
+        },
+        StatementKind::Push(handle, value) => {
+            let handle_type = operand_type(body, handle)?;
+            let DataTypeEnum::Vector(ref element) = handle_type.value else {
+                return Err(format!("{} isn't a vector", handle_type.symbol));
+            };
+            expect(&operand_type(body, value)?, element)
+        },
+    }
+}
+
+fn verify_terminator(body: &Body, kind: &TerminatorKind) -> Result<(), String> {
+    for target in kind.successors() {
+        if target.index() >= body.blocks.len() {
+            return Err(format!("{} doesn't exist", target));
+        }
+    }
+    match kind {
+        TerminatorKind::Branch(condition, _, _) => expect(&operand_type(body, condition)?, &DataType::primitive("bool")),
+        TerminatorKind::Switch(tag, _, _) => expect(&operand_type(body, tag)?, &DataType::primitive("i64")),
+        TerminatorKind::Return(value) => match (value, &body.return_type) {
+            (Some(value), Some(return_type)) => expect(&operand_type(body, value)?, return_type),
+            (None, None) => Ok(()),
+            (Some(_), None) => Err("the function has no return type".to_string()),
+            (None, Some(_)) => Err("the function needs a value".to_string()),
+        },
+        TerminatorKind::Goto(_) | TerminatorKind::Unreachable => Ok(()),
+    }
+}
+
+fn rvalue_type(body: &Body, rvalue: &Rvalue) -> Result<DataType, String> {
+    let operands = rvalue.operands().into_iter().map(|operand| operand_type(body, operand)).collect::<Result<Vec<_>, _>>()?;
+    match rvalue {
+        Rvalue::Binary(operation, _, _, _) => {
+            expect(&operands[1], &operands[0])?;
+            if operands[0].symbol != "f64" {
+                expect_integer(&operands[0])?;
+            }
+            if operation.is_comparison() {
+                return Ok(DataType::primitive("bool"));
+            }
+        },
+        Rvalue::Fits(..) => {
+            expect(&operands[1], &operands[0])?;
+            expect_integer(&operands[0])?;
+            return Ok(DataType::primitive("bool"));
+        },
+        Rvalue::Unary(UnaryOperation::Not, _) => expect_integer(&operands[0])?,
+        Rvalue::Unary(UnaryOperation::Negate, _) if operands[0].symbol != "f64" => expect_integer(&operands[0])?,
+        Rvalue::Ref(place) => {
+            place_type(body, place)?;
+        },
+        Rvalue::Cast(_, data_type) => {
+            expect_integer(&operands[0])?;
+            if !matches!(data_type.symbol.as_str(), "i64" | "f64" | "char") {
+                return Err(format!("can't cast to {}", data_type.symbol));
+            }
+        },
+        Rvalue::Array(_) => {
+            let Some(first) = operands.first() else {
+                return Err("empty array".to_string());
+            };
+            for operand in &operands {
+                expect(operand, first)?;
+            }
+        },
+        Rvalue::Vector(data_type, _) => {
+            let Some(element) = data_type.element_type().filter(|_| matches!(data_type.value, DataTypeEnum::Vector(_))) else {
+                return Err(format!("{} isn't a vector", data_type.symbol));
+            };
+            for operand in &operands {
+                expect(operand, element)?;
+            }
+        },
+        Rvalue::Variant(data_type, tag, _) => {
+            let DataTypeEnum::Enum(ref variants) = data_type.value else {
+                return Err(format!("{} isn't an enum", data_type.symbol));
+            };
+            let Some((_, fields)) = variants.get(*tag as usize) else {
+                return Err(format!("{} has no variant {}", data_type.symbol, tag));
+            };
+            if fields.len() != operands.len() {
+                return Err(format!("expected {} fields", fields.len()));
+            }
+            for (operand, field) in operands.iter().zip(fields) {
+                expect(operand, field)?;
+            }
+        },
+        Rvalue::Len(_) if operands[0].element_type().is_none() => return Err(format!("{} has no length", operands[0].symbol)),
+        Rvalue::Pop(_) if !matches!(operands[0].value, DataTypeEnum::Vector(_)) => return Err(format!("{} isn't a vector", operands[0].symbol)),
+        Rvalue::Tag(_) if !matches!(operands[0].value, DataTypeEnum::Enum(_)) => return Err(format!("{} isn't an enum", operands[0].symbol)),
+        _ => {},
+    }
+    rvalue.data_type(body).ok_or_else(|| format!("{} has no type", rvalue))
+}
+
+fn operand_type(body: &Body, operand: &Operand) -> Result<DataType, String> {
+    match operand {
+        Operand::Copy(place) => place_type(body, place),
+        Operand::Constant(constant) => Ok(constant.data_type()),
+    }
+}
+
+fn place_type(body: &Body, place: &Place) -> Result<DataType, String> {
+    let locals = place.projections.iter().filter_map(|projection| match projection {
+        Projection::Index(local) => Some(*local),
+        _ => None,
+    });
+    for local in place.root_local().into_iter().chain(locals) {
+        if local.index() >= body.locals.len() {
+            return Err(format!("{} doesn't exist", local));
+        }
+    }
+    for projection in &place.projections {
+        if let Projection::Index(local) = projection {
+            expect_integer(&body.locals[local.index()].data_type)?;
+        }
+    }
+    place.data_type(body).ok_or_else(|| format!("{} doesn't fit its type", place))
+}
+
+fn expect(found: &DataType, expected: &DataType) -> Result<(), String> {
+    if found != expected {
+        return Err(format!("found {} where {} was expected", found.symbol, expected.symbol));
+    }
+    Ok(())
+}
+
+fn expect_integer(data_type: &DataType) -> Result<(), String> {
+    match data_type.symbol.as_str() {
+        "i64" | "char" | "bool" => Ok(()),
+        _ => Err(format!("{} isn't an integer", data_type.symbol)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::CompilerOptions, conformance, mir::{BasicBlock, BlockId, Constant, Local, LocalDecl, Statement, Terminator, lower}};
+
+    #[test]
+    fn accepts_lowered_programs() {
+        for (name, source, _) in conformance::PROGRAMS {
+            let mut parser = conformance::parser(source);
+            let root = parser.parse().unwrap();
+            let options = CompilerOptions { overflow_checks: true, reference_counting: true, ..Default::default() };
+            let program = lower(&root, &parser.data_types, &options);
+            if let Err(error) = verify(&program) {
+                panic!("{}: {}\n{}", name, error, program);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_ill_typed_bodies() {
+        let i64_type = DataType::primitive("i64");
+        let assign = StatementKind::Assign(Place::local(Local(0)), Rvalue::Use(Operand::Constant(Constant::Float(1.5))));
+        let body = Body {
+            name: "main".to_string(),
+            params: 0,
+            return_type: Some(i64_type.clone()),
+            line: 1,
+            locals: vec![LocalDecl { name: Some("x".to_string()), data_type: i64_type, line: 2 }],
+            blocks: vec![BasicBlock {
+                statements: vec![Statement { kind: assign, line: 2 }],
+                terminator: Terminator { kind: TerminatorKind::Goto(BlockId(1)), line: 3 },
+            }],
+        };
+        let mut program = Program { globals: Vec::new(), externs: Vec::new(), bodies: vec![body] };
+        let error = verify(&program).err().unwrap();
+        assert_eq!(error.to_string(), "main: bb0: _0 = const 1.5: found f64 where i64 was expected");
+
+        program.bodies[0].blocks[0].statements.clear();
+        let error = verify(&program).err().unwrap();
+        assert_eq!(error.message, "bb0: goto bb1: bb1 doesn't exist");
+    }
+}
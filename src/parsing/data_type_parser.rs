@@ -1,16 +1,30 @@
 use std::{collections::HashMap, fmt::format};
 
-use crate::{ast::{DataType, DataTypeEnum}, lexing::{Token, Lexer}};
+use crate::{ast::{DataType, DataTypeEnum, Expression}, lexing::{Token, Lexer}};
 
-enum BuildType {
-    Array,
-    Simple,
+use super::{expression_parser::ExpressionParser, ParsingError, ParsingResult};
+
+enum Frame {
+    Array(Option<u64>),
+    Heap,
+    Vector,
     Reference,
+    // Parameters parsed so far
+    Function(Vec<DataType>),
+    // Parameters of a function waiting on its return type
+    Return(Vec<DataType>),
 }
+
 pub struct DataTypeParser<'a> {
     data_types: &'a HashMap<String, DataType>,
     internal_type: Option<DataType>,
-    build_type: Option<BuildType>,
+    frames: Vec<Frame>,
+    waiting_wrapper: Option<Frame>,
+    size_parser: Option<ExpressionParser<'a>>,
+    closed_function: Option<Vec<DataType>>,
+    // Reported by build, consuming stops at the first one
+    error: Option<ParsingError>,
+    pub constants: Option<&'a HashMap<String, Expression>>,
 }
 
 impl<'a> DataTypeParser<'a> {
@@ -18,11 +32,16 @@ impl<'a> DataTypeParser<'a> {
         Self {
             data_types,
             internal_type: None,
-            build_type: None
+            frames: Vec::new(),
+            waiting_wrapper: None,
+            size_parser: None,
+            closed_function: None,
+            error: None,
+            constants: None,
         }
     }
 
-    pub fn parse_string(&mut self, string: String) -> DataType {
+    pub fn parse_string(&mut self, string: String) -> ParsingResult<DataType> {
         let mut lexer = Lexer::new(string);
         let mut token = lexer.next();
         // dbg!(&token);
@@ -35,46 +54,96 @@ impl<'a> DataTypeParser<'a> {
 
     pub fn consume(&mut self, token: Token) -> bool {
         // dbg!(&token);
-        // if token == Token::EOF {
-            // dbg!(&self.internal_type);
-        // }
+        // Ex: [i64:SIZE * 2], the size is everything up to the closing ]
+        if let Some(ref mut size_parser) = self.size_parser {
+            if size_parser.consume(token.clone()).unwrap_or(false) {
+                return true;
+            }
+            if let Err(error) = self.finish_size() {
+                self.error = Some(error);
+                return false;
+            }
+        }
+        // Ex: fn(i64): i64, a : directly after the parameters starts the return type
+        if let Some(params) = self.closed_function.take() {
+            if token == Token::Colon {
+                self.frames.push(Frame::Return(params));
+                return true;
+            }
+            self.internal_type = Some(DataType::function(params, None));
+            self.wrap_references();
+        }
         match token {
             Token::OpenSquare => {
-                if self.build_type.is_none() {
-                    self.build_type = Some(BuildType::Array);
-                }
+                let frame = self.waiting_wrapper.take().unwrap_or(Frame::Array(None));
+                self.frames.push(frame);
             },
             Token::Identifier(iden) => {
                 // dbg!(&iden);
-                self.internal_type = Some(self.data_types[&iden].clone());
-                if let Some(BuildType::Reference) = &self.build_type {
-                    self.internal_type = Some(DataType {
-                        symbol: format!("&{}", self.internal_type.as_ref().unwrap().symbol),
-                        value: DataTypeEnum::Pointer(Box::new(self.internal_type.as_ref().unwrap().clone())),
-                    });
+                match iden.as_str() {
+                    "box" => self.waiting_wrapper = Some(Frame::Heap),
+                    "vec" => self.waiting_wrapper = Some(Frame::Vector),
+                    "fn" => self.waiting_wrapper = Some(Frame::Function(Vec::new())),
+                    _ => {
+                        self.internal_type = Some(self.data_types[&iden].clone());
+                        self.wrap_references();
+                    }
                 }
             },
             Token::Colon => {
-
+                if let Some(Frame::Array(_)) = self.frames.last() {
+                    let mut size_parser = ExpressionParser::new();
+                    size_parser.data_types = Some(self.data_types);
+                    self.size_parser = Some(size_parser);
+                }
+            },
+            Token::Ampersand => {
+                self.frames.push(Frame::Reference);
+            },
+            Token::OpenParenth => match self.waiting_wrapper.take() {
+                Some(frame @ Frame::Function(_)) => self.frames.push(frame),
+                _ => return false,
             },
-            Token::Integer(size) => {
-                let internal = (self.internal_type.as_ref().unwrap()).clone();
-                let new_data_type = DataType {
-                    symbol: format!("[{}:{}]", &internal.symbol, &size),
-                    value: DataTypeEnum::Array(Box::new(internal), size as u64),
+            Token::Comma => match self.frames.last_mut() {
+                Some(Frame::Function(params)) => params.push(self.internal_type.take().unwrap()),
+                _ => return false,
+            },
+            Token::CloseParenth => {
+                let Some(Frame::Function(_)) = self.frames.last() else {
+                    return false;
                 };
-
-                self.internal_type = Some(new_data_type);
+                let Some(Frame::Function(mut params)) = self.frames.pop() else {
+                    unreachable!()
+                };
+                params.extend(self.internal_type.take());
+                self.closed_function = Some(params);
             },
-            Token::Ampersand => {
-                self.build_type = Some(BuildType::Reference)  
+            Token::CloseSquare => {
+                let Some(frame) = self.frames.pop() else {
+                    return false;
+                };
+                let internal = self.internal_type.take().unwrap();
+                let new_data_type = match frame {
+                    Frame::Array(size) => DataType {
+                        symbol: format!("[{}:{}]", &internal.symbol, size.unwrap()),
+                        value: DataTypeEnum::Array(Box::new(internal), size.unwrap()),
+                    },
+                    Frame::Heap => DataType {
+                        symbol: format!("box[{}]", &internal.symbol),
+                        value: DataTypeEnum::Heap(Box::new(internal)),
+                    },
+                    Frame::Vector => DataType {
+                        symbol: format!("vec[{}]", &internal.symbol),
+                        value: DataTypeEnum::Vector(Box::new(internal)),
+                    },
+                    Frame::Reference | Frame::Function(_) | Frame::Return(_) => unreachable!(),
+                };
+                self.internal_type = Some(new_data_type);
+                self.wrap_references();
             },
-            Token::CloseSquare => return true,
             Token::EOL => return false,
             Token::EOF => return false,
-            Token::Comma => return false,
             Token::Equal => return false,
-            Token::CloseParenth => return false,
             Token::OpenCurly => return false,
             _ => return false,
         }
@@ -82,8 +151,85 @@ impl<'a> DataTypeParser<'a> {
         true
     }
 
-    pub fn build(&mut self) -> DataType {
-        let thing = self.internal_type.as_ref().unwrap().clone();
-        thing
+    fn finish_size(&mut self) -> Result<(), ParsingError> {
+        let mut size_parser = self.size_parser.take().unwrap();
+        let empty = HashMap::new();
+        let size = match size_parser.build() {
+            Ok(size) => size.evaluate_constant(self.constants.unwrap_or(&empty)),
+            Err(error) => return Err(error.downcast::<ParsingError>().map_or(ParsingError::MissingToken, |error| *error)),
+        };
+        // Negative sizes are reported the same way
+        let Some(Expression::IntegerLiteral(size @ 0..)) = size else {
+            return Err(ParsingError::NotConstant("Array size".to_string()));
+        };
+        if let Some(Frame::Array(ref mut len)) = self.frames.last_mut() {
+            *len = Some(size as u64);
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+
+    // A completed type is wrapped by every & written directly before it, or finishes a function's return type
+    fn wrap_references(&mut self) {
+        while let Some(Frame::Reference | Frame::Return(_)) = self.frames.last() {
+            let internal = self.internal_type.take().unwrap();
+            self.internal_type = Some(match self.frames.pop() {
+                Some(Frame::Return(params)) => DataType::function(params, Some(internal)),
+                _ => DataType {
+                    symbol: format!("&{}", internal.symbol),
+                    value: DataTypeEnum::Pointer(Box::new(internal)),
+                },
+            });
+        }
+    }
+
+    pub fn build(&mut self) -> ParsingResult<DataType> {
+        if let Some(error) = self.error.take() {
+            return Err(Box::new(error));
+        }
+        self.internal_type.clone().ok_or_else(|| Box::new(ParsingError::MissingToken) as _)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn primitives() -> HashMap<String, DataType> {
+        let mut data_types = HashMap::new();
+        for name in ["i64", "char"] {
+            data_types.insert(name.to_string(), DataType { symbol: name.to_string(), value: DataTypeEnum::Primitive });
+        }
+        data_types
+    }
+
+    #[test]
+    fn can_parse_nested_types() {
+        let data_types = primitives();
+        for symbol in ["i64", "&i64", "[char:4]", "&[char:4]", "[&i64:2]", "box[i64]", "box[[char:2]]", "&box[i64]", "vec[i64]", "vec[box[char]]",
+            "fn()", "fn(i64): i64", "fn(&i64, [char:4])", "&fn(i64)", "[fn(i64): i64:2]", "fn(fn(i64): i64, i64): char"] {
+            let data_type = DataTypeParser::new(&data_types).parse_string(symbol.to_string()).unwrap();
+            assert_eq!(data_type.symbol, symbol);
+            assert_eq!(data_type.produce_string(), symbol);
+        }
+    }
+
+    #[test]
+    fn array_sizes_can_be_constant_expressions() {
+        let data_types = primitives();
+        let mut constants = HashMap::new();
+        constants.insert("SIZE".to_string(), Expression::IntegerLiteral(4));
+        let mut data_type_parser = DataTypeParser::new(&data_types);
+        data_type_parser.constants = Some(&constants);
+        let data_type = data_type_parser.parse_string("[[char:SIZE * 2]:(SIZE - 1)]".to_string()).unwrap();
+        assert_eq!(data_type.symbol, "[[char:8]:3]");
+    }
+
+    #[test]
+    fn array_sizes_must_be_constant() {
+        let data_types = primitives();
+        for symbol in ["[i64:n]", "[i64:-1]"] {
+            let error = DataTypeParser::new(&data_types).parse_string(symbol.to_string()).err().unwrap();
+            assert_eq!(error.to_string(), "Array size must be a compile-time constant");
+        }
+    }
+}
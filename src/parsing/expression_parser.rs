@@ -1,41 +1,80 @@
-use std::{collections::{VecDeque, HashMap}};
+use std::collections::HashMap;
 
-use crate::{lexing::Token, ast::{Expression, Scope, UnaryExpressionType, DataType}};
+use crate::{lexing::Token, ast::{Expression, Scope, UnaryExpressionType, BinaryExpressionType, DataType, DataTypeEnum}};
 
-use super::{parser::{ParsingResult}, scope_stack::ScopeStack, function_call_parser::FunctionCallParser, DataTypeParser, expression_cast_parser::ExpressionCastParser};
+use super::{parser::{ParsingResult, ParsingError}, scope_stack::ScopeStack, DataTypeParser};
 
-enum WaitingUnaryTypes {
-    Reference,
-    Dereference,
-    Negation,
+enum Operator {
+    Prefix(UnaryExpressionType),
+    Infix(BinaryExpressionType),
+    Call,
+    Index,
+    Field,
+    Cast,
+}
+
+impl Operator {
+    // The single operator table, prefix is true when no operand has been parsed yet
+    fn from_token(token: &Token, prefix: bool) -> Option<Operator> {
+        let operator = match token {
+            Token::Minus if prefix => Operator::Prefix(UnaryExpressionType::Negation),
+            Token::Star if prefix => Operator::Prefix(UnaryExpressionType::Dereference),
+            Token::Ampersand if prefix => Operator::Prefix(UnaryExpressionType::Reference),
+            Token::Tilde if prefix => Operator::Prefix(UnaryExpressionType::BitwiseNot),
+            _ if prefix => return None,
+            Token::Plus => Operator::Infix(BinaryExpressionType::Addition),
+            Token::Minus => Operator::Infix(BinaryExpressionType::Subtraction),
+            Token::Star => Operator::Infix(BinaryExpressionType::Multiplication),
+            Token::Slash => Operator::Infix(BinaryExpressionType::Division),
+            Token::Percent => Operator::Infix(BinaryExpressionType::Modulo),
+            Token::Ampersand => Operator::Infix(BinaryExpressionType::BitwiseAnd),
+            Token::Pipe => Operator::Infix(BinaryExpressionType::BitwiseOr),
+            Token::Caret => Operator::Infix(BinaryExpressionType::BitwiseXor),
+            Token::ShiftLeft => Operator::Infix(BinaryExpressionType::ShiftLeft),
+            Token::ShiftRight => Operator::Infix(BinaryExpressionType::ShiftRight),
+            Token::Lesser => Operator::Infix(BinaryExpressionType::Less),
+            Token::LesserEqual => Operator::Infix(BinaryExpressionType::LessEqual),
+            Token::Greater => Operator::Infix(BinaryExpressionType::Greater),
+            Token::GreaterEqual => Operator::Infix(BinaryExpressionType::GreaterEqual),
+            Token::DoubleEqual => Operator::Infix(BinaryExpressionType::Equal),
+            Token::NotEqual => Operator::Infix(BinaryExpressionType::NotEqual),
+            Token::OpenParenth => Operator::Call,
+            Token::OpenSquare => Operator::Index,
+            Token::Dot => Operator::Field,
+            Token::As => Operator::Cast,
+            _ => return None,
+        };
+        Some(operator)
+    }
+
+    // Higher precidence operations are computed first
+    fn precidence(&self) -> i64 {
+        match self {
+            Operator::Prefix(t) => t.precidence(),
+            Operator::Infix(t) => t.precidence(),
+            Operator::Cast => 9,
+            Operator::Call | Operator::Index | Operator::Field => 20,
+        }
+    }
 }
 
 pub struct ExpressionParser<'a> {
-    // top_expression: Option<Expression>,
-    expression_stack: VecDeque<Expression>,
+    tokens: Vec<Token>,
+    position: usize,
+    depth: i64,
     scope_stack: Option<&'a ScopeStack>,
-    parser_stack: VecDeque<ExpressionParser<'a>>,
-    waiting_variable_name: Option<String>,
-    waiting_unary_operation: Option<WaitingUnaryTypes>, 
-    waiting_function_parser: Option<Box<FunctionCallParser<'a>>>,
-    waiting_data_type_parser: Option<Box<ExpressionCastParser<'a>>>,
     pub data_types: Option<&'a HashMap<String, DataType>>,
-    was_last_binary: bool,
     pub check_stack: bool,
 }
 
 impl<'a> ExpressionParser<'a> {
     pub fn new() -> Self {
         Self {
-            expression_stack: VecDeque::new(),
+            tokens: Vec::new(),
+            position: 0,
+            depth: 0,
             scope_stack: None,
-            parser_stack: VecDeque::new(),
-            waiting_variable_name: None,
-            waiting_unary_operation: None,
-            waiting_function_parser: None,
-            waiting_data_type_parser: None,
             data_types: None,
-            was_last_binary: false,
             check_stack: true,
         }
     }
@@ -46,260 +85,242 @@ impl<'a> ExpressionParser<'a> {
         return new;
     }
 
+    // Collects tokens until one that can't continue the expression, which isn't consumed
     pub fn consume(&mut self, token: Token) -> ParsingResult<bool> {
-        if let Some(ref mut parser) = self.waiting_data_type_parser {
-            if !parser.consume(token)? {
-                let res = parser.build();
-                self.waiting_data_type_parser = None;
-                self.append_expr(res);
-            }
-            return Ok(true);
+        match token {
+            Token::OpenParenth | Token::OpenSquare => self.depth += 1,
+            Token::CloseParenth | Token::CloseSquare if self.depth > 0 => self.depth -= 1,
+            Token::CloseParenth | Token::CloseSquare => return Ok(false),
+            Token::EOL | Token::EOF | Token::OpenCurly | Token::ClosedCurly => return Ok(false),
+            Token::Comma | Token::Colon | Token::Equal if self.depth == 0 => return Ok(false),
+            Token::PlusEqual | Token::MinusEqual | Token::StarEqual | Token::SlashEqual |
+            Token::PercentEqual | Token::AmpersandEqual | Token::PipeEqual | Token::CaretEqual |
+            Token::ShiftLeftEqual | Token::ShiftRightEqual => return Ok(false),
+            _ => {}
         }
-        if let Some(ref mut parser) = self.waiting_function_parser {
-            // dbg!("started parsing function with token");
-            // dbg!(&token);
-            if !parser.consume(token)? {
-                let built = parser.build();
-                self.append_expr(built);
-                self.waiting_function_parser = None;
-            }
-            return Ok(true);
+        self.tokens.push(token);
+
+        Ok(true)
+    }
+
+    pub fn build(&mut self) -> ParsingResult<Expression> {
+        self.position = 0;
+        let expression = self.parse_expression(0)?;
+        if let Some(token) = self.peek() {
+            return Err(Box::new(ParsingError::UnexpectedToken(token)));
         }
-        if !self.parser_stack.is_empty() {
-            let can_continue = self.parser_stack.front_mut().unwrap().consume(token.clone())?;
-            if can_continue { return Ok(true); }
-            let sub_expression = self.parser_stack.pop_front().unwrap().build();
-            // dbg!(&self.expression_stack, &sub_expression);
+        Ok(expression)
+    }
 
-            match token {
-                Token::CloseSquare => {
-                    if let Some(Expression::Array(_)) = self.expression_stack.front() {
-                        let Some(Expression::Array(mut arr)) = self.expression_stack.pop_front() else {
-                            panic!();
-                        };
-                        if sub_expression.is_some() {
-                            arr.push(sub_expression.unwrap());
-                        }
-                        self.expression_stack.push_front(Expression::Array(arr));
-                        return Ok(false);
-                    } else if let Some(ref name) = self.waiting_variable_name {
-                        // dbg!(&self.expression_stack, &sub_expression);
-                        let new_value = Expression::VariableExtract(name.clone(), Box::new(sub_expression.unwrap()));
-                        self.waiting_variable_name = None;
-                        // dbg!(&self.expression_stack);
-                        self.append_expr(new_value);
-                        // dbg!(&self.expression_stack);
-                        return Ok(true);
-                    }
-                }
-                Token::Comma => {
-                    if let Expression::Array(mut arr) = self.expression_stack.pop_front().unwrap() {
-                        arr.push(sub_expression.unwrap());
-                        self.expression_stack.push_front(Expression::Array(arr));
-                        self.parser_stack.push_front(ExpressionParser::with_scope_stack(&self.scope_stack.unwrap()));
+    fn parse_expression(&mut self, min_precidence: i64) -> ParsingResult<Expression> {
+        let mut left = self.parse_prefix()?;
+        while let Some(token) = self.peek() {
+            let Some(operator) = Operator::from_token(&token, false) else {
+                break;
+            };
+            if operator.precidence() < min_precidence {
+                break;
+            }
+            self.advance();
+            left = match operator {
+                Operator::Infix(binary_type) => {
+                    // Binding the right side one level tighter makes operators left associative
+                    let right = self.parse_expression(binary_type.precidence() + 1)?;
+                    Expression::Binary(Some(Box::new(left)), Some(Box::new(right)), binary_type)
+                },
+                Operator::Call => {
+                    let arguments = self.parse_list(Token::CloseParenth)?.into_iter().map(Box::new).collect();
+                    match left {
+                        Expression::VariableRead(name) if !self.is_variable(&name) => Expression::FunctionCall(self.function_name(&name), arguments),
+                        // Ex: math.sqrt(x)
+                        Expression::FieldAccess(module, member) if matches!(*module, Expression::VariableRead(ref module) if !self.is_variable(module)) => {
+                            let Expression::VariableRead(module) = *module else {
+                                unreachable!()
+                            };
+                            let name = format!("{}.{}", module, member);
+                            if !self.is_function(&name) {
+                                return Err(Box::new(ParsingError::UnknownFunction(name)));
+                            }
+                            Expression::FunctionCall(name, arguments)
+                        },
+                        // Ex: handlers[i](x), calls through a function value
+                        Expression::VariableRead(_) | Expression::VariableExtract(..) | Expression::FieldAccess(..) => {
+                            Expression::IndirectCall(Box::new(left), arguments)
+                        },
+                        _ => return Err(Box::new(ParsingError::UnexpectedToken(Token::OpenParenth))),
                     }
                 },
-                Token::CloseParenth => {
-                    if let Some(new_expression) = sub_expression {
-                        self.append_expr(new_expression);
-                        self.was_last_binary = false;
+                Operator::Index => {
+                    let index = self.parse_expression(0)?;
+                    self.expect(Token::CloseSquare)?;
+                    Expression::VariableExtract(Box::new(left), Box::new(index))
+                },
+                Operator::Field => {
+                    let Some(Token::Identifier(field)) = self.advance() else {
+                        return Err(Box::new(ParsingError::MissingToken));
+                    };
+                    match left {
+                        Expression::VariableRead(name) if self.is_enum(&name) => {
+                            let mut payload = Vec::new();
+                            if self.peek() == Some(Token::OpenParenth) {
+                                self.advance();
+                                payload = self.parse_list(Token::CloseParenth)?;
+                            }
+                            Expression::EnumVariant(name, field, payload.into_iter().map(Box::new).collect())
+                        },
+                        _ => Expression::FieldAccess(Box::new(left), field),
                     }
-                }
-                _ => panic!()
-            }
-            return Ok(true);
+                },
+                Operator::Cast => {
+                    let data_type = self.parse_data_type()?;
+                    Expression::ExpressionCast(Box::new(left), data_type.produce_string())
+                },
+                Operator::Prefix(_) => unreachable!(),
+            };
         }
-        self.check_variable(&token);
 
-        // dbg!(&token);
-        let unary_mode = self.was_last_binary || self.expression_stack.is_empty();
+        Ok(left)
+    }
 
-        match token {
-            Token::Integer(v) => {
-                let mini_expr = Expression::IntegerLiteral(v);
-                self.append_expr(mini_expr);
-            },
-            Token::Float(v) => {
-                self.append_expr(Expression::FloatLiteral(v));
-            },
-            Token::String(v) => {
-                self.append_expr(Expression::StringLiteral(v));
-            },
-            Token::Char(v) => {
-                self.append_expr(Expression::CharLiteral(v));
-            },
-            Token::Plus => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::Addition)),
-            Token::Lesser => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::Less)),
-            Token::LesserEqual => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::LessEqual)),
-            Token::Greater => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::Greater)),
-            Token::GreaterEqual => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::GreaterEqual)),
-            Token::NotEqual => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::NotEqual)),
-            Token::DoubleEqual => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::Equal)),
-            Token::Minus if !unary_mode => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::Subtraction)),
-            Token::Star if !unary_mode => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::Multiplication)),
-            Token::Star if unary_mode => self.waiting_unary_operation = Some(WaitingUnaryTypes::Dereference),
-            Token::Ampersand if unary_mode => self.waiting_unary_operation = Some(WaitingUnaryTypes::Reference),
-            Token::Minus if unary_mode => self.waiting_unary_operation = Some(WaitingUnaryTypes::Negation),
-            Token::Slash => self.append_expr(Expression::Binary(None, None, crate::ast::BinaryExpressionType::Division)),
-            Token::OpenSquare => {
-//        dbg!("Open Square reached", &self.expression_stack);
-                if self.expression_stack.is_empty() && self.waiting_variable_name.is_none() {
-                    let new_parser = ExpressionParser::with_scope_stack(&self.scope_stack.unwrap());
-                    self.parser_stack.push_front(new_parser);
-                    self.append_expr(Expression::Array(Vec::new()));
-                } else {
-                    // dbg!(&self.expression_stack);
-                    let new_parser = ExpressionParser::with_scope_stack(&self.scope_stack.unwrap());
-                    self.parser_stack.push_front(new_parser);
-                }
-            }
-            Token::Identifier(ref name) => {
-                // dbg!("Looking for variable");
-                if !self.check_stack {
-                    self.waiting_variable_name = Some(name.clone());
-                    return Ok(true);
-                }
-                if let Some(stack) = self.scope_stack {
-                    if stack.get_variable(&name).is_some() {
-                        self.waiting_variable_name = Some(name.clone());
-//            self.append_expr(Expression::VariableRead(name.clone()));
-                        return Ok(true);
-                    } else if stack.contains_function(&name) {
-                        let mut function_parser = Box::new(FunctionCallParser::new(stack));
-                        function_parser.consume(Token::Identifier(name.clone()))?;
-                        self.waiting_function_parser = Some(function_parser);
-                    } else {
-                        self.waiting_data_type_parser = Some(Box::new(ExpressionCastParser::new(&self.scope_stack.unwrap(), &self.data_types.unwrap())));
-                        self.waiting_data_type_parser.as_mut().unwrap().consume(token)?;
-                    }
-                } else {
-                }
-            }
+    fn parse_prefix(&mut self) -> ParsingResult<Expression> {
+        let Some(token) = self.advance() else {
+            return Err(Box::new(ParsingError::MissingToken));
+        };
+        if let Some(Operator::Prefix(unary_type)) = Operator::from_token(&token, true) {
+            let interior = self.parse_expression(unary_type.precidence())?;
+            return Ok(Expression::Unary(Some(Box::new(interior)), unary_type));
+        }
+
+        let expression = match token {
+            Token::Integer(v) => Expression::IntegerLiteral(v),
+            Token::Float(v) => Expression::FloatLiteral(v),
+            Token::String(v) => Expression::StringLiteral(v),
+            Token::Char(v) => Expression::CharLiteral(v),
             Token::OpenParenth => {
-                self.parser_stack.push_front(self.scope_stack.map(|v| ExpressionParser::with_scope_stack(v)).unwrap_or(ExpressionParser::new()));
-            }
-            Token::EOL => return Ok(false),
-            Token::Comma => return Ok(false),
-            Token::Colon => return Ok(false),
-            Token::CloseParenth => return Ok(false),
-            Token::CloseSquare => return Ok(false),
-            Token::ClosedCurly => return Ok(false),
-            Token::OpenCurly => return Ok(false),
-            Token::Equal => return Ok(false),
-            _ => panic!("Didn't expect {:?}", token)
+                let interior = self.parse_expression(0)?;
+                self.expect(Token::CloseParenth)?;
+                interior
+            },
+            Token::OpenSquare => Expression::Array(self.parse_list(Token::CloseSquare)?),
+            Token::Identifier(name) => self.parse_identifier(name)?,
+            _ => return Err(Box::new(ParsingError::UnexpectedToken(token))),
         };
 
-        Ok(true)
+        Ok(expression)
     }
 
-    fn check_variable(&mut self, token: &Token) {
-        if self.waiting_variable_name.is_some() {
-            match token {
-                Token::OpenSquare => {}
-                _ => {
-                    self.append_expr(Expression::VariableRead(self.waiting_variable_name.as_ref().unwrap().clone()));
-                    self.waiting_variable_name = None;
-                }
-            }
+    fn parse_identifier(&mut self, name: String) -> ParsingResult<Expression> {
+        if !self.check_stack || self.is_variable(&name) {
+            return Ok(Expression::VariableRead(name));
         }
-    }
-
-    pub fn build(&mut self) -> Option<Expression> {
-        // dbg!(&self.expression_stack);
-        if let Expression::Array(_) = self.expression_stack.front()? {
-            return Some(self.expression_stack.front()?.clone());
+        if name == "vec" && self.peek() == Some(Token::OpenSquare) {
+            self.advance();
+            return Ok(Expression::VectorLiteral(self.parse_list(Token::CloseSquare)?));
         }
-        let mut current = self.expression_stack.pop_front();
-        while !self.expression_stack.is_empty() {
-            if self.front().is_binary() && !self.binary_right() {
-                self.binary_set_right(current);
-            }
-            current = self.expression_stack.pop_front();
+        // Ex: char(90)
+        if self.is_data_type(&name) && self.peek() == Some(Token::OpenParenth) {
+            self.advance();
+            let interior = self.parse_expression(0)?;
+            self.expect(Token::CloseParenth)?;
+            return Ok(Expression::ExpressionCast(Box::new(interior), name));
         }
 
-        return Some(current.unwrap());
+        Ok(Expression::VariableRead(name))
     }
 
-    fn append_expr(&mut self, expression: Expression) {
-        self.was_last_binary = expression.is_binary();
-        if self.waiting_unary_operation.is_some() {
-            // dbg!("unary thing");
-            let new_expression = match self.waiting_unary_operation.as_ref().unwrap() {
-                WaitingUnaryTypes::Reference => Expression::Unary(Some(Box::new(expression)), UnaryExpressionType::Reference),
-                WaitingUnaryTypes::Dereference => Expression::Unary(Some(Box::new(expression)), UnaryExpressionType::Dereference),
-                WaitingUnaryTypes::Negation => todo!(),
-            };
-            self.waiting_unary_operation = None;
-            return self.append_expr(new_expression);
+    // Comma separated expressions, the opening token has already been consumed
+    fn parse_list(&mut self, close: Token) -> ParsingResult<Vec<Expression>> {
+        let mut list = Vec::new();
+        if self.peek() == Some(close.clone()) {
+            self.advance();
+            return Ok(list);
         }
-
-        if self.expression_stack.is_empty() {
-            self.expression_stack.push_front(expression);
-            return;
+        loop {
+            list.push(self.parse_expression(0)?);
+            match self.advance() {
+                Some(Token::Comma) => {},
+                Some(token) if token == close => return Ok(list),
+                Some(token) => return Err(Box::new(ParsingError::UnexpectedToken(token))),
+                None => return Err(Box::new(ParsingError::MissingToken)),
+            }
         }
- 
-        if self.front().is_binary() {
-            if !self.binary_left() {
-                self.binary_set_left(Some(expression));
-            } else if !self.binary_right() {
-                self.binary_set_right(Some(expression));
-            } else if expression.is_binary() {
-                let new_expr_precidence = expression.precidence();
-                let top_expr_precidence = self.front().precidence();
-
-                // Ex: 3+2*5
-                if new_expr_precidence >= top_expr_precidence {
-                    let tmp_right = self.front().binary_get_right().clone();
-                    self.binary_set_right(None);
-                    let new_expr = expression.binary_set_left(tmp_right.map(|v| *v));
-                    self.expression_stack.push_front(new_expr);
-                } else { // Ex: 2*5+3
-                    let new_outer_expr = expression.binary_set_left(self.expression_stack.pop_front());
-                    self.expression_stack.push_front(new_outer_expr);
-                }
+    }
+
+    fn parse_data_type(&mut self) -> ParsingResult<DataType> {
+        let Some(data_types) = self.data_types else {
+            return Err(Box::new(ParsingError::MissingToken));
+        };
+        let mut data_type_parser = DataTypeParser::new(data_types);
+        while let Some(token) = self.peek() {
+            if !data_type_parser.consume(token) {
+                break;
             }
-        } else if expression.is_binary() {
-            let v = expression.binary_set_left(self.expression_stack.pop_front());
-            self.expression_stack.push_front(v);
+            self.advance();
         }
+
+        data_type_parser.build()
     }
 
-    fn front(&self) -> &Expression {
-        return &self.expression_stack[0];
+    fn is_variable(&self, name: &str) -> bool {
+        self.scope_stack.map_or(false, |stack| stack.get_variable(name).is_some())
     }
 
-    fn binary_left(&self) -> bool {
-        if let Expression::Binary(l, r, t) = self.front() {
-            return l.is_some();
-        }
-        false
+    fn is_function(&self, name: &str) -> bool {
+        self.scope_stack.map_or(false, |stack| stack.contains_function(name))
     }
 
-    fn binary_set_left(&mut self, expression: Option<Expression>) {
-        let v = self.expression_stack.pop_front().unwrap().binary_set_left(expression);
-        self.expression_stack.push_front(v);
+    fn function_name(&self, name: &str) -> String {
+        self.scope_stack.map_or(name.to_string(), |stack| stack.qualified_name(name))
     }
 
-    fn binary_set_right(&mut self, expression: Option<Expression>) {
-        let v = self.expression_stack.pop_front().unwrap().binary_set_right(expression);
-        self.expression_stack.push_front(v);
+    fn is_enum(&self, name: &str) -> bool {
+        self.data_types.and_then(|data_types| data_types.get(name)).map_or(false, |data_type| matches!(data_type.value, DataTypeEnum::Enum(_)))
     }
 
+    fn is_data_type(&self, name: &str) -> bool {
+        self.data_types.map_or(false, |data_types| data_types.contains_key(name))
+    }
 
-    fn binary_right(&self) -> bool {
-        if let Expression::Binary(l, r, t) = self.front() {
-            return r.is_some();
+    fn expect(&mut self, expected: Token) -> ParsingResult<()> {
+        match self.advance() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(Box::new(ParsingError::UnexpectedToken(token))),
+            None => Err(Box::new(ParsingError::MissingToken)),
         }
-        false
+    }
+
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.position).cloned()
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
     }
 }
 
 #[cfg(test)]
 mod test {
+    use proptest::prelude::*;
+
     use super::*;
     use super::Expression::*;
     use crate::ast::BinaryExpressionType::*;
 
+    fn parse(values: &[Token]) -> Expression {
+        let mut expression_parser = ExpressionParser::new();
+        for value in values {
+            assert!(expression_parser.consume(value.clone()).expect("Some Error"));
+        }
+
+        expression_parser.build().expect("Some Error")
+    }
+
+    fn binary(left: Expression, right: Expression, binary_type: BinaryExpressionType) -> Expression {
+        Binary(Some(Box::new(left)), Some(Box::new(right)), binary_type)
+    }
+
     #[test]
     fn can_parse_number() {
         let number = Token::Integer(24);
@@ -327,27 +348,263 @@ mod test {
 
     #[test]
     fn can_parse_multiple_operations() {
-        let values = [Token::Integer(24), Token::Plus, Token::Integer(7), Token::Star, Token::Integer(3)];
+        let expr = parse(&[Token::Integer(24), Token::Plus, Token::Integer(7), Token::Star, Token::Integer(3)]);
 
-        let mut expression_parser = ExpressionParser::new();
-        for value in values {
-            expression_parser.consume(value).expect("Some Error");
-        }
-
-        let expr = expression_parser.build();
-        println!("{:?}", expr);
+        let product = binary(IntegerLiteral(7), IntegerLiteral(3), Multiplication);
+        assert_eq!(expr, binary(IntegerLiteral(24), product, Addition));
     }
 
     #[test]
     fn can_parse_multiple_operations_2() {
-        let values = [Token::Integer(24), Token::Slash, Token::Integer(7), Token::Plus, Token::Integer(3)];
+        let expr = parse(&[Token::Integer(24), Token::Slash, Token::Integer(7), Token::Plus, Token::Integer(3)]);
+
+        let quotient = binary(IntegerLiteral(24), IntegerLiteral(7), Division);
+        assert_eq!(expr, binary(quotient, IntegerLiteral(3), Addition));
+    }
+
+    #[test]
+    fn can_parse_bitwise_operations() {
+        let expr = parse(&[Token::Integer(1), Token::Pipe, Token::Integer(6), Token::Ampersand, Token::Integer(3)]);
+
+        let and = binary(IntegerLiteral(6), IntegerLiteral(3), BitwiseAnd);
+        assert_eq!(expr, binary(IntegerLiteral(1), and, BitwiseOr))
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        let a = Token::Identifier("a".into());
+        let b = Token::Identifier("b".into());
+        let c = Token::Identifier("c".into());
+        let expr = parse(&[a, Token::Minus, b, Token::Minus, c]);
+
+        let left = binary(VariableRead("a".into()), VariableRead("b".into()), Subtraction);
+        assert_eq!(expr, binary(left, VariableRead("c".into()), Subtraction));
+    }
+
+    #[test]
+    fn can_parse_nested_unary_operations() {
+        let p = Token::Identifier("p".into());
+        let expr = parse(&[Token::Star, Token::Star, p]);
+        let inner = Unary(Some(Box::new(VariableRead("p".into()))), UnaryExpressionType::Dereference);
+        assert_eq!(expr, Unary(Some(Box::new(inner)), UnaryExpressionType::Dereference));
+
+        let x = Token::Identifier("x".into());
+        let expr = parse(&[Token::Minus, Token::Ampersand, x]);
+        let inner = Unary(Some(Box::new(VariableRead("x".into()))), UnaryExpressionType::Reference);
+        assert_eq!(expr, Unary(Some(Box::new(inner)), UnaryExpressionType::Negation));
+    }
+
+    #[test]
+    fn can_parse_postfix_operations() {
+        let f = Token::Identifier("f".into());
+        let arr = Token::Identifier("arr".into());
+        let expr = parse(&[f, Token::OpenParenth, arr, Token::OpenSquare, Token::Integer(1), Token::CloseSquare,
+            Token::Comma, Token::Integer(2), Token::CloseParenth, Token::Plus, Token::Integer(3)]);
+
+        let call = FunctionCall("f".into(), vec![
+            Box::new(VariableExtract(Box::new(VariableRead("arr".into())), Box::new(IntegerLiteral(1)))),
+            Box::new(IntegerLiteral(2)),
+        ]);
+        assert_eq!(expr, binary(call, IntegerLiteral(3), Addition));
+    }
 
+    #[test]
+    fn can_index_any_expression() {
+        let index = |i| [Token::OpenSquare, Token::Integer(i), Token::CloseSquare];
+        let s = Token::Identifier("s".into());
+        let items = Token::Identifier("items".into());
+        let expr = parse(&[&[s, Token::Dot, items][..], &index(0)].concat());
+        let field = FieldAccess(Box::new(VariableRead("s".into())), "items".into());
+        assert_eq!(expr, VariableExtract(Box::new(field), Box::new(IntegerLiteral(0))));
+
+        let f = Token::Identifier("f".into());
+        let expr = parse(&[&[f, Token::OpenParenth, Token::CloseParenth][..], &index(1), &index(2)].concat());
+        let row = VariableExtract(Box::new(FunctionCall("f".into(), vec![])), Box::new(IntegerLiteral(1)));
+        assert_eq!(expr, VariableExtract(Box::new(row), Box::new(IntegerLiteral(2))));
+    }
+
+    #[test]
+    fn can_parse_indirect_calls() {
+        let handlers = Token::Identifier("handlers".into());
+        let expr = parse(&[handlers, Token::OpenSquare, Token::Integer(0), Token::CloseSquare,
+            Token::OpenParenth, Token::Identifier("x".into()), Token::CloseParenth]);
+
+        let callee = VariableExtract(Box::new(VariableRead("handlers".into())), Box::new(IntegerLiteral(0)));
+        assert_eq!(expr, IndirectCall(Box::new(callee), vec![Box::new(VariableRead("x".into()))]));
+    }
+
+    #[test]
+    fn stops_at_unmatched_tokens() {
         let mut expression_parser = ExpressionParser::new();
+        let values = [Token::OpenParenth, Token::Integer(1), Token::Comma, Token::Integer(2), Token::CloseParenth];
         for value in values {
-            expression_parser.consume(value).expect("Some Error");
+            assert!(expression_parser.consume(value).unwrap());
         }
+        assert!(!expression_parser.consume(Token::CloseParenth).unwrap());
+        assert!(!expression_parser.consume(Token::OpenCurly).unwrap());
+    }
 
-        let expr = expression_parser.build();
-        println!("{:?}", expr);
+    fn apply_binary(binary_type: &BinaryExpressionType, left: i64, right: i64) -> Option<i64> {
+        let value = match binary_type {
+            Addition => left.wrapping_add(right),
+            Subtraction => left.wrapping_sub(right),
+            Multiplication => left.wrapping_mul(right),
+            Division => left.checked_div(right)?,
+            Modulo => left.checked_rem(right)?,
+            BitwiseAnd => left & right,
+            BitwiseOr => left | right,
+            BitwiseXor => left ^ right,
+            ShiftLeft => left.wrapping_shl(right as u32),
+            ShiftRight => left.wrapping_shr(right as u32),
+            Less => (left < right) as i64,
+            LessEqual => (left <= right) as i64,
+            Greater => (left > right) as i64,
+            GreaterEqual => (left >= right) as i64,
+            Equal => (left == right) as i64,
+            NotEqual => (left != right) as i64,
+        };
+        Some(value)
     }
-}
\ No newline at end of file
+
+    fn evaluate(expression: &Expression) -> Option<i64> {
+        match expression {
+            IntegerLiteral(v) => Some(*v),
+            Binary(Some(left), Some(right), binary_type) => apply_binary(binary_type, evaluate(left)?, evaluate(right)?),
+            Unary(Some(interior), UnaryExpressionType::Negation) => Some(evaluate(interior)?.wrapping_neg()),
+            Unary(Some(interior), UnaryExpressionType::BitwiseNot) => Some(!evaluate(interior)?),
+            _ => None,
+        }
+    }
+
+    // Reference evaluator, a shunting yard over the raw tokens with its own C precedence table
+    fn reference_evaluate(tokens: &[Token]) -> Option<i64> {
+        fn binary_level(token: &Token) -> Option<(i64, BinaryExpressionType)> {
+            let level = match token {
+                Token::Star => (10, Multiplication),
+                Token::Slash => (10, Division),
+                Token::Percent => (10, Modulo),
+                Token::Plus => (9, Addition),
+                Token::Minus => (9, Subtraction),
+                Token::ShiftLeft => (8, ShiftLeft),
+                Token::ShiftRight => (8, ShiftRight),
+                Token::Lesser => (7, Less),
+                Token::LesserEqual => (7, LessEqual),
+                Token::Greater => (7, Greater),
+                Token::GreaterEqual => (7, GreaterEqual),
+                Token::DoubleEqual => (6, Equal),
+                Token::NotEqual => (6, NotEqual),
+                Token::Ampersand => (5, BitwiseAnd),
+                Token::Caret => (4, BitwiseXor),
+                Token::Pipe => (3, BitwiseOr),
+                _ => return None,
+            };
+            Some(level)
+        }
+
+        enum Pending {
+            Binary(i64, BinaryExpressionType),
+            Unary(Token),
+            Parenth,
+        }
+
+        fn reduce(values: &mut Vec<i64>, pending: Pending) -> Option<()> {
+            match pending {
+                Pending::Binary(_, binary_type) => {
+                    let right = values.pop()?;
+                    let left = values.pop()?;
+                    values.push(apply_binary(&binary_type, left, right)?);
+                },
+                Pending::Unary(Token::Minus) => {
+                    let value = values.pop()?;
+                    values.push(value.wrapping_neg());
+                },
+                Pending::Unary(_) => {
+                    let value = values.pop()?;
+                    values.push(!value);
+                },
+                Pending::Parenth => return None,
+            }
+            Some(())
+        }
+
+        let mut values = Vec::new();
+        let mut operators: Vec<Pending> = Vec::new();
+        let mut expecting_operand = true;
+        for token in tokens {
+            match token {
+                Token::Integer(v) => {
+                    values.push(*v);
+                    expecting_operand = false;
+                },
+                Token::Minus | Token::Tilde if expecting_operand => operators.push(Pending::Unary(token.clone())),
+                Token::OpenParenth => operators.push(Pending::Parenth),
+                Token::CloseParenth => {
+                    loop {
+                        match operators.pop()? {
+                            Pending::Parenth => break,
+                            pending => reduce(&mut values, pending)?,
+                        }
+                    }
+                },
+                _ => {
+                    let (level, binary_type) = binary_level(token)?;
+                    while let Some(top) = operators.last() {
+                        let reduces = match top {
+                            Pending::Unary(_) => true,
+                            Pending::Binary(top_level, _) => *top_level >= level,
+                            Pending::Parenth => false,
+                        };
+                        if !reduces {
+                            break;
+                        }
+                        let pending = operators.pop()?;
+                        reduce(&mut values, pending)?;
+                    }
+                    operators.push(Pending::Binary(level, binary_type));
+                    expecting_operand = true;
+                },
+            }
+        }
+        while let Some(pending) = operators.pop() {
+            reduce(&mut values, pending)?;
+        }
+
+        values.pop()
+    }
+
+    fn operand() -> impl Strategy<Value = Vec<Token>> {
+        (0i64..100).prop_map(|v| vec![Token::Integer(v)])
+    }
+
+    fn binary_token() -> impl Strategy<Value = Token> {
+        prop::sample::select(vec![
+            Token::Plus, Token::Minus, Token::Star, Token::Slash, Token::Percent,
+            Token::Ampersand, Token::Pipe, Token::Caret, Token::ShiftLeft, Token::ShiftRight,
+            Token::Lesser, Token::LesserEqual, Token::Greater, Token::GreaterEqual,
+            Token::DoubleEqual, Token::NotEqual,
+        ])
+    }
+
+    fn expression_tokens() -> impl Strategy<Value = Vec<Token>> {
+        operand().prop_recursive(6, 64, 2, |inner| prop_oneof![
+            (inner.clone(), binary_token(), inner.clone()).prop_map(|(left, operator, right)| {
+                [left, vec![operator], right].concat()
+            }),
+            inner.clone().prop_map(|interior| {
+                [vec![Token::OpenParenth], interior, vec![Token::CloseParenth]].concat()
+            }),
+            (prop::sample::select(vec![Token::Minus, Token::Tilde]), inner).prop_map(|(operator, interior)| {
+                [vec![operator], interior].concat()
+            }),
+        ])
+    }
+
+    proptest! {
+        #[test]
+        fn matches_reference_evaluator(tokens in expression_tokens()) {
+            let expected = reference_evaluate(&tokens);
+            prop_assume!(expected.is_some());
+            prop_assert_eq!(evaluate(&parse(&tokens)), expected);
+        }
+    }
+}
@@ -2,8 +2,8 @@ mod parser;
 mod expression_parser;
 mod scope_stack;
 mod data_type_parser;
-mod function_call_parser;
-mod expression_cast_parser;
+mod module_loader;
 
 pub use parser::*;
 pub use data_type_parser::*;
+pub use module_loader::*;
@@ -0,0 +1,233 @@
+use std::{collections::{HashMap, HashSet}, error::Error, fmt::Display, path::{Path, PathBuf}};
+
+use crate::{lexing::{Lexer, Token}, ast::{RootScope, DataType, Item}};
+
+use super::{Exports, Parser, ParsingResult};
+
+
+#[derive(Debug)]
+pub enum ModuleError {
+    NotFound(PathBuf),
+    ImportCycle(Vec<PathBuf>),
+}
+
+impl Display for ModuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModuleError::NotFound(path) => write!(f, "Couldn't find module {}", path.display()),
+            ModuleError::ImportCycle(chain) => {
+                let chain: Vec<String> = chain.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "Import cycle: {}", chain.join(" -> "))
+            },
+        }
+    }
+}
+
+impl Error for ModuleError {}
+
+struct Import {
+    module: String,
+    path: PathBuf,
+}
+
+// Parses a file and everything it imports, dependencies come before the modules using them
+#[derive(Default)]
+pub struct ModuleLoader {
+    loading: Vec<PathBuf>,
+    exports: HashMap<PathBuf, Exports>,
+    roots: Vec<RootScope>,
+    pub data_types: HashMap<String, DataType>,
+}
+
+impl ModuleLoader {
+    // Parses path and everything it imports into a single root scope
+    pub fn load(&mut self, path: &Path) -> ParsingResult<RootScope> {
+        self.load_module(path, None)?;
+        self.link()
+    }
+
+    // Links the imported modules with a root parsed by a parser from parser_for
+    pub fn link_with(&mut self, root: RootScope) -> ParsingResult<RootScope> {
+        self.roots.push(root);
+        self.link()
+    }
+
+    // Links every module into a single root scope
+    fn link(&mut self) -> ParsingResult<RootScope> {
+        let mut root = RootScope::default();
+        // Every module using an instance of a generic function has its own copy of it
+        let mut instances = HashSet::new();
+        for module in self.roots.drain(..) {
+            for item in module.items {
+                if let Item::Function(ref function) = item {
+                    if function.name.ends_with(']') && !instances.insert(function.name.clone()) {
+                        continue;
+                    }
+                }
+                root.items.push(item);
+            }
+        }
+        Ok(root)
+    }
+
+    fn load_module(&mut self, path: &Path, namespace: Option<&str>) -> ParsingResult<Exports> {
+        let Ok(path) = path.canonicalize() else {
+            return Err(Box::new(ModuleError::NotFound(path.to_path_buf())));
+        };
+        if let Some(position) = self.loading.iter().position(|loading| *loading == path) {
+            let mut chain = self.loading[position..].to_vec();
+            chain.push(path);
+            return Err(Box::new(ModuleError::ImportCycle(chain)));
+        }
+        if let Some(exports) = self.exports.get(&path) {
+            return Ok(exports.clone());
+        }
+
+        let source = std::fs::read_to_string(&path)?;
+        self.loading.push(path.clone());
+        let mut parser = self.parser_for(source, path.parent().unwrap_or(Path::new(".")))?;
+        self.loading.pop();
+
+        if let Some(namespace) = namespace {
+            parser.set_namespace(namespace);
+        }
+        self.roots.push(parser.parse()?);
+        self.data_types.extend(parser.data_types.clone());
+        self.exports.insert(path, parser.exports.clone());
+
+        Ok(parser.exports)
+    }
+
+    // Parser for source whose imports have already been loaded, relative to directory
+    pub fn parser_for(&mut self, source: String, directory: &Path) -> ParsingResult<Parser> {
+        let mut imported = Vec::new();
+        for import in find_imports(&source, directory) {
+            let exports = self.load_module(&import.path, Some(&import.module))?;
+            imported.push((import.module, exports));
+        }
+        let mut parser = Parser::new(source);
+        for (module, exports) in &imported {
+            parser.import_module(module, exports);
+        }
+        Ok(parser)
+    }
+}
+
+// Ex: import math -> math.ss next to the importing file, import "lib/util.ss" -> module util
+fn find_imports(source: &str, directory: &Path) -> Vec<Import> {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut imports = Vec::new();
+    let mut token = lexer.next();
+    while token != Token::EOF {
+        if token == Token::Import {
+            match lexer.next() {
+                Token::Identifier(module) => imports.push(Import {
+                    path: directory.join(format!("{}.ss", module)),
+                    module,
+                }),
+                Token::String(relative) => {
+                    let path = directory.join(relative);
+                    let module = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+                    imports.push(Import { module, path });
+                },
+                _ => {},
+            }
+        }
+        token = lexer.next();
+    }
+    imports
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::CompilerOptions, interpreter::{Interpreter, Value}};
+
+    // Unique to the test and the process, removed when the test is done
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("ss_modules_{}_{}", test, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn write_module(directory: &TempDir, name: &str, source: &str) -> PathBuf {
+        let path = directory.0.join(name);
+        std::fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn resolves_qualified_calls() {
+        let directory = TempDir::new("qualified");
+        write_module(&directory, "math.ss", "def helper(x: i64): i64 {\n    return x * x\n}\npub def square(x: i64): i64 {\n    return helper(x)\n}\n");
+        let main = write_module(&directory, "main.ss", "import math\ndef main(): i64 {\n    return math.square(3)\n}\n");
+
+        let root = ModuleLoader::default().load(&main).unwrap();
+        assert_eq!(root.items.len(), 3);
+    }
+
+    #[test]
+    fn instantiates_exported_generics() {
+        let directory = TempDir::new("generic");
+        let math = "def helper(x: i64): i64 {\n    return x\n}\npub def pick[T](a: T, b: T): T {\n    x = helper(1)\n    return a\n}\npub def twice(x: i64): i64 {\n    return pick(x, x) * 2\n}\n";
+        write_module(&directory, "math.ss", math);
+        let main = write_module(&directory, "main.ss", "import math\ndef main(): i64 {\n    x = math.pick(1.5, 2.5)\n    return math.pick(1, 2) + math.twice(3)\n}\n");
+
+        let root = ModuleLoader::default().load(&main).unwrap();
+        let mut names: Vec<&str> = root.items.iter().filter_map(|item| match item {
+            Item::Function(function) => Some(function.name.as_str()),
+            _ => None,
+        }).collect();
+        names.sort();
+        assert_eq!(names, ["main", "math.helper", "math.pick[f64]", "math.pick[i64]", "math.twice"]);
+    }
+
+    #[test]
+    fn private_functions_are_hidden() {
+        let directory = TempDir::new("private");
+        write_module(&directory, "math.ss", "def helper(x: i64): i64 {\n    return x * x\n}\n");
+        let main = write_module(&directory, "main.ss", "import math\ndef main(): i64 {\n    return math.helper(3)\n}\n");
+
+        assert!(ModuleLoader::default().load(&main).is_err());
+    }
+
+    #[test]
+    fn namespaces_globals() {
+        let directory = TempDir::new("globals");
+        write_module(&directory, "counter.ss", "count = 1\npub def bump(): i64 {\n    count += 1\n    total = count * 10\n    return total\n}\n");
+        let main = write_module(&directory, "main.ss", "import counter\ncount = 2\ndef main(): i64 {\n    count = counter.bump() + count\n    return count\n}\n");
+
+        let mut loader = ModuleLoader::default();
+        let root = loader.load(&main).unwrap();
+        let mut globals: Vec<&str> = root.items.iter().filter_map(|item| match item {
+            Item::Global(global) => Some(global.name.as_str()),
+            _ => None,
+        }).collect();
+        globals.sort();
+        assert_eq!(globals, ["count", "counter.count"]);
+        let result = Interpreter::new(&root, loader.data_types, CompilerOptions::default()).unwrap().call("main", Vec::new());
+        assert_eq!(result.unwrap(), Some(Value::Int(22)));
+    }
+
+    #[test]
+    fn detects_import_cycles() {
+        let directory = TempDir::new("cycle");
+        write_module(&directory, "a.ss", "import b\npub def a(): i64 {\n    return 1\n}\n");
+        write_module(&directory, "b.ss", "import \"a.ss\"\npub def b(): i64 {\n    return 2\n}\n");
+        let main = write_module(&directory, "main.ss", "import a\ndef main(): i64 {\n    return a.a()\n}\n");
+
+        let error = ModuleLoader::default().load(&main).err().unwrap();
+        assert!(error.to_string().starts_with("Import cycle"));
+    }
+}
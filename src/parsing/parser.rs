@@ -1,28 +1,126 @@
-use crate::{lexing::{Lexer, Token}, ast::{Scope, Function, Expression, SetVariable, InsertVariable, ReturnCommand, Variable, DataType, IfCondition}};
-use std::{collections::{HashMap}, error::Error, fmt::Display, cell::RefCell};
+use crate::{lexing::{Lexer, Token}, ast::{Scope, Function, Expression, SetVariable, InsertVariable, ReturnCommand, Variable, DataType, IfCondition, ForLoop, MatchStatement, MatchArm, GlobalVariable, DataTypeEnum, LocatedStatement, Stmt, Item, BinaryExpressionType, is_builtin}};
+use std::{collections::{HashMap, HashSet}, error::Error, fmt::Display, cell::RefCell};
 use crate::ast::{RootScope};
 use crate::parsing::ParsingError::MissingToken;
 
-use super::{scope_stack::ScopeStack, expression_parser::ExpressionParser, data_type_parser::DataTypeParser};
+use super::{scope_stack::{ScopeStack, Block}, expression_parser::ExpressionParser, data_type_parser::DataTypeParser};
 
 
 pub struct Parser {
+    source: String,
     lexer: RefCell<Lexer>,
     current_token: RefCell<Token>,
+    current_line: RefCell<usize>,
+    statement_line: usize,
     scope_stack: ScopeStack,
+    modules: HashSet<String>,
+    signatures: HashMap<String, Option<DataType>>,
+    function_types: HashMap<String, DataType>,
+    generics: HashMap<String, GenericFunction>,
+    instances: HashSet<String>,
+    instantiated: Vec<Item>,
+    instance_name: Option<String>,
+    constants: HashMap<String, Expression>,
+    pub exports: Exports,
     pub data_types: HashMap<String, DataType>,
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SymbolKind {
+    Function,
+    Variable,
+    Global,
+    Constant,
+}
+
+// Where a name was introduced, for editor tooling
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub line: usize,
+    pub kind: SymbolKind,
+    pub data_type: Option<DataType>,
+}
+
+struct Signature {
+    name: String,
+    type_params: Vec<String>,
+    params: Vec<(String, DataType)>,
+    return_type: Option<DataType>,
+}
+
+// Parameter types refer to the type parameters by name, the source is parsed again per instantiation
+#[derive(Clone)]
+struct GenericFunction {
+    type_params: Vec<String>,
+    params: Vec<DataType>,
+    return_type: Option<DataType>,
+    source: String,
+    // Line the definition starts on, instances report the lines of the original file
+    line: usize,
+    // Instances are parsed in the module of the definition, also when an importer instantiates them
+    namespace: Option<String>,
+    signatures: HashMap<String, Option<DataType>>,
+}
+
+#[cfg(feature = "llvm")]
+pub struct Checkpoint {
+    variables: HashMap<String, Variable>,
+    functions: HashMap<String, Option<DataType>>,
+    signatures: HashMap<String, Option<DataType>>,
+    function_types: HashMap<String, DataType>,
+    generics: HashMap<String, GenericFunction>,
+    instances: HashSet<String>,
+    constants: HashMap<String, Expression>,
+    data_types: HashMap<String, DataType>,
+    symbols: usize,
+}
+
+// Public functions of a module, generic ones are instantiated by the importer
+#[derive(Clone, Default)]
+pub struct Exports {
+    pub functions: Vec<(String, Option<DataType>)>,
+    generics: Vec<(String, GenericFunction)>,
 }
 
 pub type ParsingResult<T> = Result<T, Box<dyn Error>>;
 
 #[derive(Debug)]
 pub enum ParsingError {
-    MissingToken
+    MissingToken,
+    UnexpectedToken(Token),
+    UnknownFunction(String),
+    CannotInferType(String),
+    NotAnEnum(String),
+    NonExhaustiveMatch(Vec<String>),
+    NotConstant(String),
+    MismatchedType(String, String),
+    StaticAssertFailed(Option<String>),
+    StatementOutsideFunction,
+    DuplicateArm(String),
+    ArgumentCount(usize, usize),
+    NotCType(String),
 }
 
 impl Display for ParsingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Error")
+        match self {
+            ParsingError::MissingToken => write!(f, "Missing token"),
+            ParsingError::UnexpectedToken(token) => write!(f, "Unexpected token {:?}", token),
+            ParsingError::UnknownFunction(name) => write!(f, "Unknown or private function {}", name),
+            ParsingError::CannotInferType(name) => write!(f, "Couldn't infer type arguments for {}", name),
+            ParsingError::NotAnEnum(name) => write!(f, "Can only match on enums, found {}", name),
+            ParsingError::NonExhaustiveMatch(missing) => write!(f, "Match is missing arms for {}", missing.join(", ")),
+            ParsingError::NotConstant(name) => write!(f, "{} must be a compile-time constant", name),
+            ParsingError::MismatchedType(expected, found) => write!(f, "Expected {} but found {}", expected, found),
+            ParsingError::StaticAssertFailed(Some(message)) => write!(f, "Static assertion failed: {}", message),
+            ParsingError::StaticAssertFailed(None) => write!(f, "Static assertion failed"),
+            ParsingError::StatementOutsideFunction => write!(f, "Statements must be inside a function"),
+            ParsingError::DuplicateArm(variant) => write!(f, "Match has more than one arm for {}", variant),
+            ParsingError::ArgumentCount(expected, found) => write!(f, "Expected {} arguments but found {}", expected, found),
+            ParsingError::NotCType(name) => write!(f, "{} can't cross an extern call, only numbers and pointers can", name),
+        }
     }
 }
 
@@ -30,7 +128,12 @@ impl Error for ParsingError {}
 
 impl Parser {
     pub fn new(raw: String) -> Self {
-        let mut lexer = Lexer::new(raw);
+        Self::starting_at(raw, 1)
+    }
+
+    // Lines are counted from line, for source cut out of a larger file
+    pub fn starting_at(raw: String, line: usize) -> Self {
+        let mut lexer = Lexer::starting_at(raw.clone(), line);
         let mut data_types = HashMap::new();
         data_types.insert("i64".to_string(), DataType {
             symbol: "i64".to_string(),
@@ -45,43 +148,206 @@ impl Parser {
             value: crate::ast::DataTypeEnum::Primitive,
         });
         let mut scope_stack = ScopeStack::default();
-        scope_stack.push_front(Box::new(RootScope::default()));
+        scope_stack.push_front(Block::Root(RootScope::default()));
+        let current_token = RefCell::new(lexer.next());
         Self {
+            source: raw,
             scope_stack,
-            current_token: RefCell::new(lexer.next()),
+            modules: HashSet::new(),
+            signatures: HashMap::new(),
+            function_types: HashMap::new(),
+            generics: HashMap::new(),
+            instances: HashSet::new(),
+            instantiated: Vec::new(),
+            instance_name: None,
+            constants: HashMap::new(),
+            exports: Exports::default(),
+            current_token,
+            current_line: RefCell::new(lexer.line()),
+            statement_line: lexer.line(),
             lexer: RefCell::new(lexer),
             data_types,
+            symbols: Vec::new(),
+        }
+    }
+
+    // Functions of this file are named "namespace.function" so modules can't collide
+    pub fn set_namespace(&mut self, namespace: &str) {
+        self.scope_stack.namespace = Some(namespace.to_string());
+    }
+
+    // Makes the public functions of an already parsed module callable as "module.function"
+    pub fn import_module(&mut self, module: &str, exports: &Exports) {
+        self.modules.insert(module.to_string());
+        for (name, return_type) in &exports.functions {
+            self.declare_global(name, return_type.clone());
         }
+        for (name, generic) in &exports.generics {
+            self.declare_generic(name, generic.clone());
+        }
+    }
+
+    pub fn parse(&mut self) -> ParsingResult<RootScope> {
+        self.parse_source(None)?;
+        let Some(Block::Root(mut root)) = self.scope_stack.pop_front() else {
+            return Err(Box::new(MissingToken));
+        };
+        root.items.append(&mut self.instantiated);
+        Ok(root)
+    }
+
+    // Parses more source against the scopes, functions and constants seen so far, only the new items are returned
+    #[cfg(feature = "llvm")]
+    pub fn parse_more(&mut self, source: String) -> ParsingResult<RootScope> {
+        self.load_source(source);
+        self.parse_source(None)?;
+        let mut root = RootScope::default();
+        root.items.append(&mut self.scope_stack.root_mut().items);
+        root.items.append(&mut self.instantiated);
+        Ok(root)
+    }
+
+    // Saves what later input can change, Ex: the repl restores it when an input fails to compile
+    #[cfg(feature = "llvm")]
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        let root = self.scope_stack.root_mut();
+        Checkpoint {
+            variables: root.variables.clone(),
+            functions: root.functions.clone(),
+            signatures: self.signatures.clone(),
+            function_types: self.function_types.clone(),
+            generics: self.generics.clone(),
+            instances: self.instances.clone(),
+            constants: self.constants.clone(),
+            data_types: self.data_types.clone(),
+            symbols: self.symbols.len(),
+        }
+    }
+
+    #[cfg(feature = "llvm")]
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.discard_input();
+        self.instantiated.clear();
+        let root = self.scope_stack.root_mut();
+        root.variables = checkpoint.variables;
+        root.functions = checkpoint.functions;
+        self.signatures = checkpoint.signatures;
+        self.function_types = checkpoint.function_types;
+        self.generics = checkpoint.generics;
+        self.instances = checkpoint.instances;
+        self.constants = checkpoint.constants;
+        self.data_types = checkpoint.data_types;
+        self.symbols.truncate(checkpoint.symbols);
+    }
+
+    // Drops the scopes and items left behind by source that failed to parse
+    #[cfg(feature = "llvm")]
+    pub fn discard_input(&mut self) {
+        while !self.at_root() {
+            self.scope_stack.pop_front();
+        }
+        self.scope_stack.root_mut().items.clear();
+    }
+
+    // Type of a single expression, None for calls that don't return a value
+    #[cfg(feature = "llvm")]
+    pub fn infer_type(&mut self, source: String) -> ParsingResult<Option<DataType>> {
+        self.load_source(source);
+        let expression = self.parse_expression()?;
+        if self.current_token() != Token::EOL && self.current_token() != Token::EOF {
+            return Err(Box::new(ParsingError::UnexpectedToken(self.current_token())));
+        }
+        Ok(expression.expression_type(&self.scope_stack, &self.data_types))
+    }
+
+    // Stops before the first statement after line, leaving the scopes as they are at that point
+    pub fn parse_until(&mut self, line: usize) -> ParsingResult<()> {
+        self.parse_source(Some(line))
     }
 
-    pub fn parse(&mut self) -> ParsingResult<Box<dyn Scope>> {
-        while self.current_token() != Token::EOF {
-            if self.current_token() == Token::Def {
+    // Type a name has at the current point of parsing, functions have fn(..) types
+    pub fn lookup_type(&self, name: &str) -> Option<DataType> {
+        if let Some(variable) = self.scope_stack.get_variable(name) {
+            return Some(variable.data_type.clone());
+        }
+        self.function_types.get(&self.scope_stack.qualified_name(name)).cloned()
+    }
+
+    // Makes a variable visible to all later source as a global
+    #[cfg(feature = "llvm")]
+    pub fn add_global(&mut self, variable: Variable) {
+        self.scope_stack.set_variable(variable);
+    }
+
+    #[cfg(feature = "llvm")]
+    fn load_source(&mut self, source: String) {
+        let mut lexer = Lexer::new(source.clone());
+        self.current_token.replace(lexer.next());
+        self.current_line.replace(lexer.line());
+        self.statement_line = lexer.line();
+        self.lexer.replace(lexer);
+        self.source = source;
+    }
+
+    fn parse_source(&mut self, stop_line: Option<usize>) -> ParsingResult<()> {
+        self.declare_types()?;
+        self.declare_functions()?;
+        while self.current_token() != Token::EOF && stop_line.map_or(true, |line| self.current_line() <= line) {
+            self.statement_line = self.current_line();
+            if self.in_match() && self.current_token() != Token::ClosedCurly && self.current_token() != Token::EOL {
+                self.parse_match_arm()?;
+            } else if self.current_token() == Token::Enum {
+                // Enums are declared before parsing
+                self.skip_block();
+            } else if self.current_token() == Token::Const {
+                self.parse_const()?;
+            } else if self.current_token() == Token::StaticAssert {
+                self.parse_static_assert()?;
+            } else if self.current_token() == Token::Match {
+                self.parse_match()?;
+            } else if self.current_token() == Token::Import {
+                // Imports are resolved by the module loader before parsing
+                while self.current_token() != Token::EOL && self.current_token() != Token::EOF {
+                    self.next();
+                }
+            } else if self.current_token() == Token::Pub {
+                self.next();
                 self.parse_function()?
+            } else if self.current_token() == Token::Def {
+                self.parse_function()?
+            } else if self.current_token() == Token::Extern {
+                self.next();
+                self.parse_extern()?
             } else if self.current_token() == Token::Return {
                 self.parse_return()?;
             } else if self.current_token() == Token::If {
                 self.parse_if_statement()?;
+            } else if self.current_token() == Token::For {
+                self.parse_for_statement()?;
+            } else if self.is_call_statement() {
+                let expression = self.parse_expression()?;
+                self.push_statement(Stmt::Expression(expression))?;
             } else if let Token::Identifier(ref name) = self.current_token() {
                 let expression = self.parse_expression_choice(false).expect("Couldn't parse expected expression");
-                if let Expression::VariableRead(ref iden) = expression {
-                    self.parse_set_variable(iden)?;
-                } else {
-                    self.parse_insert_value(expression)?;
+                match expression {
+                    Expression::VariableRead(ref iden) => self.parse_set_variable(iden)?,
+                    Expression::IndirectCall(..) => self.push_statement(Stmt::Expression(expression))?,
+                    _ => self.parse_insert_value(expression)?,
                 }
             } else if self.current_token() == Token::Star {
                 let expression = self.parse_expression_choice(false).expect("Couldn't parse expected expression");
                 // dbg!(&expression);
                 self.parse_insert_value(expression)?;
             } else if Token::ClosedCurly == self.current_token() {
-                let mut thing = self.scope_stack.pop_front().unwrap();
-                thing.wrap_up_parsing(self);
-                self.scope_stack.peek_front_mut().unwrap().commands_mut().push(thing);
+                if self.at_root() {
+                    return Err(Box::new(ParsingError::UnexpectedToken(Token::ClosedCurly)));
+                }
+                let block = self.scope_stack.pop_front().unwrap();
+                self.close_block(block)?;
             }
             self.next();
         }
-
-        Ok(self.scope_stack.pop_front().unwrap())
+        Ok(())
     }
 
     fn parse_if_statement(&mut self) -> ParsingResult<()> {
@@ -90,20 +356,177 @@ impl Parser {
         }
         let mut token = self.next();
         let mut expression_parser = ExpressionParser::with_scope_stack(&self.scope_stack);
+        expression_parser.data_types = Some(&self.data_types);
         while expression_parser.consume(token)? {
             token = self.next();
         }
-        let condition = expression_parser.build().unwrap();
+        let mut condition = expression_parser.build()?;
+        self.resolve_names(&mut condition);
+        condition.fold_constants();
+        self.instantiate_generics(&mut condition)?;
         // dbg!(&condition);
         if self.current_token() != Token::OpenCurly {
             return Err(Box::new(MissingToken))
         }
 
-        let condition = IfCondition::new(condition);
-        self.scope_stack.push_front(Box::new(condition));
+        let mut condition = IfCondition::new(condition);
+        condition.line = self.statement_line;
+        self.scope_stack.push_front(Block::If(condition));
+        Ok(())
+    }
+
+    fn in_match(&mut self) -> bool {
+        matches!(self.scope_stack.peek_front(), Some(Block::Match(_)))
+    }
+
+    fn parse_match(&mut self) -> ParsingResult<()> {
+        if self.current_token() != Token::Match {
+            return Err(Box::new(MissingToken))
+        }
+        self.next();
+        let scrutinee = self.parse_expression()?;
+        if self.current_token() != Token::OpenCurly {
+            return Err(Box::new(MissingToken))
+        }
+
+        let enum_type = self.expression_type(&scrutinee);
+        if !matches!(enum_type.value, DataTypeEnum::Enum(_)) {
+            return Err(Box::new(ParsingError::NotAnEnum(enum_type.symbol)));
+        }
+        let mut statement = MatchStatement::new(scrutinee, enum_type);
+        statement.line = self.statement_line;
+        self.scope_stack.push_front(Block::Match(statement));
+        Ok(())
+    }
+
+    // Ex: Rect(width, height) { or else {
+    fn parse_match_arm(&mut self) -> ParsingResult<()> {
+        let Some(Block::Match(statement)) = self.scope_stack.peek_front() else {
+            return Err(Box::new(MissingToken));
+        };
+        let enum_type = statement.enum_type.clone();
+        let tags: Vec<Option<u64>> = statement.arms.iter().map(|arm| arm.tag).collect();
+        let arm = match self.current_token() {
+            Token::Else => {
+                if tags.contains(&None) {
+                    return Err(Box::new(ParsingError::DuplicateArm("else".to_string())));
+                }
+                self.next();
+                MatchArm::new(None, Vec::new())
+            },
+            Token::Identifier(variant) => {
+                let Some((tag, fields)) = enum_type.variant(&variant) else {
+                    return Err(Box::new(ParsingError::UnexpectedToken(Token::Identifier(variant))));
+                };
+                if tags.contains(&Some(tag)) {
+                    return Err(Box::new(ParsingError::DuplicateArm(variant)));
+                }
+                let mut bindings = Vec::new();
+                let mut next = self.next();
+                if next == Token::OpenParenth {
+                    next = self.next();
+                    while next != Token::CloseParenth {
+                        let Token::Identifier(binding) = next else {
+                            return Err(Box::new(ParsingError::UnexpectedToken(next)));
+                        };
+                        let Some(data_type) = fields.get(bindings.len()) else {
+                            return Err(Box::new(ParsingError::UnexpectedToken(Token::Identifier(binding))));
+                        };
+                        bindings.push((binding, data_type.clone()));
+                        next = self.next();
+                        if next == Token::Comma {
+                            next = self.next();
+                        }
+                    }
+                    self.next();
+                }
+                MatchArm::new(Some(tag), bindings)
+            },
+            token => return Err(Box::new(ParsingError::UnexpectedToken(token))),
+        };
+        if self.current_token() != Token::OpenCurly {
+            return Err(Box::new(MissingToken))
+        }
+
+        self.scope_stack.push_front(Block::Arm(arm));
         Ok(())
     }
 
+    // Ex: enum Shape { Circle(f64), Rect(f64, f64) }
+    fn parse_enum(&mut self) -> ParsingResult<DataType> {
+        let Token::Identifier(name) = self.next() else {
+            return Err(Box::new(MissingToken))
+        };
+        if self.next() != Token::OpenCurly {
+            return Err(Box::new(MissingToken))
+        }
+        let mut variants = Vec::new();
+        let mut next = self.next();
+        while next != Token::ClosedCurly {
+            match next {
+                Token::EOL | Token::Comma => next = self.next(),
+                Token::Identifier(variant) => {
+                    let mut fields = Vec::new();
+                    next = self.next();
+                    if next == Token::OpenParenth {
+                        next = self.next();
+                        while next != Token::CloseParenth {
+                            let mut data_type_parser = self.data_type_parser();
+                            while data_type_parser.consume(next.clone()) {
+                                next = self.next();
+                            }
+                            fields.push(data_type_parser.build()?);
+                            if next == Token::Comma {
+                                next = self.next();
+                            }
+                        }
+                        next = self.next();
+                    }
+                    variants.push((variant, fields));
+                },
+                token => return Err(Box::new(ParsingError::UnexpectedToken(token))),
+            }
+        }
+
+        Ok(DataType { symbol: name, value: DataTypeEnum::Enum(variants) })
+    }
+
+    fn parse_for_statement(&mut self) -> ParsingResult<()> {
+        if self.current_token() != Token::For {
+            return Err(Box::new(MissingToken))
+        }
+        let Token::Identifier(variable) = self.next() else {
+            return Err(Box::new(MissingToken))
+        };
+        if self.next() != Token::In {
+            return Err(Box::new(MissingToken))
+        }
+        self.next();
+        let iterable = self.parse_expression()?;
+        if self.current_token() != Token::OpenCurly {
+            return Err(Box::new(MissingToken))
+        }
+
+        let iterable_type = self.expression_type(&iterable);
+        self.record_symbol(&variable, SymbolKind::Variable, iterable_type.element_type().cloned());
+        let mut for_loop = ForLoop::new(variable, iterable, iterable_type);
+        for_loop.line = self.statement_line;
+        self.scope_stack.push_front(Block::For(for_loop));
+        Ok(())
+    }
+
+    // Calls such as push(v, 2) used as a statement
+    fn is_call_statement(&self) -> bool {
+        let token = self.current_token();
+        let Token::Identifier(ref name) = token else {
+            return false;
+        };
+        if self.scope_stack.get_variable(name).is_some() {
+            return false;
+        }
+        self.modules.contains(name) || self.scope_stack.contains_function(&self.scope_stack.qualified_name(name)) || is_builtin(name)
+    }
+
     fn parse_return(&mut self) -> ParsingResult<()> {
         if self.current_token() != Token::Return {
             return Err(Box::new(ParsingError::MissingToken));
@@ -112,8 +535,7 @@ impl Parser {
         // // dbg!("Did return");
         let value = self.parse_expression()?;
         let command = ReturnCommand::new(value);
-        self.scope_stack.commands_mut().push(Box::new(command));
-        Ok(())
+        self.push_statement(Stmt::Return(command))
     }
 
     fn parse_expression(&mut self) -> ParsingResult<Expression> {
@@ -128,11 +550,134 @@ impl Parser {
             self.next();
         }
 
-        Ok(expr_parser.build().unwrap())
+        let mut expression = expr_parser.build()?;
+        self.resolve_names(&mut expression);
+        expression.fold_constants();
+        self.instantiate_generics(&mut expression)?;
+        self.check_indirect_calls(&expression)?;
+        Ok(expression)
+    }
+
+    // Calls through function values take the parameters of the value's fn(..) type
+    fn check_indirect_calls(&self, expression: &Expression) -> ParsingResult<()> {
+        for child in expression.children() {
+            self.check_indirect_calls(child)?;
+        }
+        let Expression::IndirectCall(callee, args) = expression else {
+            return Ok(());
+        };
+        let callee_type = callee.expression_type(&self.scope_stack, &self.data_types);
+        let Some(DataTypeEnum::Function(params, _)) = callee_type.as_ref().map(|data_type| &data_type.value) else {
+            let found = callee_type.map_or("nothing".to_string(), |data_type| data_type.symbol);
+            return Err(Box::new(ParsingError::MismatchedType("a function".to_string(), found)));
+        };
+        if params.len() != args.len() {
+            return Err(Box::new(ParsingError::ArgumentCount(params.len(), args.len())));
+        }
+        for (param, arg) in params.iter().zip(args) {
+            let found = arg.expression_type(&self.scope_stack, &self.data_types);
+            if found.as_ref() != Some(param) {
+                let found = found.map_or("nothing".to_string(), |data_type| data_type.symbol);
+                return Err(Box::new(ParsingError::MismatchedType(param.symbol.clone(), found)));
+            }
+        }
+        Ok(())
+    }
+
+    // Points calls of generic functions at an instance for the argument types, Ex: max(1, 2) -> max[i64](1, 2)
+    fn instantiate_generics(&mut self, expression: &mut Expression) -> ParsingResult<()> {
+        for child in expression.children_mut() {
+            self.instantiate_generics(child)?;
+        }
+        if let Expression::FunctionCall(name, args) = expression {
+            if let Some(generic) = self.generics.get(name.as_str()).cloned() {
+                *name = self.instantiate(name, &generic, args)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Replaces reads of const declarations with their folded value and reads of functions with their address,
+    // globals of a module get their "module.name"
+    fn resolve_names(&self, expression: &mut Expression) {
+        if let Expression::VariableRead(name) = expression {
+            if self.scope_stack.get_variable(name).is_some() {
+                *name = self.scope_stack.variable_name(name);
+            } else {
+                let function = self.scope_stack.qualified_name(name);
+                if let Some(value) = self.constants.get(name) {
+                    *expression = value.clone();
+                } else if let Some(function_type) = self.function_types.get(&function) {
+                    *expression = Expression::FunctionReference(function, function_type.symbol.clone());
+                }
+            }
+            return;
+        }
+        for child in expression.children_mut() {
+            self.resolve_names(child);
+        }
+    }
+
+    fn instantiate(&mut self, name: &str, generic: &GenericFunction, args: &[Box<Expression>]) -> ParsingResult<String> {
+        let mut bindings = HashMap::new();
+        for (param, arg) in generic.params.iter().zip(args) {
+            let Some(arg_type) = arg.expression_type(&self.scope_stack, &self.data_types) else {
+                return Err(Box::new(ParsingError::CannotInferType(name.to_string())));
+            };
+            if !param.bind_generics(&arg_type, &generic.type_params, &mut bindings) {
+                return Err(Box::new(ParsingError::CannotInferType(name.to_string())));
+            }
+        }
+        let mut type_arguments = Vec::new();
+        for type_param in &generic.type_params {
+            let Some(bound) = bindings.get(type_param) else {
+                return Err(Box::new(ParsingError::CannotInferType(name.to_string())));
+            };
+            type_arguments.push(bound.symbol.clone());
+        }
+        let instance_name = format!("{}[{}]", name, type_arguments.join(", "));
+        if self.instances.contains(&instance_name) {
+            return Ok(instance_name);
+        }
+
+        // Declared before parsing so the instance can call itself
+        self.instances.insert(instance_name.clone());
+        let return_type = generic.return_type.as_ref().map(|data_type| data_type.substitute(&bindings));
+        self.declare_global(&instance_name, return_type);
+
+        let mut instance = Parser::starting_at(generic.source.clone(), generic.line);
+        instance.data_types = self.data_types.clone();
+        instance.data_types.extend(bindings);
+        instance.instance_name = Some(instance_name.clone());
+        instance.scope_stack.namespace = generic.namespace.clone();
+        instance.modules = self.modules.clone();
+        instance.constants = self.constants.clone();
+        instance.function_types = self.function_types.clone();
+        instance.instances = self.instances.clone();
+        for (function, return_type) in self.signatures.iter().chain(&generic.signatures) {
+            instance.declare_global(function, return_type.clone());
+        }
+        for (function, generic) in &self.generics {
+            instance.declare_generic(function, generic.clone());
+        }
+        let root = instance.parse()?;
+
+        // Only the instances come back, the rest of the definition's module stays private
+        for (function, return_type) in instance.signatures {
+            if instance.instances.contains(&function) {
+                self.declare_global(&function, return_type);
+            }
+        }
+        self.instances.extend(instance.instances);
+        self.instantiated.extend(root.items);
+        Ok(instance_name)
     }
 
     fn parse_set_variable(&mut self, iden: &str) -> ParsingResult<()> {
-        let mut val = self.current_token();
+        if self.at_root() {
+            return self.parse_global(iden);
+        }
+        let val = self.current_token();
         if val == Token::Colon {
             // let data_type_iden = self.next();
             // if let Token::Identifier(ref data_iden) = data_type_iden {
@@ -146,10 +691,13 @@ impl Parser {
             // } else {
             //     panic!("Missing data type");
             // }
-            let mut data_type_parser = DataTypeParser::new(&self.data_types);
+            let mut data_type_parser = self.data_type_parser();
             while data_type_parser.consume(self.next()) {
             }
-            let data_type = data_type_parser.build();
+            let data_type = data_type_parser.build()?;
+            if self.scope_stack.get_variable(iden).is_none() {
+                self.record_symbol(iden, SymbolKind::Variable, Some(data_type.clone()));
+            }
             let variable = Variable {
                 name: iden.to_string(),
                 data_type
@@ -157,6 +705,18 @@ impl Parser {
             // dbg!("setting variable");
             self.scope_stack.set_variable(variable);
         }
+        if let Some(operation) = Self::compound_operation(&self.current_token()) {
+            if self.scope_stack.get_variable(iden).is_none() {
+                return Err(Box::new(ParsingError::MissingToken));
+            }
+            self.next();
+            let expr = self.parse_expression()?;
+            let name = self.scope_stack.variable_name(iden);
+            let value = Expression::Binary(Some(Box::new(Expression::VariableRead(name.clone()))), Some(Box::new(expr)), operation);
+            let data_type = self.scope_stack.get_variable(iden).unwrap().data_type.clone();
+            let stmt = SetVariable::new(name, data_type, value);
+            return self.push_statement(Stmt::Set(stmt));
+        }
         if self.current_token() != Token::Equal {
             dbg!("Missing equal");
             dbg!(&self.current_token());
@@ -169,28 +729,155 @@ impl Parser {
                 name: iden.to_string(),
                 data_type: self.expression_type(&expr),
             };
+            self.record_symbol(iden, SymbolKind::Variable, Some(variable.data_type.clone()));
             // dbg!("setting variable");
             self.scope_stack.set_variable(variable);
         }
         let data_type = self.scope_stack.get_variable(iden).expect("Missing variable").data_type.clone();
-        let stmt = SetVariable::new(iden.to_string(), data_type, expr);
-        self.scope_stack.commands_mut().push(Box::new(stmt));
+        let stmt = SetVariable::new(self.scope_stack.variable_name(iden), data_type, expr);
+        self.push_statement(Stmt::Set(stmt))
+    }
+
+    // Ex: count = 0 or buffer: [i64:16] outside of any function
+    fn parse_global(&mut self, iden: &str) -> ParsingResult<()> {
+        let mut declared_type = None;
+        if self.current_token() == Token::Colon {
+            let mut data_type_parser = self.data_type_parser();
+            while data_type_parser.consume(self.next()) {
+            }
+            declared_type = Some(data_type_parser.build()?);
+        }
+        let mut initializer = None;
+        if self.current_token() == Token::Equal {
+            self.next();
+            let value = self.parse_expression()?;
+            let Some(value) = value.evaluate_constant(&self.constants) else {
+                return Err(Box::new(ParsingError::NotConstant(iden.to_string())));
+            };
+            initializer = Some(value);
+        }
+        let data_type = match (declared_type, &initializer) {
+            (Some(data_type), Some(value)) => {
+                self.check_type(&data_type, value)?;
+                data_type
+            },
+            (Some(data_type), None) => data_type,
+            (None, Some(value)) => self.expression_type(value),
+            (None, None) => return Err(Box::new(MissingToken)),
+        };
+
+        self.record_symbol(iden, SymbolKind::Global, Some(data_type.clone()));
+        self.scope_stack.set_variable(Variable { name: iden.to_string(), data_type: data_type.clone() });
+        // Named like the functions of the module so two modules can both define it
+        let name = self.namespaced(iden);
+        if name != iden {
+            self.scope_stack.set_variable(Variable { name: name.clone(), data_type: data_type.clone() });
+        }
+        self.scope_stack.push_item(Item::Global(GlobalVariable::new(name, data_type, initializer)));
+        Ok(())
+    }
+
+    // Ex: const SIZE: i64 = 4 * 8
+    fn parse_const(&mut self) -> ParsingResult<()> {
+        let Token::Identifier(name) = self.next() else {
+            return Err(Box::new(MissingToken))
+        };
+        if self.next() != Token::Colon {
+            return Err(Box::new(MissingToken))
+        }
+        let mut data_type_parser = self.data_type_parser();
+        while data_type_parser.consume(self.next()) {
+        }
+        let data_type = data_type_parser.build()?;
+        if self.current_token() != Token::Equal {
+            return Err(Box::new(MissingToken))
+        }
+        self.next();
+        let value = self.parse_expression()?;
+        let Some(value) = value.evaluate_constant(&self.constants) else {
+            return Err(Box::new(ParsingError::NotConstant(name)));
+        };
+        self.check_type(&data_type, &value)?;
+        self.record_symbol(&name, SymbolKind::Constant, Some(data_type));
+        self.constants.insert(name, value);
         Ok(())
     }
 
+    // Ex: static_assert(SIZE % 8 == 0, "SIZE must be a multiple of 8")
+    fn parse_static_assert(&mut self) -> ParsingResult<()> {
+        if self.next() != Token::OpenParenth {
+            return Err(Box::new(MissingToken))
+        }
+        self.next();
+        let condition = self.parse_expression()?;
+        let mut message = None;
+        if self.current_token() == Token::Comma {
+            let Token::String(text) = self.next() else {
+                return Err(Box::new(MissingToken))
+            };
+            message = Some(text);
+            self.next();
+        }
+        if self.current_token() != Token::CloseParenth {
+            return Err(Box::new(MissingToken))
+        }
+        match condition.evaluate_constant(&self.constants) {
+            Some(Expression::IntegerLiteral(0)) => Err(Box::new(ParsingError::StaticAssertFailed(message))),
+            Some(Expression::IntegerLiteral(_)) => Ok(()),
+            _ => Err(Box::new(ParsingError::NotConstant("static_assert condition".to_string()))),
+        }
+    }
+
+    fn check_type(&mut self, expected: &DataType, value: &Expression) -> ParsingResult<()> {
+        let found = self.expression_type(value);
+        if found != *expected {
+            return Err(Box::new(ParsingError::MismatchedType(expected.symbol.clone(), found.symbol)));
+        }
+        Ok(())
+    }
+
+    fn data_type_parser(&self) -> DataTypeParser<'_> {
+        let mut data_type_parser = DataTypeParser::new(&self.data_types);
+        data_type_parser.constants = Some(&self.constants);
+        data_type_parser
+    }
+
+    fn at_root(&mut self) -> bool {
+        matches!(self.scope_stack.peek_front(), Some(Block::Root(_)))
+    }
+
     fn parse_insert_value(&mut self, location: Expression) -> ParsingResult<()> {
-        if self.current_token() != Token::Equal {return Err(Box::new(MissingToken))}
+        let operation = Self::compound_operation(&self.current_token());
+        if self.current_token() != Token::Equal && operation.is_none() {return Err(Box::new(MissingToken))}
         self.next();
 
         let expr = self.parse_expression()?;
-        let stmt = InsertVariable::new(location, expr);
+        let data_type = location.expression_type(&self.scope_stack, &self.data_types);
+        let mut stmt = InsertVariable::new(location, expr, data_type);
+        stmt.operation = operation;
 
-        self.scope_stack.commands_mut().push(Box::new(stmt));
-        Ok(())
+        self.push_statement(Stmt::Insert(stmt))
+    }
+
+    pub fn compound_operation(token: &Token) -> Option<BinaryExpressionType> {
+        let operation = match token {
+            Token::PlusEqual => BinaryExpressionType::Addition,
+            Token::MinusEqual => BinaryExpressionType::Subtraction,
+            Token::StarEqual => BinaryExpressionType::Multiplication,
+            Token::SlashEqual => BinaryExpressionType::Division,
+            Token::PercentEqual => BinaryExpressionType::Modulo,
+            Token::AmpersandEqual => BinaryExpressionType::BitwiseAnd,
+            Token::PipeEqual => BinaryExpressionType::BitwiseOr,
+            Token::CaretEqual => BinaryExpressionType::BitwiseXor,
+            Token::ShiftLeftEqual => BinaryExpressionType::ShiftLeft,
+            Token::ShiftRightEqual => BinaryExpressionType::ShiftRight,
+            _ => return None,
+        };
+        Some(operation)
     }
 
     fn expression_type(&mut self, expr: &Expression) -> DataType {
-        // let mut data_type_parser = DataTypeParser::new(&self.data_types);
+        // let mut data_type_parser = self.data_type_parser();
         // let thing = expr.data_type(&self.scope_stack).unwrap();
 
         // let data_type = data_type_parser.parse_string(thing);
@@ -198,25 +885,168 @@ impl Parser {
         expr.expression_type(&self.scope_stack, &self.data_types).unwrap()
     }
 
-    fn parse_function(&mut self) -> ParsingResult<()> {
-        if self.current_token() != Token::Def {
-            return Err(Box::new(ParsingError::MissingToken));
+    // Enums and consts can be used in signatures and bodies that come before their declaration
+    fn declare_types(&mut self) -> ParsingResult<()> {
+        let mut declarations = Parser::new(self.source.clone());
+        declarations.data_types = self.data_types.clone();
+        declarations.constants = self.constants.clone();
+        while declarations.current_token() != Token::EOF {
+            if declarations.current_token() == Token::Enum {
+                let data_type = declarations.parse_enum()?;
+                declarations.data_types.insert(data_type.symbol.clone(), data_type.clone());
+                self.data_types.insert(data_type.symbol.clone(), data_type);
+            } else if declarations.current_token() == Token::Const {
+                declarations.parse_const()?;
+            }
+            declarations.next();
         }
+        self.constants.extend(declarations.constants);
+        Ok(())
+    }
 
-        let mut func_name = String::new();
+    // Registers every function signature before any body is parsed, so calls can refer to later definitions
+    fn declare_functions(&mut self) -> ParsingResult<()> {
+        let mut declarations = Parser::new(self.source.clone());
+        declarations.data_types = self.data_types.clone();
+        declarations.constants = self.constants.clone();
+        let mut public = false;
+        let mut external = false;
+        let mut generics = Vec::new();
+        while declarations.current_token() != Token::EOF {
+            if declarations.current_token() == Token::Def {
+                let line = declarations.current_line();
+                let start = declarations.offset() - "def".len();
+                let signature = declarations.parse_signature(external)?;
+                if self.is_template(&signature) {
+                    declarations.skip_block();
+                    let name = self.namespaced(&signature.name);
+                    self.declare_generic(&name, GenericFunction {
+                        type_params: signature.type_params,
+                        params: signature.params.into_iter().map(|(_, data_type)| data_type).collect(),
+                        return_type: signature.return_type,
+                        source: declarations.source[start..declarations.offset()].to_string(),
+                        line,
+                        namespace: self.scope_stack.namespace.clone(),
+                        signatures: HashMap::new(),
+                    });
+                    generics.push((name, public));
+                } else {
+                    let name = match external {
+                        true => signature.name.clone(),
+                        false => self.declared_name(&signature),
+                    };
+                    if public {
+                        self.exports.functions.push((name.clone(), signature.return_type.clone()));
+                    }
+                    let params = signature.params.into_iter().map(|(_, data_type)| data_type).collect();
+                    self.function_types.insert(name.clone(), DataType::function(params, signature.return_type.clone()));
+                    self.declare_global(&name, signature.return_type);
+                }
+            }
+            public = declarations.current_token() == Token::Pub;
+            external = declarations.current_token() == Token::Extern;
+            declarations.next();
+        }
+        // Bodies can call any function of the module, including ones defined after them
+        for (name, public) in generics {
+            let generic = self.generics.get_mut(&name).unwrap();
+            generic.signatures = self.signatures.clone();
+            if public {
+                self.exports.generics.push((name, generic.clone()));
+            }
+        }
+        Ok(())
+    }
 
-        {
-            let Token::Identifier(fn_name) = self.next().clone() else {
-                return Err(Box::new(ParsingError::MissingToken));
-            };
+    // Calls parse as calls of a function, they are pointed at an instance once the argument types are known
+    fn declare_generic(&mut self, name: &str, generic: GenericFunction) {
+        self.scope_stack.add_global_function(name, None);
+        self.generics.insert(name.to_string(), generic);
+    }
+
+    fn declare_global(&mut self, name: &str, return_type: Option<DataType>) {
+        self.signatures.insert(name.to_string(), return_type.clone());
+        self.scope_stack.add_global_function(name, return_type);
+    }
+
+    fn namespaced(&self, name: &str) -> String {
+        match self.scope_stack.namespace {
+            Some(ref namespace) => format!("{}.{}", namespace, name),
+            None => name.to_string(),
+        }
+    }
 
-            func_name = fn_name.clone();
+    // Generic definitions are only parsed once their type parameters are known
+    fn is_template(&self, signature: &Signature) -> bool {
+        !signature.type_params.is_empty() && self.instance_name.is_none()
+    }
+
+    fn declared_name(&self, signature: &Signature) -> String {
+        match self.instance_name {
+            Some(ref instance_name) if !signature.type_params.is_empty() => instance_name.clone(),
+            _ => self.namespaced(&signature.name),
+        }
+    }
+
+    // Moves to the } that closes the block opened at the current token
+    fn skip_block(&self) {
+        let mut depth = 0;
+        loop {
+            match self.current_token() {
+                Token::OpenCurly => depth += 1,
+                Token::ClosedCurly => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                },
+                Token::EOF => return,
+                _ => {},
+            }
+            self.next();
+        }
+    }
+
+    // Extern functions end at their signature, the others at the { of their body
+    fn parse_signature(&mut self, external: bool) -> ParsingResult<Signature> {
+        if self.current_token() != Token::Def {
+            return Err(Box::new(ParsingError::MissingToken));
         }
 
-        if Token::OpenParenth != self.next() {
+        let Token::Identifier(func_name) = self.next() else {
             return Err(Box::new(ParsingError::MissingToken));
         };
+
+        // Ex: def max[T](a: T, b: T): T
         let mut next = self.next();
+        let mut type_params = Vec::new();
+        let mut placeholders = Vec::new();
+        if next == Token::OpenSquare {
+            next = self.next();
+            while next != Token::CloseSquare {
+                let Token::Identifier(type_param) = next else {
+                    return Err(Box::new(ParsingError::UnexpectedToken(next)));
+                };
+                if !self.data_types.contains_key(&type_param) {
+                    self.data_types.insert(type_param.clone(), DataType {
+                        symbol: type_param.clone(),
+                        value: crate::ast::DataTypeEnum::Primitive,
+                    });
+                    placeholders.push(type_param.clone());
+                }
+                type_params.push(type_param);
+                next = self.next();
+                if next == Token::Comma {
+                    next = self.next();
+                }
+            }
+            next = self.next();
+        }
+
+        if Token::OpenParenth != next {
+            return Err(Box::new(ParsingError::MissingToken));
+        };
+        next = self.next();
         let mut params = Vec::new();
         while next != Token::CloseParenth {
             let Token::Identifier(iden) = next.clone() else {
@@ -227,11 +1057,11 @@ impl Parser {
                 return Err(Box::new(ParsingError::MissingToken));
             };
             next = self.next();
-            let mut dt_parser = DataTypeParser::new(&self.data_types);
+            let mut dt_parser = self.data_type_parser();
             while dt_parser.consume(next.clone()) {
                 next = self.next();
             }
-            let dt = dt_parser.build();
+            let dt = dt_parser.build()?;
             params.push((iden, dt));
             if next == Token::Comma {
                 next = self.next();
@@ -242,15 +1072,37 @@ impl Parser {
         let mut return_type = None;
         if next == Token::Colon {
             next = self.next();
-            let mut data_type_parser = DataTypeParser::new(&self.data_types);
+            let mut data_type_parser = self.data_type_parser();
             while data_type_parser.consume(next.clone()) {
                 next = self.next();
             }
-            return_type = Some(data_type_parser.build());
+            return_type = Some(data_type_parser.build()?);
+        }
+        for placeholder in placeholders {
+            self.data_types.remove(&placeholder);
+        }
+        match next {
+            Token::OpenCurly if !external => {},
+            Token::EOL | Token::EOF if external => {},
+            _ => return Err(Box::new(ParsingError::MissingToken)),
+        }
+
+        Ok(Signature { name: func_name, type_params, params, return_type })
+    }
+
+    fn parse_function(&mut self) -> ParsingResult<()> {
+        let signature = self.parse_signature(false)?;
+        let function_type = DataType::function(signature.params.iter().map(|(_, data_type)| data_type.clone()).collect(), signature.return_type.clone());
+        self.record_symbol(&signature.name, SymbolKind::Function, Some(function_type));
+        if self.is_template(&signature) {
+            self.skip_block();
+            return Ok(());
+        }
+        let func_name = self.declared_name(&signature);
+        let Signature { params, return_type, .. } = signature;
+        for (name, data_type) in &params {
+            self.record_symbol(name, SymbolKind::Variable, Some(data_type.clone()));
         }
-        let Token::OpenCurly = next else {
-            return Err(Box::new(ParsingError::MissingToken));
-        };
 
         let mut function = Function::new(return_type.clone());
         for (name, dt) in &params {
@@ -259,18 +1111,223 @@ impl Parser {
         function.params = params;
         self.scope_stack.add_function(&func_name, return_type.clone());
         function.name = func_name.to_string();
-        self.scope_stack.push_front(Box::new(function));
+        function.line = self.statement_line;
+        self.scope_stack.push_front(Block::Function(function));
+
+        Ok(())
+    }
 
+    // Ex: extern def labs(x: i64): i64, called with the C calling convention of the target
+    fn parse_extern(&mut self) -> ParsingResult<()> {
+        let signature = self.parse_signature(true)?;
+        if !signature.type_params.is_empty() {
+            return Err(Box::new(ParsingError::UnexpectedToken(Token::OpenSquare)));
+        }
+        let mut types = signature.params.iter().map(|(_, data_type)| data_type).chain(signature.return_type.as_ref());
+        if let Some(data_type) = types.find(|data_type| !data_type.is_c_type()) {
+            return Err(Box::new(ParsingError::NotCType(data_type.symbol.clone())));
+        }
+        let function_type = DataType::function(signature.params.iter().map(|(_, data_type)| data_type.clone()).collect(), signature.return_type.clone());
+        self.record_symbol(&signature.name, SymbolKind::Function, Some(function_type));
+
+        let mut function = Function::new(signature.return_type.clone());
+        // The name is the C symbol, modules don't namespace it
+        function.name = signature.name;
+        function.params = signature.params;
+        function.line = self.statement_line;
+        function.external = true;
+        self.scope_stack.add_function(&function.name, signature.return_type);
+        self.scope_stack.push_item(Item::Function(function));
+        Ok(())
+    }
+
+    fn record_symbol(&mut self, name: &str, kind: SymbolKind, data_type: Option<DataType>) {
+        self.symbols.push(Symbol { name: name.to_string(), line: self.statement_line, kind, data_type });
+    }
+
+    fn push_statement(&mut self, stmt: Stmt) -> ParsingResult<()> {
+        let located = LocatedStatement::new(self.statement_line, stmt);
+        self.scope_stack.push_statement(Stmt::Located(located))
+    }
+
+    // Hands a finished block to the one around it, blocks carry their own line
+    fn close_block(&mut self, block: Block) -> ParsingResult<()> {
+        match block {
+            Block::Function(function) => self.scope_stack.push_item(Item::Function(function)),
+            Block::If(condition) => self.scope_stack.push_statement(Stmt::If(condition))?,
+            Block::For(for_loop) => self.scope_stack.push_statement(Stmt::For(for_loop))?,
+            Block::Match(statement) => {
+                statement.check_exhaustive()?;
+                self.scope_stack.push_statement(Stmt::Match(statement))?;
+            },
+            Block::Arm(arm) => {
+                let Some(Block::Match(statement)) = self.scope_stack.peek_front_mut() else {
+                    return Err(Box::new(MissingToken));
+                };
+                statement.arms.push(arm);
+            },
+            Block::Root(_) => unreachable!(),
+        }
         Ok(())
     }
 
     fn next(&self) -> Token {
         let a = RefCell::new(self.lexer.borrow_mut().next());
         self.current_token.swap(&a);
+        self.current_line.replace(self.lexer.borrow().line());
         self.current_token()
     }
 
+    // Byte offset in the source just past the current token
+    fn offset(&self) -> usize {
+        self.source.len() - self.lexer.borrow().remaining()
+    }
+
+    pub fn current_line(&self) -> usize {
+        *self.current_line.borrow()
+    }
+
     fn current_token(&self) -> Token {
         return self.current_token.borrow().clone();
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn functions_can_be_called_before_definition() {
+        let source = "def is_even(n: i64): i64 {\n    if n == 0 {\n        return 1\n    }\n    return is_odd(n - 1)\n}\ndef is_odd(n: i64): i64 {\n    if n == 0 {\n        return 0\n    }\n    return is_even(n - 1)\n}\ndef main(): i64 {\n    return is_even(10)\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        assert_eq!(root.items.len(), 3);
+        assert!(root.contains_function("is_odd"));
+    }
+
+    #[test]
+    fn declares_extern_functions() {
+        let source = "def main(): i64 {\n    return labs(-5)\n}\nextern def labs(x: i64): i64\n";
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        let Some(Item::Function(labs)) = root.items.iter().find(|item| matches!(item, Item::Function(function) if function.name == "labs")) else {
+            panic!("labs wasn't declared");
+        };
+        assert!(labs.external && labs.body.is_empty());
+        assert_eq!(labs.params.len(), 1);
+
+        let mut parser = Parser::new("extern def measure(line: [i64:2]): i64\n".to_string());
+        assert_eq!(parser.parse().err().unwrap().to_string(), "[i64:2] can't cross an extern call, only numbers and pointers can");
+    }
+
+    #[test]
+    fn functions_can_be_used_as_values() {
+        let source = "def square(x: i64): i64 {\n    return x * x\n}\ndef apply(f: fn(i64): i64, x: i64): i64 {\n    return f(x)\n}\nhandler: fn(i64): i64 = square\ndef main(): i64 {\n    handlers: [fn(i64): i64:2] = [square, handler]\n    g = handlers[1]\n    g(2)\n    return apply(g, 3) + handlers[0](4)\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        assert_eq!(root.items.len(), 4);
+        assert_eq!(root.get_variable("handler").unwrap().data_type.symbol, "fn(i64): i64");
+        let Some(Item::Function(main)) = root.items.iter().find(|item| matches!(item, Item::Function(function) if function.name == "main")) else {
+            panic!("main wasn't parsed");
+        };
+        let Stmt::Located(ref located) = main.body[2] else {
+            panic!("Statements are located");
+        };
+        assert!(matches!(*located.statement, Stmt::Expression(Expression::IndirectCall(_, ref args)) if args.len() == 1));
+    }
+
+    #[test]
+    fn indirect_calls_check_their_arguments() {
+        let prelude = "def square(x: i64): i64 {\n    return x * x\n}\ndef main(): i64 {\n    f = square\n";
+        for (call, message) in [("f(1, 2)", "Expected 1 arguments but found 2"), ("f(1.5)", "Expected i64 but found f64"), ("f()", "Expected 1 arguments but found 0")] {
+            let source = format!("{}    return {}\n}}\n", prelude, call);
+            let error = Parser::new(source).parse().err().unwrap();
+            assert_eq!(error.to_string(), message, "{}", call);
+        }
+    }
+
+    #[test]
+    fn generic_functions_are_instantiated_per_type() {
+        let source = "def main(): i64 {\n    x = max(1, 2)\n    y = max(1.5, 0.5)\n    z = max(3, x)\n    return x\n}\ndef max[T](a: T, b: T): T {\n    if a > b {\n        return a\n    }\n    return b\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        assert_eq!(root.items.len(), 3);
+        assert_eq!(root.return_type_of("max[f64]").unwrap().symbol, "f64");
+        assert!(root.contains_function("max[i64]"));
+    }
+
+    #[test]
+    fn generic_instances_keep_their_lines() {
+        let source = "def main(): i64 {\n    return id(4)\n}\ndef id[T](x: T): T {\n    return x\n} count = 1\n";
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        // The global sharing a line with the closing } isn't part of the instance
+        assert_eq!(root.items.len(), 3);
+        let Some(Item::Function(instance)) = root.items.iter().find(|item| matches!(item, Item::Function(function) if function.name == "id[i64]")) else {
+            panic!("id[i64] wasn't instantiated");
+        };
+        assert_eq!(instance.line, 4);
+        let Stmt::Located(ref located) = instance.body[0] else {
+            panic!("Statements are located");
+        };
+        assert_eq!(located.line, 5);
+    }
+
+    #[test]
+    fn generic_arguments_must_agree() {
+        let source = "def max[T](a: T, b: T): T {\n    return a\n}\ndef main(): i64 {\n    return max(1, 2.5)\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn match_binds_enum_payloads() {
+        let source = "enum Shape { Circle(f64), Rect(f64, f64) }\ndef area(shape: Shape): f64 {\n    match shape {\n        Circle(radius) {\n            return radius * radius\n        }\n        Rect(width, height) {\n            return width * height\n        }\n    }\n}\ndef main(): i64 {\n    shape = Shape.Rect(2.0, 3.0)\n    x = area(shape)\n    return 0\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        assert!(parser.parse().is_ok());
+        assert_eq!(parser.data_types["Shape"].variant("Rect").unwrap().0, 1);
+    }
+
+    #[test]
+    fn match_must_be_exhaustive() {
+        let source = "enum Shape {\n    Circle(f64)\n    Rect(f64, f64)\n}\ndef main(): i64 {\n    shape = Shape.Circle(2.0)\n    match shape {\n        Circle(radius) {\n            return 1\n        }\n    }\n    return 0\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        let error = parser.parse().err().unwrap();
+        assert_eq!(error.to_string(), "Match is missing arms for Rect");
+    }
+
+    #[test]
+    fn match_arms_must_be_distinct() {
+        let source = "enum Shape {\n    Circle(f64)\n    Rect(f64, f64)\n}\ndef main(): i64 {\n    shape = Shape.Circle(2.0)\n    match shape {\n        Circle(radius) {\n            return 1\n        }\n        Circle(other) {\n            return 2\n        }\n        else {\n        }\n    }\n    return 0\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        let error = parser.parse().err().unwrap();
+        assert_eq!(error.to_string(), "Match has more than one arm for Circle");
+    }
+
+    #[test]
+    fn globals_and_constants() {
+        let source = "const SIZE: i64 = 4 * 8\ncounter = SIZE + 1\nbuffer: [i64:4]\ndef main(): i64 {\n    counter = counter + LIMIT\n    return counter\n}\nconst LIMIT: i64 = -SIZE\n";
+        let mut parser = Parser::new(source.to_string());
+        let root = parser.parse().unwrap();
+        assert_eq!(root.items.len(), 3);
+        assert_eq!(root.get_variable("counter").unwrap().data_type.symbol, "i64");
+    }
+
+    #[test]
+    fn global_initializers_must_be_constant() {
+        let source = "def seed(): i64 {\n    return 4\n}\ncounter = seed()\n";
+        let mut parser = Parser::new(source.to_string());
+        let error = parser.parse().err().unwrap();
+        assert_eq!(error.to_string(), "counter must be a compile-time constant");
+    }
+
+    #[test]
+    fn constant_expressions_size_arrays_and_static_asserts() {
+        let source = "const SIZE: i64 = 4\nstatic_assert(SIZE * 2 == 8)\ndef main(): i64 {\n    values: [i64:SIZE * 2] = [1, 2, 3, 4, 5, 6, 7, 8]\n    return values[0]\n}\n";
+        let mut parser = Parser::new(source.to_string());
+        assert!(parser.parse().is_ok());
+
+        let source = "const SIZE: i64 = 4\nstatic_assert(SIZE > 8, \"SIZE is too small\")\n";
+        let mut parser = Parser::new(source.to_string());
+        assert_eq!(parser.parse().err().unwrap().to_string(), "Static assertion failed: SIZE is too small");
+    }
+}
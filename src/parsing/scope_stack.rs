@@ -1,67 +1,140 @@
 use std::{collections::VecDeque};
 
-use crate::ast::{Scope, Statement, DataType};
+use crate::ast::{Scope, DataType, RootScope, Function, IfCondition, ForLoop, MatchStatement, MatchArm, Stmt, Item};
+
+use super::{ParsingResult, ParsingError};
+
+// A node that is still being parsed, closed by the matching }
+pub enum Block {
+    Root(RootScope),
+    Function(Function),
+    If(IfCondition),
+    For(ForLoop),
+    Match(MatchStatement),
+    Arm(MatchArm),
+}
+
+impl Block {
+    pub fn scope(&self) -> &dyn Scope {
+        match self {
+            Block::Root(root) => root,
+            Block::Function(function) => function,
+            Block::If(condition) => condition,
+            Block::For(for_loop) => for_loop,
+            Block::Match(statement) => statement,
+            Block::Arm(arm) => arm,
+        }
+    }
 
+    pub fn scope_mut(&mut self) -> &mut dyn Scope {
+        match self {
+            Block::Root(root) => root,
+            Block::Function(function) => function,
+            Block::If(condition) => condition,
+            Block::For(for_loop) => for_loop,
+            Block::Match(statement) => statement,
+            Block::Arm(arm) => arm,
+        }
+    }
+
+    // The root only holds items and a match only holds arms
+    pub fn body_mut(&mut self) -> Option<&mut Vec<Stmt>> {
+        match self {
+            Block::Function(function) => Some(&mut function.body),
+            Block::If(condition) => Some(&mut condition.body),
+            Block::For(for_loop) => Some(&mut for_loop.body),
+            Block::Arm(arm) => Some(&mut arm.body),
+            Block::Root(_) | Block::Match(_) => None,
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct ScopeStack {
-    scope_stack: VecDeque<Box<dyn Scope>>,
+    scope_stack: VecDeque<Block>,
+    pub namespace: Option<String>,
 }
 
 impl ScopeStack {
-    pub fn push_front(&mut self, scope: Box<dyn Scope>) {
-        self.scope_stack.push_front(scope);
+    // Functions defined inside a module are registered under "module.name"
+    pub fn qualified_name(&self, name: &str) -> String {
+        if let Some(ref namespace) = self.namespace {
+            let qualified = format!("{}.{}", namespace, name);
+            if self.contains_function(&qualified) {
+                return qualified;
+            }
+        }
+        name.to_string()
+    }
+
+    // Globals defined inside a module are also registered under "module.name", which is what reads and writes use
+    pub fn variable_name(&self, name: &str) -> String {
+        let Some(ref namespace) = self.namespace else {
+            return name.to_string();
+        };
+        let qualified = format!("{}.{}", namespace, name);
+        match self.scope_stack.iter().find(|block| block.scope().get_variable(name).is_some()) {
+            Some(Block::Root(root)) if root.get_variable(&qualified).is_some() => qualified,
+            _ => name.to_string(),
+        }
+    }
+
+    pub fn push_front(&mut self, block: Block) {
+        self.scope_stack.push_front(block);
     }
 
-    pub fn pop_front(&mut self) -> Option<Box<dyn Scope>> {
+    pub fn pop_front(&mut self) -> Option<Block> {
         self.scope_stack.pop_front()
     }
 
-    pub fn peek_front(&mut self) -> Option<&Box<dyn Scope>> {
+    // Functions known everywhere are kept in the root scope
+    pub fn add_global_function(&mut self, name: &str, return_type: Option<DataType>) {
+        self.scope_stack.back_mut().unwrap().scope_mut().add_function(name, return_type);
+    }
+
+    pub fn peek_front(&self) -> Option<&Block> {
         self.scope_stack.front()
     }
 
-    pub fn peek_front_mut(&mut self) -> Option<&mut Box<dyn Scope>> {
+    pub fn peek_front_mut(&mut self) -> Option<&mut Block> {
         self.scope_stack.front_mut()
     }
-}
 
-impl Statement for ScopeStack {
-    fn visit<'a>(&self, data: &'a crate::ast::Compiler) -> Option<Box<dyn inkwell::values::AnyValue + 'a>> {
-        None
+    pub fn root_mut(&mut self) -> &mut RootScope {
+        match self.scope_stack.back_mut() {
+            Some(Block::Root(root)) => root,
+            _ => panic!("the root scope is always at the bottom of the stack"),
+        }
+    }
+
+    pub fn push_statement(&mut self, stmt: Stmt) -> ParsingResult<()> {
+        let body = self.scope_stack.front_mut().and_then(|block| block.body_mut());
+        body.ok_or(ParsingError::StatementOutsideFunction)?.push(stmt);
+        Ok(())
+    }
+
+    pub fn push_item(&mut self, item: Item) {
+        self.root_mut().items.push(item);
     }
 }
 
 impl Scope for ScopeStack {
     fn get_variable(&self, name: &str) -> Option<&crate::ast::Variable> {
-        for scope in &self.scope_stack {
-            if scope.get_variable(name).is_some() {
-                return scope.get_variable(name);
+        for block in &self.scope_stack {
+            if block.scope().get_variable(name).is_some() {
+                return block.scope().get_variable(name);
             }
         }
         None
     }
 
     fn set_variable(&mut self, variable: crate::ast::Variable) {
-        self.scope_stack[0].set_variable(variable);
-    }
-
-    fn commands(&self) -> &Vec<Box<dyn crate::ast::Statement>> {
-        self.scope_stack.front().unwrap().commands()
-    }
-
-    fn commands_mut(&mut self) -> &mut Vec<Box<dyn crate::ast::Statement>> {
-        let front_scope = self.scope_stack.front_mut().unwrap();
-        front_scope.commands_mut()
-    }
-
-    fn scope_type(&self) -> &'static str {
-        "scope_stack"
+        self.scope_stack[0].scope_mut().set_variable(variable);
     }
 
     fn contains_function(&self, name: &str) -> bool {
-        for scope in &self.scope_stack {
-            if scope.contains_function(name) {
+        for block in &self.scope_stack {
+            if block.scope().contains_function(name) {
                 return true;
             }
         }
@@ -69,13 +142,13 @@ impl Scope for ScopeStack {
     }
 
     fn add_function(&mut self, name: &str, return_type: Option<DataType>) {
-        self.scope_stack.front_mut().unwrap().add_function(name, return_type);
+        self.scope_stack.front_mut().unwrap().scope_mut().add_function(name, return_type);
     }
 
     fn return_type_of(&self, name: &str) -> Option<DataType> {
-        for scope in &self.scope_stack {
-            if scope.contains_function(name) {
-                return scope.return_type_of(name);
+        for block in &self.scope_stack {
+            if block.scope().contains_function(name) {
+                return block.scope().return_type_of(name);
             }
         }
         panic!()
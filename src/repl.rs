@@ -0,0 +1,315 @@
+use std::{error::Error, io::{Lines, StdinLock, Write}, panic::{catch_unwind, AssertUnwindSafe}};
+
+use inkwell::{context::Context, execution_engine::{ExecutionEngine, JitFunction}, types::BasicType, OptimizationLevel};
+
+use crate::{ast::{CompilerOptions, DataType, DataTypeEnum, GlobalVariable, Item, RootScope, Variable}, codegen::Compiler,
+    lexing::{Lexer, Token}, parsing::{Parser, ParsingResult}, runner::map_runtime_functions, runtime::{self, RuntimeVector}};
+
+type StatementFunc = unsafe extern "C" fn();
+type ValueFunc = unsafe extern "C" fn() -> *const u8;
+
+// Every input is compiled into its own module, earlier definitions are declared again so they can be called
+struct Repl<'ctx> {
+    context: &'ctx Context,
+    engine: ExecutionEngine<'ctx>,
+    parser: Parser,
+    options: CompilerOptions,
+    definitions: Vec<Item>,
+    inputs: usize,
+}
+
+pub fn run(options: CompilerOptions) {
+    let context = Context::create();
+    let module = context.create_module("repl");
+    let engine = module.create_jit_execution_engine(OptimizationLevel::None).unwrap();
+    let mut repl = Repl {
+        context: &context,
+        engine,
+        parser: Parser::new(String::new()),
+        options,
+        definitions: Vec::new(),
+        inputs: 0,
+    };
+
+    let mut lines = std::io::stdin().lines();
+    while let Some(input) = read_input(&mut lines) {
+        match input.trim() {
+            ":quit" => break,
+            "" => continue,
+            _ => {},
+        }
+        match repl.evaluate(&input) {
+            Ok(Some(output)) => println!("{}", output),
+            Ok(None) => {},
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+    if repl.options.leak_check {
+        runtime::report_leaks();
+    }
+}
+
+// Reads lines until every { has been closed, None once stdin is closed
+fn read_input(lines: &mut Lines<StdinLock<'static>>) -> Option<String> {
+    let mut input = String::new();
+    let mut depth = 0;
+    loop {
+        print!("{}", if input.is_empty() { ">> " } else { ".. " });
+        std::io::stdout().flush().ok();
+        let line = lines.next()?.ok()?;
+        for token in tokens(&line) {
+            match token {
+                Token::OpenCurly => depth += 1,
+                Token::ClosedCurly => depth -= 1,
+                _ => {},
+            }
+        }
+        input.push_str(&line);
+        input.push('\n');
+        if depth <= 0 {
+            return Some(input);
+        }
+    }
+}
+
+fn tokens(source: &str) -> Vec<Token> {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut tokens = Vec::new();
+    let mut token = lexer.next();
+    while token != Token::EOF {
+        tokens.push(token);
+        token = lexer.next();
+    }
+    tokens
+}
+
+// Assignments and control flow run for their effect, anything else is an expression to print
+fn is_statement(tokens: &[Token]) -> bool {
+    matches!(tokens.first(), Some(Token::If | Token::For | Token::Match | Token::Return)) ||
+        tokens.iter().any(|token| *token == Token::Equal || Parser::compound_operation(token).is_some())
+}
+
+impl<'ctx> Repl<'ctx> {
+    // Input that fails after parsing mustn't leave its definitions behind
+    fn evaluate(&mut self, input: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let checkpoint = self.parser.checkpoint();
+        let result = self.evaluate_input(input);
+        if result.is_err() {
+            self.parser.rollback(checkpoint);
+        }
+        result
+    }
+
+    fn evaluate_input(&mut self, input: &str) -> Result<Option<String>, Box<dyn Error>> {
+        self.inputs += 1;
+        let name = format!("__repl_{}", self.inputs);
+        let tokens = tokens(input);
+        // Ex: 1 + 2 becomes def __repl_1(): &i64 { _1 = 1 + 2; return &_1 }
+        let (source, result) = match tokens.first() {
+            Some(Token::Import) => return Err("Imports aren't supported in the repl".into()),
+            Some(Token::Def | Token::Pub | Token::Extern | Token::Enum | Token::Const | Token::StaticAssert) => (input.to_string(), None),
+            _ if is_statement(&tokens) => (format!("def {}() {{\n{}}}\n", name, input), None),
+            _ => match self.guarded(|parser| parser.infer_type(input.to_string()))? {
+                Some(data_type) => {
+                    let result = format!("_{}", self.inputs);
+                    let source = format!("def {}(): &{} {{\n{} = {}\nreturn &{}\n}}\n", name, data_type.symbol, result, input.trim(), result);
+                    (source, Some((result, data_type)))
+                },
+                None => (format!("def {}() {{\n{}}}\n", name, input), None),
+            },
+        };
+
+        let items = self.parse(source, &name)?;
+        let root = RootScope { items, ..Default::default() };
+        let module = self.context.create_module(&name);
+        let compiler = Compiler::new(self.context, module, self.parser.data_types.clone(), self.options.clone());
+        for definition in &self.definitions {
+            match definition {
+                Item::Function(function) => {
+                    compiler.declare_function(function);
+                },
+                Item::Global(global) => {
+                    compiler.declare_global(global);
+                },
+            }
+        }
+        // Lowering still panics on some programs the parser accepts
+        let compiled = catch_unwind(AssertUnwindSafe(|| {
+            compiler.compile_root(&root);
+            compiler.module.verify().map_err(|error| error.to_string())
+        }));
+        match compiled {
+            Ok(verified) => verified?,
+            Err(_) => return Err("Couldn't compile input".into()),
+        }
+        map_runtime_functions(&self.engine, &compiler.module);
+        self.engine.add_module(&compiler.module).map_err(|_| "Couldn't add the module to the execution engine")?;
+        let defines_wrapper = compiler.module.get_function(&name).is_some();
+        self.definitions.extend(root.items);
+        if !defines_wrapper {
+            return Ok(None);
+        }
+
+        unsafe {
+            let Some((result, data_type)) = result else {
+                let function: JitFunction<StatementFunc> = self.engine.get_function(&name).map_err(|error| format!("{:?}", error))?;
+                function.call();
+                return Ok(None);
+            };
+            let function: JitFunction<ValueFunc> = self.engine.get_function(&name).map_err(|error| format!("{:?}", error))?;
+            let address = function.call();
+            Ok(Some(format!("{}: {} = {}", result, data_type.symbol, self.format_value(address, &data_type))))
+        }
+    }
+
+    // Many parsing errors are still panics, they shouldn't end the session
+    fn guarded<T>(&mut self, parse: impl FnOnce(&mut Parser) -> ParsingResult<T>) -> ParsingResult<T> {
+        match catch_unwind(AssertUnwindSafe(|| parse(&mut self.parser))) {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(error)) => {
+                self.parser.discard_input();
+                Err(error)
+            },
+            Err(_) => {
+                self.parser.discard_input();
+                Err("Couldn't parse input".into())
+            },
+        }
+    }
+
+    // Parses against everything entered so far, variables assigned at the top of the wrapper outlive it as globals
+    fn parse(&mut self, source: String, wrapper: &str) -> ParsingResult<Vec<Item>> {
+        let mut root = self.guarded(|parser| parser.parse_more(source))?;
+
+        let mut globals = Vec::new();
+        for item in &root.items {
+            if let Item::Function(function) = item {
+                if function.name == wrapper {
+                    for variable in function.variables.values() {
+                        globals.push(Variable { name: variable.name.clone(), data_type: variable.data_type.clone() });
+                    }
+                }
+            }
+        }
+        let mut items = Vec::new();
+        for variable in globals {
+            items.push(Item::Global(GlobalVariable::new(variable.name.clone(), variable.data_type.clone(), None)));
+            self.parser.add_global(variable);
+        }
+        items.append(&mut root.items);
+        Ok(items)
+    }
+
+    // Renders the value stored at address the way it would be written in source
+    unsafe fn format_value(&self, address: *const u8, data_type: &DataType) -> String {
+        let target_data = self.engine.get_target_data();
+        let pointer_size = target_data.get_pointer_byte_size(None) as u64;
+        let llvm_type = |data_type: &DataType| data_type.produce_llvm_type(self.context, pointer_size).as_basic_type_enum();
+        let size_of = |data_type: &DataType| target_data.get_abi_size(&llvm_type(data_type)) as usize;
+        match data_type.value {
+            DataTypeEnum::Primitive => match data_type.symbol.as_str() {
+                "i64" => (*(address as *const i64)).to_string(),
+                "f64" => format!("{:?}", *(address as *const f64)),
+                "bool" => (*address != 0).to_string(),
+                "char" => format!("{:?}", *address as char),
+                _ => format!("<{}>", data_type.symbol),
+            },
+            DataTypeEnum::Array(ref interior, len) if interior.symbol == "char" => {
+                format!("{:?}", String::from_utf8_lossy(std::slice::from_raw_parts(address, len as usize)))
+            },
+            DataTypeEnum::Array(ref interior, len) => {
+                let elements: Vec<String> = (0..len as usize).map(|i| self.format_value(address.add(i * size_of(interior)), interior)).collect();
+                format!("[{}]", elements.join(", "))
+            },
+            DataTypeEnum::Vector(ref interior) => {
+                let handle = *(address as *const *mut RuntimeVector);
+                let elements = runtime::ss_vec_data(handle);
+                let elements: Vec<String> = (0..runtime::ss_vec_len(handle) as usize)
+                    .map(|i| self.format_value(elements.add(i * size_of(interior)), interior))
+                    .collect();
+                format!("vec[{}]", elements.join(", "))
+            },
+            DataTypeEnum::Struct(ref fields, ref names) => {
+                let struct_type = llvm_type(data_type).into_struct_type();
+                let mut names: Vec<(&String, &u64)> = names.iter().collect();
+                names.sort_by_key(|(_, index)| **index);
+                let fields: Vec<String> = names.iter().map(|(name, index)| {
+                    let offset = target_data.offset_of_element(&struct_type, **index as u32).unwrap() as usize;
+                    format!("{}: {}", name, self.format_value(address.add(offset), &fields[**index as usize]))
+                }).collect();
+                format!("{} {{ {} }}", data_type.symbol, fields.join(", "))
+            },
+            DataTypeEnum::Enum(ref variants) => {
+                let tag = *(address as *const i64) as u64;
+                let (variant, fields) = &variants[tag as usize];
+                if fields.is_empty() {
+                    return format!("{}.{}", data_type.symbol, variant);
+                }
+                let enum_type = llvm_type(data_type).into_struct_type();
+                let payload = address.add(target_data.offset_of_element(&enum_type, 1).unwrap() as usize);
+                let variant_type = data_type.produce_variant_llvm_type(self.context, tag, pointer_size);
+                let fields: Vec<String> = fields.iter().enumerate().map(|(i, field)| {
+                    let offset = target_data.offset_of_element(&variant_type, i as u32).unwrap() as usize;
+                    self.format_value(payload.add(offset), field)
+                }).collect();
+                format!("{}.{}({})", data_type.symbol, variant, fields.join(", "))
+            },
+            DataTypeEnum::Pointer(_) | DataTypeEnum::Heap(_) | DataTypeEnum::Function(..) => format!("{:?}", *(address as *const *const u8)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classifies_input() {
+        assert!(is_statement(&tokens("x = square(3)")));
+        assert!(is_statement(&tokens("total += 1")));
+        assert!(is_statement(&tokens("for i in 0..3 {")));
+        assert!(!is_statement(&tokens("x == 3")));
+        assert!(!is_statement(&tokens("square(x) + 1")));
+    }
+
+    #[test]
+    fn keeps_definitions_between_inputs() {
+        let context = Context::create();
+        let module = context.create_module("repl");
+        let mut repl = Repl {
+            context: &context,
+            engine: module.create_jit_execution_engine(OptimizationLevel::None).unwrap(),
+            parser: Parser::new(String::new()),
+            options: CompilerOptions::default(),
+            definitions: Vec::new(),
+            inputs: 0,
+        };
+
+        assert_eq!(repl.evaluate("def square(x: i64): i64 {\n    return x * x\n}\n").unwrap(), None);
+        assert_eq!(repl.evaluate("x = square(3)\n").unwrap(), None);
+        assert_eq!(repl.evaluate("x + 1\n").unwrap(), Some("_3: i64 = 10".to_string()));
+        assert_eq!(repl.evaluate("[4, 5]\n").unwrap(), Some("_4: [i64:2] = [4, 5]".to_string()));
+        assert!(repl.evaluate("missing(1)\n").is_err());
+        assert_eq!(repl.evaluate("_4[1] * 2\n").unwrap(), Some("_6: i64 = 10".to_string()));
+    }
+
+    #[test]
+    fn forgets_input_that_fails_to_compile() {
+        let context = Context::create();
+        let module = context.create_module("repl");
+        let mut repl = Repl {
+            context: &context,
+            engine: module.create_jit_execution_engine(OptimizationLevel::None).unwrap(),
+            parser: Parser::new(String::new()),
+            options: CompilerOptions::default(),
+            definitions: Vec::new(),
+            inputs: 0,
+        };
+
+        // len of a number passes the parser but not the mir verifier
+        assert!(repl.evaluate("x = len(5)\n").is_err());
+        assert!(repl.evaluate("x\n").is_err());
+        assert_eq!(repl.evaluate("x = 2\n").unwrap(), None);
+        assert_eq!(repl.evaluate("x + 1\n").unwrap(), Some("_4: i64 = 3".to_string()));
+    }
+}
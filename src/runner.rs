@@ -1,33 +1,179 @@
-use std::{collections::HashMap, cell::RefCell};
-use std::path::Path;
+use std::{fs, path::Path};
 
-use inkwell::{context::Context, execution_engine::JitFunction};
+#[cfg(feature = "llvm")]
+use inkwell::{context::Context, execution_engine::{JitFunction, ExecutionEngine}, module::Module, targets::FileType};
 
-use crate::{ast::Compiler, parsing::Parser};
+use crate::{ast::{CompilerOptions, RootScope}, bytecode::{self, Program, Vm}, interpreter::{InterpretResult, Interpreter, Value}, mir, parsing::ModuleLoader};
+#[cfg(feature = "llvm")]
+use crate::{codegen::{Compiler, engine_level, optimize, set_target, target_machine}, runtime};
 
+#[cfg(feature = "llvm")]
 type MainFunc = unsafe extern "C" fn() -> u8;
 
-pub fn run(file: String) {
+#[cfg(feature = "llvm")]
+pub fn run(file_path: &str, options: CompilerOptions) {
     let context = Context::create();
     let module = context.create_module("main");
-    let engine = module.create_jit_execution_engine(inkwell::OptimizationLevel::None).unwrap();
-
-    let mut parser = Parser::new(file);
-    let res = parser.parse().unwrap();
-    let compiler = Compiler {
-        context: &context,
-        module,
-        builder: context.create_builder(),
-        variable_table: RefCell::new(HashMap::new()),
-        function_table: RefCell::new(HashMap::new()),
-        current_function_params: RefCell::new(HashMap::new()),
-        data_types: parser.data_types.clone(),
-    };
-
-    res.visit(&compiler);
+    let engine = module.create_jit_execution_engine(engine_level(options.optimization)).unwrap();
+
+    let mut loader = ModuleLoader::default();
+    let res = load_or_exit(&mut loader, file_path);
+    let compiler = Compiler::new(&context, module, loader.data_types.clone(), options.clone());
+
+    let program = compiler.compile_root(&res);
+    report_warnings(&options.source_name, &program);
+    verify_or_exit(&compiler.module, &options.source_name);
+    optimize(&compiler.module, options.optimization);
     compiler.module.print_to_file(Path::new("./test/output.txt")).unwrap();
+    map_runtime_functions(&engine, &compiler.module);
     unsafe {
         let main: JitFunction<MainFunc> = engine.get_function("main").unwrap();
         println!("Result: {:?}", main.call());
     }
-}
\ No newline at end of file
+    if options.leak_check {
+        runtime::report_leaks();
+    }
+}
+
+// Compiles ahead of time for --target, main.ss becomes main.o or with --asm main.s
+#[cfg(feature = "llvm")]
+pub fn build(file_path: &str, options: CompilerOptions, assembly: bool) {
+    let machine = target_machine(&options).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    let context = Context::create();
+    let module = context.create_module("main");
+    set_target(&module, &machine);
+
+    let mut loader = ModuleLoader::default();
+    let root = load_or_exit(&mut loader, file_path);
+    let compiler = Compiler::new(&context, module, loader.data_types.clone(), options.clone());
+    let program = compiler.compile_root(&root);
+    report_warnings(&options.source_name, &program);
+    verify_or_exit(&compiler.module, &options.source_name);
+    optimize(&compiler.module, options.optimization);
+
+    let (extension, file_type) = if assembly { ("s", FileType::Assembly) } else { ("o", FileType::Object) };
+    let output = Path::new(file_path).with_extension(extension);
+    if let Err(error) = machine.write_to_file(&compiler.module, file_type, &output) {
+        eprintln!("{}: {}", output.display(), error);
+        std::process::exit(1);
+    }
+    if runtime::symbols().iter().any(|(name, _)| compiler.module.get_function(name).is_some()) {
+        let triple = machine.get_triple().as_str().to_string_lossy().to_string();
+        eprintln!("note: link {} with libss_runtime.a from cargo build -p ss_runtime --release --target {}", output.display(), triple);
+    }
+}
+
+// Invalid IR crashes LLVM instead of reporting anything
+#[cfg(feature = "llvm")]
+fn verify_or_exit(module: &Module, source_name: &str) {
+    if let Err(error) = module.verify() {
+        eprintln!("{}: invalid LLVM IR\n{}", source_name, error);
+        std::process::exit(1);
+    }
+}
+
+// Same output as run, runtime check failures abort like they do in compiled programs
+pub fn interpret(file_path: &str, options: CompilerOptions) {
+    let mut loader = ModuleLoader::default();
+    let root = load_or_exit(&mut loader, file_path);
+    report(Interpreter::new(&root, loader.data_types.clone(), options).and_then(|mut interpreter| interpreter.call("main", Vec::new())));
+}
+
+// Runs source files, or .ssb files written by emit --bytecode, on the bytecode vm
+pub fn run_bytecode(file_path: &str, options: CompilerOptions) {
+    let program = load_program(file_path, &options);
+    report(Vm::new(&program).and_then(|mut vm| vm.call("main", Vec::new())));
+}
+
+// Writes main.ssb next to main.ss
+pub fn emit_bytecode(file_path: &str, options: CompilerOptions) {
+    let program = load_program(file_path, &options);
+    let output = Path::new(file_path).with_extension("ssb");
+    if let Err(error) = fs::write(&output, bytecode::save(&program)) {
+        eprintln!("{}: {}", output.display(), error);
+        std::process::exit(1);
+    }
+}
+
+pub fn emit_disassembly(file_path: &str, options: CompilerOptions) {
+    print!("{}", bytecode::disassemble(&load_program(file_path, &options)));
+}
+
+fn report(result: InterpretResult<Option<Value>>) {
+    match result {
+        Ok(value) => {
+            let code = value.and_then(|value| value.as_integer()).map_or(0, |(bits, _)| bits as u8);
+            println!("Result: {:?}", code);
+        },
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::abort();
+        },
+    }
+}
+
+// Prints the mid-level IR the llvm backend is generated from, warnings go to stderr
+pub fn emit_mir(file_path: &str, options: CompilerOptions) {
+    let mut loader = ModuleLoader::default();
+    let root = load_or_exit(&mut loader, file_path);
+    let program = mir::lower(&root, &loader.data_types, &options);
+    report_warnings(&options.source_name, &program);
+    print!("{}", program);
+}
+
+fn report_warnings(source_name: &str, program: &mir::Program) {
+    for warning in mir::lint(program) {
+        eprintln!("{}:{}: warning: {}", source_name, warning.line, warning.message);
+    }
+}
+
+// Prints the parsed program and everything it imports, as an indented tree or as JSON
+pub fn emit_ast(file_path: &str, json: bool) {
+    let mut loader = ModuleLoader::default();
+    let root = load_or_exit(&mut loader, file_path);
+    let tree = root.inspect(&loader.data_types);
+    if json {
+        println!("{}", tree.to_json());
+    } else {
+        print!("{}", tree.to_tree());
+    }
+}
+
+fn load_or_exit(loader: &mut ModuleLoader, file_path: &str) -> RootScope {
+    match loader.load(Path::new(file_path)) {
+        Ok(root) => root,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn load_program(file_path: &str, options: &CompilerOptions) -> Program {
+    if Path::new(file_path).extension().is_some_and(|extension| extension == "ssb") {
+        let program = fs::read(file_path).map_err(|error| error.to_string())
+            .and_then(|bytes| bytecode::load(&bytes).map_err(|error| error.to_string()));
+        return program.unwrap_or_else(|error| {
+            eprintln!("{}: {}", file_path, error);
+            std::process::exit(1);
+        });
+    }
+    let mut loader = ModuleLoader::default();
+    let root = load_or_exit(&mut loader, file_path);
+    bytecode::compile(&root, &loader.data_types, options).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    })
+}
+
+#[cfg(feature = "llvm")]
+pub fn map_runtime_functions(engine: &ExecutionEngine, module: &Module) {
+    for (name, address) in runtime::symbols() {
+        if let Some(function) = module.get_function(name) {
+            engine.add_global_mapping(&function, address);
+        }
+    }
+}